@@ -178,6 +178,10 @@ impl<T: Storable + CondSync> Storable for Arc<T> {
         Ok(Arc::new(T::from_serializable(cid, serializable).await?))
     }
 
+    async fn load(cid: &Cid, store: &impl BlockStore) -> Result<Self> {
+        Ok(Arc::new(T::load(cid, store).await?))
+    }
+
     fn persisted_as(&self) -> Option<&OnceCell<Cid>> {
         self.as_ref().persisted_as()
     }
@@ -185,7 +189,7 @@ impl<T: Storable + CondSync> Storable for Arc<T> {
 
 impl_storable_from_serde! { [u8; 0], [u8; 1], [u8; 2], [u8; 4], [u8; 8], [u8; 16], [u8; 32] }
 impl_storable_from_serde! { usize, u128, u64, u32, u16, u8, isize, i128, i64, i32, i16, i8 }
-impl_storable_from_serde! { String }
+impl_storable_from_serde! { String, Cid }
 impl_storable_from_serde! {
     (A,): <A>,
     (A, B): <A, B>,
@@ -59,7 +59,7 @@ impl SnapshotBlockStore {
 
     pub fn handle_block(&self, cid: &Cid, bytes: &Bytes) -> Result<BlockSnapshot> {
         let ipld = match cid.codec() {
-            CODEC_DAG_CBOR => serde_ipld_dagcbor::from_slice(&bytes)?,
+            CODEC_DAG_CBOR => serde_ipld_dagcbor::from_slice(bytes)?,
             CODEC_RAW => match self.block_handlers.lock().get(cid) {
                 Some(func) => func.convert(bytes)?,
                 None => Ipld::Bytes(bytes.to_vec()),
@@ -358,7 +358,7 @@ mod tests {
             0b1000, 0b1100, 0b1010, 0b1010, 0b1011, 0b1111, 0b1111, 0b1101,
         ];
 
-        for (got, expected) in hashnibbles.zip(expected_nibbles.into_iter()) {
+        for (got, expected) in hashnibbles.zip(expected_nibbles) {
             assert_eq!(expected, got);
         }
 
@@ -55,14 +55,14 @@ pub(crate) fn generate_ops_and_changes()
     })
 }
 
-pub(crate) async fn apply_changes<K: CondSync, V: CondSync>(
+pub(crate) async fn apply_changes<K, V>(
     node: &mut Arc<Node<K, V>>,
     changes: &Vec<Change<K, V>>,
     store: &impl BlockStore,
 ) -> Result<()>
 where
-    K: Storable + Debug + Clone + AsRef<[u8]>,
-    V: Storable + Debug + Clone,
+    K: Storable + Debug + Clone + AsRef<[u8]> + CondSync,
+    V: Storable + Debug + Clone + CondSync,
     K::Serializable: Serialize + DeserializeOwned,
     V::Serializable: Serialize + DeserializeOwned,
 {
@@ -83,14 +83,14 @@ where
     Ok(())
 }
 
-pub(crate) async fn prepare_node<K: CondSync, V: CondSync>(
+pub(crate) async fn prepare_node<K, V>(
     node: &mut Arc<Node<K, V>>,
     changes: &Vec<Change<K, V>>,
     store: &impl BlockStore,
 ) -> Result<()>
 where
-    K: Storable + Debug + Clone + AsRef<[u8]>,
-    V: Storable + Debug + Clone,
+    K: Storable + Debug + Clone + AsRef<[u8]> + CondSync,
+    V: Storable + Debug + Clone + CondSync,
     K::Serializable: Serialize + DeserializeOwned,
     V::Serializable: Serialize + DeserializeOwned,
 {
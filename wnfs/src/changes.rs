@@ -0,0 +1,535 @@
+//! Notifications about changes made to a root tree or a standalone directory.
+//!
+//! Every successful mutation of a [`RootTree`][crate::root_tree::RootTree] emits a
+//! [`ChangeEvent`] to all of its subscribers, so UI layers and sync daemons can react
+//! to changes without polling.
+//!
+//! Directories that aren't part of a root tree can be wrapped in a [`PublicDirectoryHandle`]
+//! or [`PrivateDirectoryHandle`] to get the same notifications.
+
+use crate::{
+    SearchResult,
+    error::FsError,
+    private::{AccessKey, PrivateDirectory, PrivateNode, forest::hamt::HamtForest},
+    public::{PublicDirectory, PublicNode},
+    root_tree::RootTreeReconciliation,
+};
+use anyhow::{Result, bail};
+use chrono::{DateTime, Utc};
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use ipld_core::ipld::Ipld;
+
+use rand_core::CryptoRngCore;
+use std::sync::{Arc, Mutex};
+use wnfs_common::{BlockStore, Cid, Storable, utils::CondSend};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The partition of a root tree that a path is in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionKind {
+    /// The public partition.
+    Public,
+    /// The exchange partition.
+    Exchange,
+    /// The private partition mounted at given path.
+    Private(Vec<String>),
+}
+
+/// A path in a root tree, along with the partition it's in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedPath {
+    /// The partition the path is in.
+    pub partition: PartitionKind,
+    /// The full path, including the partition's prefix.
+    pub path: Vec<String>,
+}
+
+/// A change made to a root tree.
+#[derive(Debug, Clone)]
+pub enum ChangeEvent {
+    /// A file was written, opened for writing or restored to an older revision.
+    Write(ChangedPath),
+    /// A directory was created, including new private roots.
+    Mkdir(ChangedPath),
+    /// A file or directory was removed.
+    Rm(ChangedPath),
+    /// A file or directory was moved, possibly into another partition.
+    Mv { from: ChangedPath, to: ChangedPath },
+    /// A file or directory was copied or published, possibly into another partition.
+    Cp { from: ChangedPath, to: ChangedPath },
+    /// The metadata of a file or directory was changed.
+    MetadataChanged(ChangedPath),
+    /// Access to a file or directory was revoked from some recipients, giving it new keys.
+    Revoked(ChangedPath),
+    /// Another version of the root tree was reconciled into this one.
+    Reconciled(RootTreeReconciliation),
+    /// The root tree was stored under a new root CID.
+    Stored(Cid),
+}
+
+/// The subscribers to the changes of a root tree.
+///
+/// Subscriptions belong to one root tree value: Clones start out without any subscribers,
+/// so changes to a copy aren't mistaken for changes to the original.
+#[derive(Debug, Default)]
+pub struct ChangeNotifier {
+    subscribers: Mutex<Vec<UnboundedSender<ChangeEvent>>>,
+}
+
+/// A public directory that isn't part of a root tree, along with its subscribers.
+///
+/// Changes are reported with paths relative to the directory, in the public partition.
+/// Mutations made directly on `root` bypass the notifications.
+#[derive(Debug, Clone)]
+pub struct PublicDirectoryHandle<B: BlockStore> {
+    pub root: Arc<PublicDirectory>,
+    pub store: B,
+    pub(crate) notifier: ChangeNotifier,
+}
+
+/// A private directory that isn't part of a root tree, along with its subscribers.
+///
+/// Changes are reported with paths relative to the directory, in a private partition
+/// mounted at the empty path. Mutations made directly on `root` bypass the notifications.
+#[derive(Debug, Clone)]
+pub struct PrivateDirectoryHandle<B: BlockStore> {
+    pub root: Arc<PrivateDirectory>,
+    pub forest: Arc<HamtForest>,
+    pub store: B,
+    pub(crate) notifier: ChangeNotifier,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ChangeNotifier {
+    /// Creates a notifier without subscribers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribes to all changes from now on.
+    ///
+    /// Dropping the returned stream ends the subscription.
+    pub fn subscribe(&self) -> UnboundedReceiver<ChangeEvent> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Sends given event to all subscribers, forgetting the ones that have unsubscribed.
+    pub fn notify(&self, event: ChangeEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+    }
+}
+
+impl Clone for ChangeNotifier {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<B: BlockStore> PublicDirectoryHandle<B> {
+    /// Wraps given directory without any subscribers.
+    pub fn new(root: Arc<PublicDirectory>, store: B) -> Self {
+        Self {
+            root,
+            store,
+            notifier: ChangeNotifier::new(),
+        }
+    }
+
+    /// Subscribes to all changes made through this handle from now on.
+    pub fn subscribe(&self) -> UnboundedReceiver<ChangeEvent> {
+        self.notifier.subscribe()
+    }
+
+    pub async fn write(
+        &mut self,
+        path: &[String],
+        content: Vec<u8>,
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        self.root.write(path, content, time, &self.store).await?;
+        self.notifier.notify(ChangeEvent::Write(changed(path)));
+        Ok(())
+    }
+
+    pub async fn mkdir(&mut self, path: &[String], time: DateTime<Utc>) -> Result<()> {
+        self.root.mkdir(path, time, &self.store).await?;
+        self.notifier.notify(ChangeEvent::Mkdir(changed(path)));
+        Ok(())
+    }
+
+    pub async fn rm(&mut self, path: &[String]) -> Result<PublicNode> {
+        let node = self.root.rm(path, &self.store).await?;
+        self.notifier.notify(ChangeEvent::Rm(changed(path)));
+        Ok(node)
+    }
+
+    pub async fn basic_mv(
+        &mut self,
+        from: &[String],
+        to: &[String],
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        self.root.basic_mv(from, to, time, &self.store).await?;
+        self.notifier.notify(ChangeEvent::Mv {
+            from: changed(from),
+            to: changed(to),
+        });
+        Ok(())
+    }
+
+    pub async fn cp(&mut self, from: &[String], to: &[String], time: DateTime<Utc>) -> Result<()> {
+        self.root.cp(from, to, time, &self.store).await?;
+        self.notifier.notify(ChangeEvent::Cp {
+            from: changed(from),
+            to: changed(to),
+        });
+        Ok(())
+    }
+
+    /// Sets a metadata entry on the file or directory at given path.
+    pub async fn set_metadata(
+        &mut self,
+        path: &[String],
+        key: &str,
+        value: Ipld,
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        match self.root.get_node(path, &self.store).await? {
+            Some(PublicNode::File(_)) => {
+                let file = self.root.open_file_mut(path, time, &self.store).await?;
+                file.get_metadata_mut().put(key, value);
+            }
+            _ => {
+                let SearchResult::Found(dir) =
+                    self.root.get_leaf_dir_mut(path, &self.store).await?
+                else {
+                    bail!(FsError::NotFound)
+                };
+                dir.get_metadata_mut().upsert_mtime(time);
+                dir.get_metadata_mut().put(key, value);
+            }
+        }
+
+        self.notifier
+            .notify(ChangeEvent::MetadataChanged(changed(path)));
+        Ok(())
+    }
+
+    /// Stores the directory, notifying subscribers of its new CID.
+    pub async fn store(&self) -> Result<Cid> {
+        let cid = self.root.store(&self.store).await?;
+        self.notifier.notify(ChangeEvent::Stored(cid));
+        Ok(cid)
+    }
+}
+
+impl<B: BlockStore> PrivateDirectoryHandle<B> {
+    /// Wraps given directory and the forest it's stored in without any subscribers.
+    pub fn new(root: Arc<PrivateDirectory>, forest: Arc<HamtForest>, store: B) -> Self {
+        Self {
+            root,
+            forest,
+            store,
+            notifier: ChangeNotifier::new(),
+        }
+    }
+
+    /// Subscribes to all changes made through this handle from now on.
+    pub fn subscribe(&self) -> UnboundedReceiver<ChangeEvent> {
+        self.notifier.subscribe()
+    }
+
+    pub async fn write(
+        &mut self,
+        path: &[String],
+        content: Vec<u8>,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        self.root
+            .write(
+                path,
+                true,
+                time,
+                content,
+                &mut self.forest,
+                &self.store,
+                rng,
+            )
+            .await?;
+        self.notifier
+            .notify(ChangeEvent::Write(changed_private(path)));
+        Ok(())
+    }
+
+    pub async fn mkdir(
+        &mut self,
+        path: &[String],
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        self.root
+            .mkdir(path, true, time, &self.forest, &self.store, rng)
+            .await?;
+        self.notifier
+            .notify(ChangeEvent::Mkdir(changed_private(path)));
+        Ok(())
+    }
+
+    pub async fn rm(&mut self, path: &[String]) -> Result<PrivateNode> {
+        let node = self.root.rm(path, true, &self.forest, &self.store).await?;
+        self.notifier.notify(ChangeEvent::Rm(changed_private(path)));
+        Ok(node)
+    }
+
+    pub async fn basic_mv(
+        &mut self,
+        from: &[String],
+        to: &[String],
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        self.root
+            .basic_mv(from, to, true, time, &mut self.forest, &self.store, rng)
+            .await?;
+        self.notifier.notify(ChangeEvent::Mv {
+            from: changed_private(from),
+            to: changed_private(to),
+        });
+        Ok(())
+    }
+
+    pub async fn cp(
+        &mut self,
+        from: &[String],
+        to: &[String],
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        self.root
+            .cp(from, to, true, time, &mut self.forest, &self.store, rng)
+            .await?;
+        self.notifier.notify(ChangeEvent::Cp {
+            from: changed_private(from),
+            to: changed_private(to),
+        });
+        Ok(())
+    }
+
+    /// Sets a metadata entry on the file or directory at given path.
+    pub async fn set_metadata(
+        &mut self,
+        path: &[String],
+        key: &str,
+        value: Ipld,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        match self
+            .root
+            .get_node(path, true, &self.forest, &self.store)
+            .await?
+        {
+            Some(PrivateNode::File(_)) => {
+                let file = self
+                    .root
+                    .open_file_mut(path, true, time, &mut self.forest, &self.store, rng)
+                    .await?;
+                file.get_metadata_mut().put(key, value);
+            }
+            _ => {
+                let SearchResult::Found(dir) = self
+                    .root
+                    .get_leaf_dir_mut(path, true, &self.forest, &self.store)
+                    .await?
+                else {
+                    bail!(FsError::NotFound)
+                };
+                dir.get_metadata_mut().upsert_mtime(time);
+                dir.get_metadata_mut().put(key, value);
+            }
+        }
+
+        self.notifier
+            .notify(ChangeEvent::MetadataChanged(changed_private(path)));
+        Ok(())
+    }
+
+    /// Stores the directory and the forest, notifying subscribers of the forest's new CID.
+    ///
+    /// Returns the access key to the stored directory.
+    pub async fn store(&mut self, rng: &mut (impl CryptoRngCore + CondSend)) -> Result<AccessKey> {
+        let access_key = self
+            .root
+            .as_node()
+            .store(&mut self.forest, &self.store, rng)
+            .await?;
+        let cid = self.forest.store(&self.store).await?;
+        self.notifier.notify(ChangeEvent::Stored(cid));
+        Ok(access_key)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+fn changed(path: &[String]) -> ChangedPath {
+    ChangedPath {
+        partition: PartitionKind::Public,
+        path: path.to_vec(),
+    }
+}
+
+fn changed_private(path: &[String]) -> ChangedPath {
+    ChangedPath {
+        partition: PartitionKind::Private(vec![]),
+        path: path.to_vec(),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::traits::PrivateForest;
+    use chrono::TimeZone;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    fn path(segments: &[&str]) -> Vec<String> {
+        segments.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn pending(changes: &mut UnboundedReceiver<ChangeEvent>) -> Vec<ChangeEvent> {
+        std::iter::from_fn(|| changes.try_next().ok().flatten()).collect()
+    }
+
+    #[async_std::test]
+    async fn test_public_directory_handle_notifies_subscribers() -> TestResult {
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let public = |segments: &[&str]| ChangedPath {
+            partition: PartitionKind::Public,
+            path: path(segments),
+        };
+
+        let mut handle =
+            PublicDirectoryHandle::new(PublicDirectory::new_rc(time), MemoryBlockStore::new());
+        let mut changes = handle.subscribe();
+
+        handle.write(&path(&["a.md"]), b"a".to_vec(), time).await?;
+        handle.mkdir(&path(&["docs"]), time).await?;
+        handle
+            .set_metadata(&path(&["docs"]), "color", Ipld::String("blue".into()), time)
+            .await?;
+        handle
+            .basic_mv(&path(&["a.md"]), &path(&["docs", "a.md"]), time)
+            .await?;
+        handle
+            .cp(&path(&["docs", "a.md"]), &path(&["b.md"]), time)
+            .await?;
+        handle.rm(&path(&["b.md"])).await?;
+        handle.rm(&path(&["b.md"])).await.unwrap_err();
+        let cid = handle.store().await?;
+
+        let events = pending(&mut changes);
+        assert!(matches!(&events[..], [
+            ChangeEvent::Write(written),
+            ChangeEvent::Mkdir(dir),
+            ChangeEvent::MetadataChanged(changed),
+            ChangeEvent::Mv { from: moved_from, to: moved_to },
+            ChangeEvent::Cp { from: copied_from, to: copied_to },
+            ChangeEvent::Rm(removed),
+            ChangeEvent::Stored(stored),
+        ] if *written == public(&["a.md"])
+            && *dir == public(&["docs"])
+            && *changed == public(&["docs"])
+            && *moved_from == public(&["a.md"])
+            && *moved_to == public(&["docs", "a.md"])
+            && *copied_from == public(&["docs", "a.md"])
+            && *copied_to == public(&["b.md"])
+            && *removed == public(&["b.md"])
+            && *stored == cid));
+
+        let mut copy = handle.clone();
+        copy.mkdir(&path(&["copy"]), time).await?;
+        assert!(pending(&mut changes).is_empty());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_private_directory_handle_notifies_subscribers() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let private = |segments: &[&str]| ChangedPath {
+            partition: PartitionKind::Private(vec![]),
+            path: path(segments),
+        };
+
+        let forest = HamtForest::new_rsa_2048_rc(rng);
+        let root = PrivateDirectory::new_rc(&forest.empty_name(), time, rng);
+        let mut handle = PrivateDirectoryHandle::new(root, forest, MemoryBlockStore::new());
+        let mut changes = handle.subscribe();
+
+        handle
+            .write(&path(&["a.md"]), b"a".to_vec(), time, rng)
+            .await?;
+        handle
+            .set_metadata(
+                &path(&["a.md"]),
+                "color",
+                Ipld::String("blue".into()),
+                time,
+                rng,
+            )
+            .await?;
+        handle.mkdir(&path(&["docs"]), time, rng).await?;
+        handle
+            .basic_mv(&path(&["a.md"]), &path(&["docs", "a.md"]), time, rng)
+            .await?;
+        handle.rm(&path(&["docs"])).await?;
+        let access_key = handle.store(rng).await?;
+
+        let events = pending(&mut changes);
+        assert!(matches!(&events[..], [
+            ChangeEvent::Write(written),
+            ChangeEvent::MetadataChanged(changed),
+            ChangeEvent::Mkdir(dir),
+            ChangeEvent::Mv { from, to },
+            ChangeEvent::Rm(removed),
+            ChangeEvent::Stored(_),
+        ] if *written == private(&["a.md"])
+            && *changed == private(&["a.md"])
+            && *dir == private(&["docs"])
+            && *from == private(&["a.md"])
+            && *to == private(&["docs", "a.md"])
+            && *removed == private(&["docs"])));
+
+        let node = PrivateNode::load(&access_key, &handle.forest, &handle.store, None).await?;
+        assert!(
+            node.as_dir()?
+                .ls(&[], true, &handle.forest, &handle.store)
+                .await?
+                .is_empty()
+        );
+
+        Ok(())
+    }
+}
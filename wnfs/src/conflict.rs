@@ -0,0 +1,205 @@
+//! Policies for resolving conflicts during reconciliation.
+//!
+//! When two replicas changed the same path concurrently, reconciliation asks a
+//! [`ConflictPolicy`] which version should survive. Every resolved conflict is
+//! reported back as a [`Conflict`].
+
+use crate::{private::PrivateNode, public::PublicNode};
+use std::cmp::Ordering;
+use wnfs_common::{Cid, utils::CondSync};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Decides how a conflict between two concurrent versions of a node is resolved.
+///
+/// Directories never conflict with each other, they are merged recursively.
+/// A conflict is either between two files or between a file and a directory.
+///
+/// Closures of type `Fn(&[String], &N, &N) -> ConflictResolution` implement this trait,
+/// so custom policies can be passed directly.
+///
+/// Only [`ConflictResolution::TieBreak`] and [`ConflictResolution::KeepBoth`] are guaranteed
+/// to produce the same result regardless of which replica runs the reconciliation.
+/// Other resolutions can make replicas diverge unless all of them use a policy that agrees.
+pub trait ConflictPolicy<N>: CondSync {
+    /// Resolves a conflict at `path` between our version and their version of a node.
+    fn resolve(&self, path: &[String], ours: &N, theirs: &N) -> ConflictResolution;
+}
+
+/// How a single conflict gets resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// Applies the built-in deterministic rules:
+    /// Directories win over files, and between two files the one with the lower content hash wins.
+    TieBreak,
+    /// Keeps our version.
+    KeepOurs,
+    /// Keeps their version.
+    KeepTheirs,
+    /// Keeps the version that wins the tie-break at the original path, and stores the
+    /// other version next to it as a conflict copy.
+    KeepBoth,
+}
+
+/// The two sides of a reconciliation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replica {
+    /// The replica that is being reconciled into.
+    Ours,
+    /// The replica that is merged in.
+    Theirs,
+}
+
+/// A conflict that came up during reconciliation, together with how it was resolved.
+#[derive(Debug, Clone)]
+pub struct Conflict<N> {
+    /// The path of the conflicting node.
+    pub path: Vec<String>,
+    /// Our version of the node.
+    pub ours: N,
+    /// Their version of the node.
+    pub theirs: N,
+    /// The resolution the policy chose.
+    pub resolution: ConflictResolution,
+    /// The replica whose version now lives at `path`.
+    pub winner: Replica,
+    /// Where the losing version was stored, if a conflict copy was made.
+    pub copy: Option<Vec<String>>,
+}
+
+/// The default policy, resolving every conflict with [`ConflictResolution::TieBreak`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TieBreakPolicy;
+
+/// Resolves every conflict by keeping both versions, see [`ConflictResolution::KeepBoth`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConflictCopiesPolicy;
+
+/// Keeps the version with the later `modified` timestamp, tie-breaking if they're equal or missing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LastWriterWinsPolicy;
+
+/// Always keeps the version of the given replica.
+#[derive(Debug, Clone, Copy)]
+pub struct PreferReplicaPolicy(pub Replica);
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<N> ConflictPolicy<N> for TieBreakPolicy {
+    fn resolve(&self, _: &[String], _: &N, _: &N) -> ConflictResolution {
+        ConflictResolution::TieBreak
+    }
+}
+
+impl<N> ConflictPolicy<N> for ConflictCopiesPolicy {
+    fn resolve(&self, _: &[String], _: &N, _: &N) -> ConflictResolution {
+        ConflictResolution::KeepBoth
+    }
+}
+
+impl<N> ConflictPolicy<N> for PreferReplicaPolicy {
+    fn resolve(&self, _: &[String], _: &N, _: &N) -> ConflictResolution {
+        match self.0 {
+            Replica::Ours => ConflictResolution::KeepOurs,
+            Replica::Theirs => ConflictResolution::KeepTheirs,
+        }
+    }
+}
+
+impl ConflictPolicy<PublicNode> for LastWriterWinsPolicy {
+    fn resolve(&self, _: &[String], ours: &PublicNode, theirs: &PublicNode) -> ConflictResolution {
+        let ours = ours.get_metadata().get_modified();
+        let theirs = theirs.get_metadata().get_modified();
+        last_writer_wins(ours.cmp(&theirs))
+    }
+}
+
+impl ConflictPolicy<PrivateNode> for LastWriterWinsPolicy {
+    fn resolve(
+        &self,
+        _: &[String],
+        ours: &PrivateNode,
+        theirs: &PrivateNode,
+    ) -> ConflictResolution {
+        let ours = ours.get_metadata().get_modified();
+        let theirs = theirs.get_metadata().get_modified();
+        last_writer_wins(ours.cmp(&theirs))
+    }
+}
+
+impl<N, F> ConflictPolicy<N> for F
+where
+    F: Fn(&[String], &N, &N) -> ConflictResolution + CondSync,
+{
+    fn resolve(&self, path: &[String], ours: &N, theirs: &N) -> ConflictResolution {
+        self(path, ours, theirs)
+    }
+}
+
+impl Replica {
+    /// Returns the other replica.
+    pub fn other(self) -> Self {
+        match self {
+            Self::Ours => Self::Theirs,
+            Self::Theirs => Self::Ours,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+fn last_writer_wins(ordering: Ordering) -> ConflictResolution {
+    match ordering {
+        Ordering::Greater => ConflictResolution::KeepOurs,
+        Ordering::Less => ConflictResolution::KeepTheirs,
+        Ordering::Equal => ConflictResolution::TieBreak,
+    }
+}
+
+/// Derives the name of a conflict copy from the original name and the CID of the losing version.
+///
+/// The CID makes the name deterministic across replicas. The extension, if any, is kept.
+pub(crate) fn conflict_copy_name(name: &str, cid: &Cid) -> String {
+    let suffix = cid.hash().digest()[..4]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    match name.rfind('.') {
+        Some(index) if index > 0 => {
+            let (stem, extension) = name.split_at(index);
+            format!("{stem} (conflict {suffix}){extension}")
+        }
+        _ => format!("{name} (conflict {suffix})"),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wnfs_common::{BlockStore, CODEC_RAW, MemoryBlockStore};
+
+    #[async_std::test]
+    async fn conflict_copy_names_keep_extensions() {
+        let store = &MemoryBlockStore::new();
+        let cid = store.put_block(b"hello".to_vec(), CODEC_RAW).await.unwrap();
+
+        let name = conflict_copy_name("notes.md", &cid);
+        assert!(name.starts_with("notes (conflict "));
+        assert!(name.ends_with(").md"));
+
+        let name = conflict_copy_name(".profile", &cid);
+        assert!(name.starts_with(".profile (conflict "));
+        assert!(name.ends_with(')'));
+    }
+}
@@ -0,0 +1,228 @@
+//! Types for describing path-level changes between two revisions of a file tree.
+
+use crate::find::NodeKind;
+use std::collections::HashMap;
+use wnfs_common::Metadata;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The kind of change that happened at a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathChangeType {
+    /// A node was added at the path.
+    Added,
+    /// The node at the path was removed.
+    Removed,
+    /// The content of the file at the path changed.
+    ContentModified,
+    /// Only the metadata of the node at the path changed.
+    ///
+    /// For directories, changes to the modification time alone are not reported,
+    /// since these happen every time something below the directory changes.
+    MetadataModified,
+    /// The node at the path was moved here from another path, without changing its content.
+    Moved {
+        /// The path the node was at in the old revision.
+        from: Vec<String>,
+    },
+}
+
+/// A change to a single path between two revisions of a file tree.
+///
+/// When a whole directory is added or removed, only the directory itself is reported,
+/// not every node below it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathChange {
+    pub r#type: PathChangeType,
+    pub path: Vec<String>,
+    pub kind: NodeKind,
+}
+
+/// Collects added and removed nodes during a diff and pairs them up into moves.
+#[derive(Debug, Default)]
+pub(crate) struct MoveDetector {
+    removed: HashMap<(NodeKind, Vec<u8>), Vec<Vec<String>>>,
+    added: HashMap<(NodeKind, Vec<u8>), Vec<Vec<String>>>,
+}
+
+/// Hashes the entries of a directory into an identity for move detection.
+///
+/// The identity only depends on the names, kinds and identities of the entries,
+/// so it's equal for directories with equal content, whether or not they were stored.
+#[derive(Default)]
+pub(crate) struct DirectoryIdentity(blake3::Hasher);
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PathChange {
+    /// Returns whether this change is a move from another path.
+    pub fn is_move(&self) -> bool {
+        matches!(self.r#type, PathChangeType::Moved { .. })
+    }
+}
+
+impl MoveDetector {
+    /// Records a node that is only present in the old revision.
+    ///
+    /// `identity` must be equal for nodes with equal content, regardless of their path.
+    pub(crate) fn removed(&mut self, path: Vec<String>, kind: NodeKind, identity: Vec<u8>) {
+        self.removed.entry((kind, identity)).or_default().push(path);
+    }
+
+    /// Records a node that is only present in the new revision.
+    pub(crate) fn added(&mut self, path: Vec<String>, kind: NodeKind, identity: Vec<u8>) {
+        self.added.entry((kind, identity)).or_default().push(path);
+    }
+
+    /// Pairs up removed and added nodes of equal content into moves.
+    ///
+    /// Only unambiguous pairs count as moves: If several removed or several added nodes
+    /// share the same content, like empty files do, none of them is reported as moved.
+    ///
+    /// Returns the resulting changes sorted by path.
+    pub(crate) fn finish(mut self) -> Vec<PathChange> {
+        let mut changes = Vec::new();
+        let change = |r#type, path, kind| PathChange { r#type, path, kind };
+
+        for (key, mut added) in self.added {
+            let kind = key.0;
+            match self.removed.remove(&key) {
+                Some(mut removed) if removed.len() == 1 && added.len() == 1 => {
+                    let from = removed.remove(0);
+                    changes.push(change(
+                        PathChangeType::Moved { from },
+                        added.remove(0),
+                        kind,
+                    ));
+                }
+                removed => {
+                    changes.extend(
+                        removed
+                            .into_iter()
+                            .flatten()
+                            .map(|path| change(PathChangeType::Removed, path, kind)),
+                    );
+                    changes.extend(
+                        added
+                            .into_iter()
+                            .map(|path| change(PathChangeType::Added, path, kind)),
+                    );
+                }
+            }
+        }
+
+        for ((kind, _), removed) in self.removed {
+            changes.extend(
+                removed
+                    .into_iter()
+                    .map(|path| change(PathChangeType::Removed, path, kind)),
+            );
+        }
+
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        changes
+    }
+}
+
+impl DirectoryIdentity {
+    /// Adds an entry of the directory. Entries must be added in the order of their names.
+    pub(crate) fn add_entry(&mut self, name: &str, kind: NodeKind, identity: &[u8]) {
+        let kind = match kind {
+            NodeKind::File => b"file".as_slice(),
+            NodeKind::Directory => b"directory".as_slice(),
+        };
+        for bytes in [name.as_bytes(), kind, identity] {
+            self.0.update(&(bytes.len() as u64).to_le_bytes());
+            self.0.update(bytes);
+        }
+    }
+
+    /// Returns the identity of the directory with all entries added so far.
+    pub(crate) fn finish(&self) -> Vec<u8> {
+        self.0.finalize().as_bytes().to_vec()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Compares directory metadata, ignoring the modification time that changes with every write below.
+pub(crate) fn metadata_eq_ignoring_mtime(a: &Metadata, b: &Metadata) -> bool {
+    let without_mtime = |metadata: &Metadata| {
+        let mut map = metadata.0.clone();
+        map.remove("modified");
+        map
+    };
+
+    without_mtime(a) == without_mtime(b)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn move_detector_pairs_equal_content() {
+        let mut moves = MoveDetector::default();
+        moves.removed(vec!["a".into()], NodeKind::File, vec![1]);
+        moves.removed(vec!["b".into()], NodeKind::File, vec![2]);
+        moves.added(vec!["c".into()], NodeKind::File, vec![1]);
+        moves.added(vec!["d".into()], NodeKind::Directory, vec![2]);
+
+        let changes = moves.finish();
+
+        assert_eq!(
+            changes,
+            vec![
+                PathChange {
+                    r#type: PathChangeType::Removed,
+                    path: vec!["b".into()],
+                    kind: NodeKind::File,
+                },
+                PathChange {
+                    r#type: PathChangeType::Moved {
+                        from: vec!["a".into()]
+                    },
+                    path: vec!["c".into()],
+                    kind: NodeKind::File,
+                },
+                PathChange {
+                    r#type: PathChangeType::Added,
+                    path: vec!["d".into()],
+                    kind: NodeKind::Directory,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn move_detector_ignores_ambiguous_content() {
+        let mut moves = MoveDetector::default();
+        moves.removed(vec!["a".into()], NodeKind::File, vec![]);
+        moves.removed(vec!["b".into()], NodeKind::File, vec![]);
+        moves.added(vec!["c".into()], NodeKind::File, vec![]);
+
+        let changes = moves.finish();
+
+        assert_eq!(
+            changes
+                .iter()
+                .map(|change| (change.path[0].as_str(), &change.r#type))
+                .collect::<Vec<_>>(),
+            vec![
+                ("a", &PathChangeType::Removed),
+                ("b", &PathChangeType::Removed),
+                ("c", &PathChangeType::Added),
+            ]
+        );
+    }
+}
@@ -3,7 +3,7 @@
 use semver::Version;
 use skip_ratchet::PreviousErr;
 use thiserror::Error;
-use wnfs_common::NodeType;
+use wnfs_common::{Cid, NodeType};
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
@@ -12,8 +12,8 @@ use wnfs_common::NodeType;
 /// Core file system errors.
 #[derive(Debug, Error)]
 pub enum FsError {
-    #[error("Invalid WNFS path")]
-    InvalidPath,
+    #[error("Invalid WNFS path: {0}")]
+    InvalidPath(InvalidPathReason),
 
     #[error("Expected a file")]
     NotAFile,
@@ -56,6 +56,58 @@ pub enum FsError {
 
     #[error("Cannot find the partition with this name")]
     PartitionNotFound,
+
+    #[error("Invalid glob pattern {0}")]
+    InvalidGlobPattern(String),
+
+    #[error("Cannot find the requested revision")]
+    RevisionNotFound,
+
+    #[error("Copying private content into a public partition requires publishing it explicitly")]
+    PublishingRequired,
+
+    #[error("None of the keys can decrypt the mount table")]
+    MountTableKeyNotFound,
+
+    #[error("The root history isn't enabled")]
+    HistoryNotEnabled,
+
+    #[error("Invalid time {1} in the history entry of root {0}")]
+    InvalidHistoryTime(Cid, i64),
+
+    #[error("Invalid history index in node {0}")]
+    InvalidClockIndex(Cid),
+
+    #[error("Files mounted from snapshots can't be changed")]
+    ReadOnlySnapshot,
+}
+
+/// Why a path was rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum InvalidPathReason {
+    #[error("The path is empty")]
+    Empty,
+
+    #[error("Path segments can't be empty")]
+    EmptySegment,
+
+    #[error("{0:?} can't be used as a name")]
+    RelativeSegment(String),
+
+    #[error("Names can't contain slashes: {0:?}")]
+    ContainsSlash(String),
+
+    #[error("Unknown partition {0:?}")]
+    UnknownPartition(String),
+
+    #[error("Private roots have to be in the private partition")]
+    NotPrivate,
+
+    #[error("Expected a path below a partition root")]
+    PartitionRoot,
+
+    #[error("Mounted files can't be removed from their mount point")]
+    MountedFile,
 }
 
 /// Data sharing related errors
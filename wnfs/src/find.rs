@@ -0,0 +1,544 @@
+//! Glob patterns and `find`-style queries over WNFS paths.
+
+use crate::{error::FsError, walk::WalkOptions};
+use anyhow::{Result, bail};
+use chrono::{DateTime, Utc};
+use ipld_core::ipld::Ipld;
+use std::{collections::BTreeSet, str::FromStr};
+use wnfs_common::{
+    Metadata,
+    utils::{Arc, CondSend, CondSync},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A glob pattern matching paths segment by segment.
+///
+/// Supported syntax:
+/// - `*` matches any number of characters within a single path segment,
+/// - `?` matches exactly one character,
+/// - `[abc]`, `[a-z]` and their negations `[!abc]`/`[^abc]` match one character from a set,
+/// - `**` as a whole segment matches any number of path segments, including none,
+/// - `\` escapes the following character.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs::find::Glob;
+///
+/// let glob = Glob::new("docs/**/*.md").unwrap();
+///
+/// assert!(glob.matches(&["docs".into(), "README.md".into()]));
+/// assert!(glob.matches(&["docs".into(), "a".into(), "b".into(), "notes.md".into()]));
+/// assert!(!glob.matches(&["src".into(), "lib.md".into()]));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    pattern: String,
+    segments: Vec<GlobSegment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum GlobSegment {
+    /// `**`
+    AnyDepth,
+    Pattern(Vec<GlobToken>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum GlobToken {
+    Literal(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnyChars,
+    /// `[...]`
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// Whether a node is a file or a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    File,
+    Directory,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+type MetadataPredicate = Arc<dyn Fn(&Metadata) -> bool + Send + Sync>;
+
+#[cfg(target_arch = "wasm32")]
+type MetadataPredicate = Arc<dyn Fn(&Metadata) -> bool>;
+
+/// A `find`-style query, selecting nodes by path, type, size, modification time and metadata.
+///
+/// All configured filters need to match for a node to be part of the results.
+///
+/// # Examples
+///
+/// ```
+/// use chrono::{TimeZone, Utc};
+/// use wnfs::find::{FindQuery, Glob, NodeKind};
+///
+/// let query = FindQuery::new()
+///     .glob(Glob::new("**/*.md").unwrap())
+///     .kind(NodeKind::File)
+///     .max_size(1024 * 1024)
+///     .modified_after(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap())
+///     .has_metadata("tags");
+/// ```
+#[derive(Clone, Default)]
+pub struct FindQuery {
+    glob: Option<Glob>,
+    kind: Option<NodeKind>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<DateTime<Utc>>,
+    modified_before: Option<DateTime<Utc>>,
+    metadata: Vec<MetadataPredicate>,
+    max_depth: Option<usize>,
+    concurrency: Option<usize>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Glob {
+    /// Parses a glob pattern. Segments are separated by `/`, empty segments are ignored.
+    pub fn new(pattern: &str) -> Result<Self> {
+        let segments = pattern
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| match segment {
+                "**" => Ok(GlobSegment::AnyDepth),
+                _ => Ok(GlobSegment::Pattern(Self::parse_segment(pattern, segment)?)),
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            segments,
+        })
+    }
+
+    /// Returns the pattern this glob was parsed from.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether given path matches this pattern as a whole.
+    pub fn matches(&self, path: &[String]) -> bool {
+        self.states_after(path).contains(&self.segments.len())
+    }
+
+    /// Whether any path below given directory path could match this pattern.
+    pub fn could_match_below(&self, path: &[String]) -> bool {
+        self.states_after(path)
+            .iter()
+            .any(|&state| state < self.segments.len())
+    }
+
+    /// The maximum path length this pattern can match, or `None` if it's unbounded.
+    pub fn max_depth(&self) -> Option<usize> {
+        if self.segments.contains(&GlobSegment::AnyDepth) {
+            None
+        } else {
+            Some(self.segments.len())
+        }
+    }
+
+    /// Simulates the pattern as a non-deterministic automaton over path segments,
+    /// returning the set of pattern positions reachable after consuming `path`.
+    fn states_after(&self, path: &[String]) -> BTreeSet<usize> {
+        let mut states = self.closure(BTreeSet::from([0]));
+        for segment in path {
+            let next = states
+                .iter()
+                .filter_map(|&state| match self.segments.get(state)? {
+                    GlobSegment::AnyDepth => Some(state),
+                    GlobSegment::Pattern(tokens) => {
+                        Self::matches_segment(tokens, segment).then_some(state + 1)
+                    }
+                })
+                .collect();
+
+            states = self.closure(next);
+            if states.is_empty() {
+                break;
+            }
+        }
+
+        states
+    }
+
+    /// Adds the positions after any `**`, since it can match zero segments.
+    fn closure(&self, mut states: BTreeSet<usize>) -> BTreeSet<usize> {
+        let mut pending = states.iter().copied().collect::<Vec<_>>();
+        while let Some(state) = pending.pop() {
+            if let Some(GlobSegment::AnyDepth) = self.segments.get(state) {
+                if states.insert(state + 1) {
+                    pending.push(state + 1);
+                }
+            }
+        }
+
+        states
+    }
+
+    fn matches_segment(tokens: &[GlobToken], segment: &str) -> bool {
+        let chars = segment.chars().collect::<Vec<_>>();
+        let (mut t, mut c) = (0, 0);
+        // Where to continue after the last `*`, in case we need to backtrack
+        let mut backtrack = None;
+
+        while c < chars.len() {
+            match tokens.get(t) {
+                Some(GlobToken::AnyChars) => {
+                    backtrack = Some((t, c));
+                    t += 1;
+                    continue;
+                }
+                Some(token) if token.matches(chars[c]) => {
+                    t += 1;
+                    c += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            match backtrack {
+                Some((star, star_c)) => {
+                    t = star + 1;
+                    c = star_c + 1;
+                    backtrack = Some((star, star_c + 1));
+                }
+                None => return false,
+            }
+        }
+
+        tokens[t..]
+            .iter()
+            .all(|token| matches!(token, GlobToken::AnyChars))
+    }
+
+    fn parse_segment(pattern: &str, segment: &str) -> Result<Vec<GlobToken>> {
+        let invalid = |reason: &str| FsError::InvalidGlobPattern(format!("{pattern:?}: {reason}"));
+
+        let mut tokens = Vec::new();
+        let mut chars = segment.chars().peekable();
+        while let Some(char) = chars.next() {
+            let token = match char {
+                '*' => GlobToken::AnyChars,
+                '?' => GlobToken::AnyChar,
+                '\\' => GlobToken::Literal(
+                    chars
+                        .next()
+                        .ok_or_else(|| invalid("trailing escape character"))?,
+                ),
+                '[' => {
+                    let negated = chars.next_if(|c| matches!(c, '!' | '^')).is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        let start = match chars.next() {
+                            // A `]` right at the start is part of the class
+                            Some(']') if !ranges.is_empty() => break,
+                            Some('\\') => chars
+                                .next()
+                                .ok_or_else(|| invalid("trailing escape character"))?,
+                            Some(char) => char,
+                            None => bail!(invalid("unclosed character class")),
+                        };
+
+                        if chars.next_if_eq(&'-').is_none() {
+                            ranges.push((start, start));
+                            continue;
+                        }
+
+                        match chars.next() {
+                            // A trailing `-` is part of the class
+                            Some(']') => {
+                                ranges.push((start, start));
+                                ranges.push(('-', '-'));
+                                break;
+                            }
+                            Some(end) if start <= end => ranges.push((start, end)),
+                            Some(_) => bail!(invalid("invalid character range")),
+                            None => bail!(invalid("unclosed character class")),
+                        }
+                    }
+
+                    GlobToken::Class { negated, ranges }
+                }
+                char => GlobToken::Literal(char),
+            };
+
+            tokens.push(token);
+        }
+
+        Ok(tokens)
+    }
+}
+
+impl GlobToken {
+    fn matches(&self, char: char) -> bool {
+        match self {
+            Self::Literal(literal) => *literal == char,
+            Self::AnyChar => true,
+            Self::AnyChars => true,
+            Self::Class { negated, ranges } => {
+                ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&char))
+                    != *negated
+            }
+        }
+    }
+}
+
+impl FromStr for Glob {
+    type Err = anyhow::Error;
+
+    fn from_str(pattern: &str) -> Result<Self> {
+        Self::new(pattern)
+    }
+}
+
+impl FindQuery {
+    /// Creates a query that matches every node.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only matches nodes whose path relative to the search root matches given glob.
+    pub fn glob(mut self, glob: Glob) -> Self {
+        self.glob = Some(glob);
+        self
+    }
+
+    /// Only matches files or only matches directories.
+    pub fn kind(mut self, kind: NodeKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Only matches files with at least given size in bytes.
+    pub fn min_size(mut self, size: u64) -> Self {
+        self.min_size = Some(size);
+        self
+    }
+
+    /// Only matches files with at most given size in bytes.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Only matches nodes that were modified at or after given time.
+    pub fn modified_after(mut self, time: DateTime<Utc>) -> Self {
+        self.modified_after = Some(time);
+        self
+    }
+
+    /// Only matches nodes that were modified before given time.
+    pub fn modified_before(mut self, time: DateTime<Utc>) -> Self {
+        self.modified_before = Some(time);
+        self
+    }
+
+    /// Only matches nodes that have a metadata entry under given key.
+    pub fn has_metadata(self, key: &str) -> Self {
+        let key = key.to_string();
+        self.metadata_matches(move |metadata| metadata.get(&key).is_some())
+    }
+
+    /// Only matches nodes that have given value stored in their metadata under given key.
+    pub fn metadata_eq(self, key: &str, value: Ipld) -> Self {
+        let key = key.to_string();
+        self.metadata_matches(move |metadata| metadata.get(&key) == Some(&value))
+    }
+
+    /// Only matches nodes for which given predicate over their metadata returns `true`.
+    pub fn metadata_matches(
+        mut self,
+        predicate: impl Fn(&Metadata) -> bool + CondSend + CondSync + 'static,
+    ) -> Self {
+        self.metadata.push(Arc::new(predicate));
+        self
+    }
+
+    /// Only searches up to given depth below the search root.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Sets how many children of a directory are fetched (and decrypted) concurrently.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Whether the query filters on file sizes, which requires loading file content headers.
+    pub(crate) fn needs_size(&self) -> bool {
+        self.min_size.is_some() || self.max_size.is_some()
+    }
+
+    /// Checks all filters except for the size filters.
+    ///
+    /// `relative_path` is the node's path relative to the search root.
+    pub(crate) fn matches(
+        &self,
+        relative_path: &[String],
+        kind: NodeKind,
+        metadata: &Metadata,
+    ) -> bool {
+        if self.kind.is_some_and(|expected| expected != kind) {
+            return false;
+        }
+
+        if self.needs_size() && kind != NodeKind::File {
+            return false;
+        }
+
+        if let Some(glob) = &self.glob {
+            if !glob.matches(relative_path) {
+                return false;
+            }
+        }
+
+        if self.modified_after.is_some() || self.modified_before.is_some() {
+            let Some(modified) = metadata.get_modified() else {
+                return false;
+            };
+
+            if self.modified_after.is_some_and(|after| modified < after)
+                || self
+                    .modified_before
+                    .is_some_and(|before| modified >= before)
+            {
+                return false;
+            }
+        }
+
+        self.metadata.iter().all(|predicate| predicate(metadata))
+    }
+
+    /// Checks the size filters.
+    pub(crate) fn matches_size(&self, size: u64) -> bool {
+        self.min_size.is_none_or(|min| size >= min) && self.max_size.is_none_or(|max| size <= max)
+    }
+
+    /// The options for walking the tree below a search root at depth `root_depth`.
+    ///
+    /// Directories that can't contain any matches of the glob are pruned.
+    pub(crate) fn walk_options(&self, root_depth: usize) -> WalkOptions {
+        let mut options = WalkOptions::new();
+
+        let max_depth = match (self.max_depth, self.glob.as_ref().and_then(Glob::max_depth)) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
+        if let Some(max_depth) = max_depth {
+            options = options.max_depth(max_depth);
+        }
+
+        if let Some(concurrency) = self.concurrency {
+            options = options.concurrency(concurrency);
+        }
+
+        if let Some(glob) = self.glob.clone() {
+            options = options.prune(move |path, _| !glob.could_match_below(&path[root_depth..]));
+        }
+
+        options
+    }
+}
+
+impl std::fmt::Debug for FindQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FindQuery")
+            .field("glob", &self.glob)
+            .field("kind", &self.kind)
+            .field("min_size", &self.min_size)
+            .field("max_size", &self.max_size)
+            .field("modified_after", &self.modified_after)
+            .field("modified_before", &self.modified_before)
+            .field("metadata", &format!("<{} predicates>", self.metadata.len()))
+            .field("max_depth", &self.max_depth)
+            .field("concurrency", &self.concurrency)
+            .finish()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(path: &str) -> Vec<String> {
+        path.split('/').map(String::from).collect()
+    }
+
+    #[test]
+    fn glob_matches_segments() {
+        let glob = Glob::new("src/*.rs").unwrap();
+        assert!(glob.matches(&path("src/lib.rs")));
+        assert!(!glob.matches(&path("src/lib.rs.bak")));
+        assert!(!glob.matches(&path("src/a/lib.rs")));
+        assert!(!glob.matches(&path("src")));
+
+        let glob = Glob::new("/data/file-??.[0-9][!a-z]").unwrap();
+        assert!(glob.matches(&path("data/file-ab.1A")));
+        assert!(!glob.matches(&path("data/file-abc.1A")));
+        assert!(!glob.matches(&path("data/file-ab.xA")));
+        assert!(!glob.matches(&path("data/file-ab.1a")));
+
+        let glob = Glob::new(r"a\*b").unwrap();
+        assert!(glob.matches(&path("a*b")));
+        assert!(!glob.matches(&path("axb")));
+
+        let glob = Glob::new("*a*b*").unwrap();
+        assert!(glob.matches(&path("xxaxxbxx")));
+        assert!(glob.matches(&path("ab")));
+        assert!(!glob.matches(&path("ba")));
+    }
+
+    #[test]
+    fn glob_matches_any_depth() {
+        let glob = Glob::new("docs/**/*.md").unwrap();
+        assert!(glob.matches(&path("docs/README.md")));
+        assert!(glob.matches(&path("docs/a/b/c.md")));
+        assert!(!glob.matches(&path("docs/a/b/c.txt")));
+        assert!(!glob.matches(&path("other/c.md")));
+        assert_eq!(glob.max_depth(), None);
+
+        assert!(glob.could_match_below(&path("docs")));
+        assert!(glob.could_match_below(&path("docs/a/b")));
+        assert!(!glob.could_match_below(&path("other")));
+
+        let glob = Glob::new("**").unwrap();
+        assert!(glob.matches(&[]));
+        assert!(glob.matches(&path("a/b/c")));
+
+        let glob = Glob::new("a/*").unwrap();
+        assert_eq!(glob.max_depth(), Some(2));
+        assert!(glob.could_match_below(&path("a")));
+        assert!(!glob.could_match_below(&path("a/b")));
+    }
+
+    #[test]
+    fn invalid_globs_are_rejected() {
+        for pattern in ["a/[bc", "a/[]", "a/b\\", "a/[z-a]"] {
+            assert!(Glob::new(pattern).is_err(), "{pattern} should be rejected");
+        }
+    }
+}
@@ -123,13 +123,24 @@
 
 #![deny(unsafe_code)]
 
+pub mod changes;
+pub mod conflict;
+pub mod diff;
 pub mod error;
+pub mod find;
+pub mod merge;
+pub mod path;
 pub mod private;
 pub mod public;
+#[doc(hidden)] // Its blocks are only written by the root tree, which is in "prerelease"
+pub mod root_history;
 #[doc(hidden)] // The API is in "prerelease" for now
 pub mod root_tree;
 pub mod traits;
+#[doc(hidden)] // Only stages changes to a root tree, so it's in "prerelease" along with it
+pub mod transaction;
 mod utils;
+pub mod walk;
 
 pub mod rand_core {
     //! Re-exports of rand-core lib.
@@ -153,12 +164,28 @@ pub mod nameaccumulator {
 //--------------------------------------------------------------------------------------------------
 
 /// The version of the WNFS data format that this library outputs
-pub const WNFS_VERSION: semver::Version = semver::Version::new(1, 0, 0);
+///
+/// Version 1.1 adds the `clock` index to public nodes with previous revisions.
+/// Version 1.0 nodes don't have one and stay readable.
+pub const WNFS_VERSION: semver::Version = semver::Version::new(1, 1, 0);
+
+/// The oldest version of the WNFS data format that this library can read
+const OLDEST_READABLE_WNFS_VERSION: semver::Version = semver::Version::new(1, 0, 0);
 
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
 
+/// Picks an earlier revision of a node, e.g. for restoring it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevisionSelector {
+    /// The revision this many steps before the current one. `Back(1)` is the previous revision.
+    Back(usize),
+    /// The revision that was current at given time,
+    /// i.e. the newest one that was modified at or before it.
+    AsOf(chrono::DateTime<chrono::Utc>),
+}
+
 /// The result of an basic get operation.
 pub(crate) enum SearchResult<T> {
     Missing(T, usize),
@@ -179,12 +206,21 @@ pub fn is_readable_wnfs_version(version: &semver::Version) -> bool {
 pub fn get_wnfs_version_req() -> semver::VersionReq {
     use semver::*;
     VersionReq {
-        comparators: vec![Comparator {
-            op: Op::Exact,
-            major: WNFS_VERSION.major,
-            minor: Some(WNFS_VERSION.minor),
-            patch: None,
-            pre: Prerelease::EMPTY,
-        }],
+        comparators: vec![
+            Comparator {
+                op: Op::GreaterEq,
+                major: OLDEST_READABLE_WNFS_VERSION.major,
+                minor: Some(OLDEST_READABLE_WNFS_VERSION.minor),
+                patch: None,
+                pre: Prerelease::EMPTY,
+            },
+            Comparator {
+                op: Op::LessEq,
+                major: WNFS_VERSION.major,
+                minor: Some(WNFS_VERSION.minor),
+                patch: None,
+                pre: Prerelease::EMPTY,
+            },
+        ],
     }
 }
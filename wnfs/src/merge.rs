@@ -0,0 +1,394 @@
+//! Three-way merging of file contents during reconciliation.
+//!
+//! When two replicas changed the same file concurrently, reconciliation can ask a
+//! [`MergeDriver`] to combine both versions based on their common ancestor, before
+//! falling back to the conflict policy.
+
+use std::ops::Range;
+use wnfs_common::utils::CondSync;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Merges two concurrent versions of a file's content.
+///
+/// The driver receives the content of the common ancestor (if the versions share any history),
+/// our content and their content. If it returns [`MergeOutcome::Conflict`], reconciliation
+/// resolves the conflict with its conflict policy as usual.
+///
+/// Closures of type `Fn(&[String], Option<&[u8]>, &[u8], &[u8]) -> MergeOutcome` implement this trait,
+/// so custom drivers can be passed directly.
+///
+/// For replicas to converge, a driver must produce the same result when `ours` and `theirs` are swapped.
+pub trait MergeDriver: CondSync {
+    /// Merges our and their content of the file at `path`, given the content of their common ancestor.
+    fn merge(
+        &self,
+        path: &[String],
+        base: Option<&[u8]>,
+        ours: &[u8],
+        theirs: &[u8],
+    ) -> MergeOutcome;
+}
+
+/// The result of a three-way merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeOutcome {
+    /// Both versions were combined into this content.
+    Merged(Vec<u8>),
+    /// The versions can't be combined automatically.
+    Conflict,
+}
+
+/// A merge driver for UTF-8 text, merging line by line like `diff3`.
+///
+/// Changes to different regions of the base are combined. If both sides changed the
+/// same region differently, or either side isn't valid UTF-8, this reports a conflict.
+/// Without a common ancestor, only identical versions can be merged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineMergeDriver;
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl MergeDriver for LineMergeDriver {
+    fn merge(&self, _: &[String], base: Option<&[u8]>, ours: &[u8], theirs: &[u8]) -> MergeOutcome {
+        if ours == theirs {
+            return MergeOutcome::Merged(ours.to_vec());
+        }
+
+        let Some(base) = base else {
+            return MergeOutcome::Conflict;
+        };
+
+        match (
+            std::str::from_utf8(base),
+            std::str::from_utf8(ours),
+            std::str::from_utf8(theirs),
+        ) {
+            (Ok(base), Ok(ours), Ok(theirs)) => match merge_lines(base, ours, theirs) {
+                Some(merged) => MergeOutcome::Merged(merged.into_bytes()),
+                None => MergeOutcome::Conflict,
+            },
+            _ => MergeOutcome::Conflict,
+        }
+    }
+}
+
+impl<F> MergeDriver for F
+where
+    F: Fn(&[String], Option<&[u8]>, &[u8], &[u8]) -> MergeOutcome + CondSync,
+{
+    fn merge(
+        &self,
+        path: &[String],
+        base: Option<&[u8]>,
+        ours: &[u8],
+        theirs: &[u8],
+    ) -> MergeOutcome {
+        self(path, base, ours, theirs)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Merges two texts line by line, given the text they're both based on.
+///
+/// The base is split into stable regions, where a line is unchanged on both sides, and the
+/// unstable regions in between. An unstable region is taken from whichever side changed it.
+/// Returns `None` if both sides changed the same region differently.
+fn merge_lines(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    let base = base.split_inclusive('\n').collect::<Vec<_>>();
+    let ours = ours.split_inclusive('\n').collect::<Vec<_>>();
+    let theirs = theirs.split_inclusive('\n').collect::<Vec<_>>();
+
+    let our_matches = matching_lines(&base, &ours);
+    let their_matches = matching_lines(&base, &theirs);
+
+    let mut merged = String::new();
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    loop {
+        // Find the next base line that's kept on both sides
+        let stable = (b..base.len()).find_map(|i| Some((i, our_matches[i]?, their_matches[i]?)));
+        let (end_b, end_o, end_t) = stable.unwrap_or((base.len(), ours.len(), theirs.len()));
+
+        let base_chunk = &base[b..end_b];
+        let our_chunk = &ours[o..end_o];
+        let their_chunk = &theirs[t..end_t];
+
+        let chunk = if our_chunk == base_chunk || our_chunk == their_chunk {
+            their_chunk
+        } else if their_chunk == base_chunk {
+            our_chunk
+        } else {
+            return None;
+        };
+        merged.extend(chunk.iter().copied());
+
+        if stable.is_none() {
+            return Some(merged);
+        }
+
+        merged.push_str(ours[end_o]);
+        (b, o, t) = (end_b + 1, end_o + 1, end_t + 1);
+    }
+}
+
+/// Finds a longest common subsequence of lines using Myers' diff algorithm.
+///
+/// This uses the linear space variant: Both texts are split at the middle of an optimal
+/// edit path, and both halves are matched on their own.
+///
+/// Returns, for every line of `a`, the index of the line of `b` it was matched with.
+fn matching_lines(a: &[&str], b: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; a.len()];
+    match_ranges(a, 0..a.len(), b, 0..b.len(), &mut matches);
+    matches
+}
+
+/// Matches the lines of `a[a_range]` with the lines of `b[b_range]`.
+fn match_ranges(
+    a: &[&str],
+    mut a_range: Range<usize>,
+    b: &[&str],
+    mut b_range: Range<usize>,
+    matches: &mut [Option<usize>],
+) {
+    // Lines both sides start or end with are matched right away
+    while !a_range.is_empty() && !b_range.is_empty() && a[a_range.start] == b[b_range.start] {
+        matches[a_range.start] = Some(b_range.start);
+        a_range.start += 1;
+        b_range.start += 1;
+    }
+    while !a_range.is_empty() && !b_range.is_empty() && a[a_range.end - 1] == b[b_range.end - 1] {
+        a_range.end -= 1;
+        b_range.end -= 1;
+        matches[a_range.end] = Some(b_range.end);
+    }
+
+    if a_range.is_empty() || b_range.is_empty() {
+        return;
+    }
+
+    let Some((x, y)) = middle_snake(&a[a_range.clone()], &b[b_range.clone()]) else {
+        return;
+    };
+    if (x, y) == (0, 0) || (x, y) == (a_range.len(), b_range.len()) {
+        return;
+    }
+
+    let (a_mid, b_mid) = (a_range.start + x, b_range.start + y);
+    match_ranges(a, a_range.start..a_mid, b, b_range.start..b_mid, matches);
+    match_ranges(a, a_mid..a_range.end, b, b_mid..b_range.end, matches);
+}
+
+/// Finds a point on an optimal edit path from `a` to `b` by searching from both ends at once,
+/// until the searches overlap in the middle.
+///
+/// Returns `None` if `a` and `b` don't have any lines in common.
+fn middle_snake(a: &[&str], b: &[&str]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d;
+    let length = 2 * max_d + 2;
+    let index = |k: isize| (offset + k) as usize;
+
+    // The furthest reaching `x` on every diagonal `k = x - y`, from the start and from the end
+    let mut forward = vec![-1isize; length as usize];
+    let mut backward = vec![-1isize; length as usize];
+    forward[index(1)] = 0;
+    backward[index(1)] = 0;
+
+    let delta = n - m;
+    let front = delta % 2 != 0;
+
+    // Diagonals whose paths left the edit graph aren't searched any further
+    let (mut forward_start, mut forward_end) = (0, 0);
+    let (mut backward_start, mut backward_end) = (0, 0);
+
+    for d in 0..max_d {
+        for k in (-d + forward_start..=d - forward_end).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[index(k - 1)] < forward[index(k + 1)]) {
+                forward[index(k + 1)]
+            } else {
+                forward[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[index(k)] = x;
+
+            if x > n {
+                forward_end += 2;
+            } else if y > m {
+                forward_start += 2;
+            } else if front {
+                let backward_k = delta - k;
+                if (-offset..length - offset).contains(&backward_k)
+                    && backward[index(backward_k)] != -1
+                    && x >= n - backward[index(backward_k)]
+                {
+                    return Some((x as usize, y as usize));
+                }
+            }
+        }
+
+        for k in (-d + backward_start..=d - backward_end).step_by(2) {
+            let mut x = if k == -d || (k != d && backward[index(k - 1)] < backward[index(k + 1)]) {
+                backward[index(k + 1)]
+            } else {
+                backward[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[index(k)] = x;
+
+            if x > n {
+                backward_end += 2;
+            } else if y > m {
+                backward_start += 2;
+            } else if !front {
+                let forward_k = delta - k;
+                if (-offset..length - offset).contains(&forward_k)
+                    && forward[index(forward_k)] != -1
+                    && forward[index(forward_k)] >= n - x
+                {
+                    let forward_x = forward[index(forward_k)];
+                    return Some((forward_x as usize, (forward_x - forward_k) as usize));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+
+    fn merge(base: Option<&str>, ours: &str, theirs: &str) -> MergeOutcome {
+        LineMergeDriver.merge(
+            &[],
+            base.map(str::as_bytes),
+            ours.as_bytes(),
+            theirs.as_bytes(),
+        )
+    }
+
+    fn merged(text: &str) -> MergeOutcome {
+        MergeOutcome::Merged(text.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn line_merge_combines_changes_to_different_lines() {
+        let base = "one\ntwo\nthree\nfour\n";
+        let ours = "ONE\ntwo\nthree\nfour\n";
+        let theirs = "one\ntwo\nthree\nfour\nfive\n";
+
+        assert_eq!(
+            merge(Some(base), ours, theirs),
+            merged("ONE\ntwo\nthree\nfour\nfive\n")
+        );
+        assert_eq!(
+            merge(Some(base), theirs, ours),
+            merged("ONE\ntwo\nthree\nfour\nfive\n")
+        );
+        assert_eq!(
+            merge(Some(base), "one\nthree\nfour\n", "one\ntwo\nthree\n4\n"),
+            merged("one\nthree\n4\n")
+        );
+    }
+
+    #[test]
+    fn line_merge_reports_overlapping_changes() {
+        let base = "one\ntwo\nthree\n";
+
+        assert_eq!(
+            merge(Some(base), "one\nours\nthree\n", "one\ntheirs\nthree\n"),
+            MergeOutcome::Conflict
+        );
+        assert_eq!(
+            merge(Some(base), "one\nsame\nthree\n", "one\nsame\nthree\n"),
+            merged("one\nsame\nthree\n")
+        );
+        assert_eq!(merge(None, "ours", "theirs"), MergeOutcome::Conflict);
+        assert_eq!(
+            LineMergeDriver.merge(&[], Some(b"a"), &[0xff], b"b"),
+            MergeOutcome::Conflict
+        );
+    }
+
+    #[test]
+    fn matching_lines_finds_common_subsequence() {
+        let a = ["a", "b", "c", "a", "b", "b", "a"];
+        let b = ["c", "b", "a", "b", "a", "c"];
+        let matches = matching_lines(&a, &b);
+
+        assert_eq!(matches.iter().flatten().count(), 4);
+        assert!(
+            matches
+                .iter()
+                .enumerate()
+                .filter_map(|(i, j)| Some((a[i], b[(*j)?])))
+                .all(|(x, y)| x == y)
+        );
+        assert!(matching_lines(&[], &["a"]).is_empty());
+        assert_eq!(matching_lines(&["a"], &[]), vec![None]);
+    }
+
+    #[test]
+    fn matching_lines_finds_longest_common_subsequences() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let lines = ["a", "b", "c", "d"];
+        let mut random_lines = |len: usize| {
+            (0..len)
+                .map(|_| lines[rng.gen_range(0..lines.len())])
+                .collect::<Vec<_>>()
+        };
+
+        for len in 0..200 {
+            let a = random_lines(len % 23);
+            let b = random_lines(len % 17);
+            let matches = matching_lines(&a, &b);
+
+            // Matches are increasing and between equal lines
+            let pairs = matches
+                .iter()
+                .enumerate()
+                .filter_map(|(i, j)| Some((i, (*j)?)))
+                .collect::<Vec<_>>();
+            assert!(pairs.iter().all(|&(i, j)| a[i] == b[j]));
+            assert!(pairs.windows(2).all(|w| w[0].1 < w[1].1));
+
+            // Compare with the length of a longest common subsequence computed by dynamic programming
+            let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+            for i in 0..a.len() {
+                for j in 0..b.len() {
+                    lcs[i + 1][j + 1] = if a[i] == b[j] {
+                        lcs[i][j] + 1
+                    } else {
+                        lcs[i][j + 1].max(lcs[i + 1][j])
+                    };
+                }
+            }
+            assert_eq!(pairs.len(), lcs[a.len()][b.len()], "{a:?} {b:?}");
+        }
+    }
+}
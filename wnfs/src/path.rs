@@ -0,0 +1,371 @@
+//! Parsing and validation of WNFS paths.
+//!
+//! All file system APIs take paths as slices of names. [`WnfsPath`] builds these slices
+//! from `/public/a/b`-style strings, so that every app splits, validates and normalizes
+//! paths the same way.
+
+use crate::error::{FsError, InvalidPathReason};
+use anyhow::{Result, bail, ensure};
+use std::{fmt, ops::Deref, str::FromStr};
+use unicode_normalization::UnicodeNormalization;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The partition of a root tree that an absolute path starts with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PartitionPrefix {
+    /// The `public` partition.
+    Public,
+    /// The `exchange` partition.
+    Exchange,
+    /// The `private` partition, which private roots are mounted in.
+    Private,
+}
+
+/// A validated path of NFC-normalized names.
+///
+/// Absolute paths like `/public/a/b` start with a partition prefix and are meant for the
+/// root tree, while relative paths like `a/b` are meant for directories. Either way, a path
+/// dereferences to its names, so it can be passed to all functions taking `&[String]` paths.
+///
+/// Names can't be empty, `.` or `..`, and can't contain slashes. A single trailing slash
+/// is ignored when parsing.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     path::{PartitionPrefix, WnfsPath},
+///     public::PublicDirectory,
+///     root_tree::RootTree,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let path: WnfsPath = "/public/docs/notes.md".parse()?;
+///     assert_eq!(path.partition(), Some(PartitionPrefix::Public));
+///     assert_eq!(path.to_string(), "/public/docs/notes.md");
+///
+///     let mut root_tree = RootTree::empty(MemoryBlockStore::new());
+///     root_tree.write(&path, b"# Notes".to_vec()).await?;
+///     assert_eq!(root_tree.read(&path).await?, b"# Notes");
+///
+///     let store = MemoryBlockStore::new();
+///     let mut dir = PublicDirectory::new_rc(Utc::now());
+///     dir.write(&path.to_relative()?, b"# Notes".to_vec(), Utc::now(), &store).await?;
+///     assert_eq!(dir.read(&WnfsPath::parse("docs/notes.md")?, &store).await?, b"# Notes");
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WnfsPath {
+    partition: Option<PartitionPrefix>,
+    /// All names, starting with the partition prefix for absolute paths.
+    segments: Vec<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PartitionPrefix {
+    /// Gets the partition for the first name of an absolute path.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "public" => Some(Self::Public),
+            "exchange" => Some(Self::Exchange),
+            "private" => Some(Self::Private),
+            _ => None,
+        }
+    }
+
+    /// The first name of absolute paths in this partition.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Public => "public",
+            Self::Exchange => "exchange",
+            Self::Private => "private",
+        }
+    }
+}
+
+impl WnfsPath {
+    /// Parses a path, which is absolute if it starts with a slash and relative otherwise.
+    pub fn parse(path: &str) -> Result<Self> {
+        let (rest, absolute) = match path.strip_prefix('/') {
+            Some(rest) => (rest, true),
+            None => (path, false),
+        };
+        let rest = rest.strip_suffix('/').unwrap_or(rest);
+        ensure!(
+            !rest.is_empty(),
+            FsError::InvalidPath(InvalidPathReason::Empty)
+        );
+
+        let mut names = rest.split('/');
+        if !absolute {
+            return Self::relative(names);
+        }
+
+        // `split` always yields at least one item
+        let first = names.next().unwrap_or_default();
+        let Some(partition) = PartitionPrefix::from_name(first) else {
+            bail!(FsError::InvalidPath(InvalidPathReason::UnknownPartition(
+                first.nfc().collect()
+            )))
+        };
+
+        Self::new(partition, names)
+    }
+
+    /// Creates an absolute path from a partition and the names below it.
+    pub fn new(
+        partition: PartitionPrefix,
+        names: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<Self> {
+        let mut segments = vec![partition.as_str().to_string()];
+        for name in names {
+            segments.push(Self::normalize_name(name.as_ref())?);
+        }
+
+        Ok(Self {
+            partition: Some(partition),
+            segments,
+        })
+    }
+
+    /// Creates a relative path from given names.
+    pub fn relative(names: impl IntoIterator<Item = impl AsRef<str>>) -> Result<Self> {
+        let segments = names
+            .into_iter()
+            .map(|name| Self::normalize_name(name.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        ensure!(
+            !segments.is_empty(),
+            FsError::InvalidPath(InvalidPathReason::Empty)
+        );
+
+        Ok(Self {
+            partition: None,
+            segments,
+        })
+    }
+
+    /// Validates a single name and converts it to Unicode normalization form C.
+    pub fn normalize_name(name: &str) -> Result<String> {
+        let name: String = name.nfc().collect();
+        ensure!(
+            !name.is_empty(),
+            FsError::InvalidPath(InvalidPathReason::EmptySegment)
+        );
+        ensure!(
+            name != "." && name != "..",
+            FsError::InvalidPath(InvalidPathReason::RelativeSegment(name))
+        );
+        ensure!(
+            !name.contains('/'),
+            FsError::InvalidPath(InvalidPathReason::ContainsSlash(name))
+        );
+
+        Ok(name)
+    }
+
+    /// The partition of an absolute path, or `None` for relative paths.
+    pub fn partition(&self) -> Option<PartitionPrefix> {
+        self.partition
+    }
+
+    /// Gets the names without the partition prefix.
+    pub fn relative_segments(&self) -> &[String] {
+        match self.partition {
+            Some(_) => &self.segments[1..],
+            None => &self.segments,
+        }
+    }
+
+    /// Gets the path without the partition prefix, like it's used within a directory.
+    ///
+    /// Fails for partition roots, since they have no names below the prefix.
+    pub fn to_relative(&self) -> Result<Self> {
+        Self::relative(self.relative_segments())
+    }
+
+    /// Gets the last name of this path, if it's not a partition root.
+    pub fn name(&self) -> Option<&str> {
+        self.relative_segments().last().map(String::as_str)
+    }
+
+    /// Gets the path of the parent directory, if there is one.
+    pub fn parent(&self) -> Option<Self> {
+        let (_, rest) = self.relative_segments().split_last()?;
+        if self.partition.is_none() && rest.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            partition: self.partition,
+            segments: self.segments[..self.segments.len() - 1].to_vec(),
+        })
+    }
+
+    /// Creates the path of a child with given name.
+    pub fn join(&self, name: &str) -> Result<Self> {
+        let mut segments = self.segments.clone();
+        segments.push(Self::normalize_name(name)?);
+
+        Ok(Self {
+            partition: self.partition,
+            segments,
+        })
+    }
+}
+
+impl Deref for WnfsPath {
+    type Target = [String];
+
+    fn deref(&self) -> &Self::Target {
+        &self.segments
+    }
+}
+
+impl AsRef<[String]> for WnfsPath {
+    fn as_ref(&self) -> &[String] {
+        &self.segments
+    }
+}
+
+impl From<WnfsPath> for Vec<String> {
+    fn from(path: WnfsPath) -> Self {
+        path.segments
+    }
+}
+
+impl FromStr for WnfsPath {
+    type Err = anyhow::Error;
+
+    fn from_str(path: &str) -> Result<Self> {
+        Self::parse(path)
+    }
+}
+
+impl fmt::Display for WnfsPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.partition.is_some() {
+            write!(f, "/")?;
+        }
+
+        write!(f, "{}", self.segments.join("/"))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{
+        PrivateDirectory,
+        forest::{hamt::HamtForest, traits::PrivateForest},
+    };
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use wnfs_common::MemoryBlockStore;
+
+    fn rejection(path: &str) -> InvalidPathReason {
+        match WnfsPath::parse(path).unwrap_err().downcast() {
+            Ok(FsError::InvalidPath(reason)) => reason,
+            other => panic!("Expected an invalid path error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_and_normalizes_paths() {
+        let path = WnfsPath::parse("/private/docs/cafe\u{301}.md/").unwrap();
+        assert_eq!(path.partition(), Some(PartitionPrefix::Private));
+        assert_eq!(&*path, ["private", "docs", "caf\u{e9}.md"]);
+        assert_eq!(path.name(), Some("caf\u{e9}.md"));
+        assert_eq!(path.to_string(), "/private/docs/caf\u{e9}.md");
+        assert_eq!(path.parent().unwrap().to_string(), "/private/docs");
+
+        let relative = path.to_relative().unwrap();
+        assert_eq!(relative.partition(), None);
+        assert_eq!(relative, WnfsPath::parse("docs/caf\u{e9}.md").unwrap());
+        assert_eq!(relative.to_string(), "docs/caf\u{e9}.md");
+        assert_eq!(
+            relative.parent().unwrap().join("b").unwrap().to_string(),
+            "docs/b"
+        );
+        assert_eq!(relative.parent().unwrap().parent(), None);
+
+        let root = WnfsPath::parse("/exchange").unwrap();
+        assert_eq!(&*root, ["exchange"]);
+        assert_eq!(root.parent(), None);
+        assert!(root.to_relative().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        assert_eq!(rejection(""), InvalidPathReason::Empty);
+        assert_eq!(rejection("/"), InvalidPathReason::Empty);
+        assert_eq!(rejection("/public//a"), InvalidPathReason::EmptySegment);
+        assert_eq!(
+            rejection("/public/a/../b"),
+            InvalidPathReason::RelativeSegment("..".into())
+        );
+        assert_eq!(
+            rejection("./a"),
+            InvalidPathReason::RelativeSegment(".".into())
+        );
+        assert_eq!(
+            rejection("/home/a"),
+            InvalidPathReason::UnknownPartition("home".into())
+        );
+
+        let error = WnfsPath::new(PartitionPrefix::Public, ["a/b"]).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(FsError::InvalidPath(InvalidPathReason::ContainsSlash(name))) if name == "a/b"
+        ));
+    }
+
+    #[async_std::test]
+    async fn private_directories_accept_paths() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let mut dir = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let path = WnfsPath::parse("docs/cafe\u{301}.md").unwrap();
+        dir.write(
+            &path,
+            true,
+            Utc::now(),
+            b"menu".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        let names = dir
+            .ls(&path.parent().unwrap(), true, forest, store)
+            .await
+            .unwrap();
+        assert_eq!(names[0].0, "caf\u{e9}.md");
+        let content = dir
+            .read(&["docs".into(), "caf\u{e9}.md".into()], true, forest, store)
+            .await
+            .unwrap();
+        assert_eq!(content, b"menu");
+    }
+}
@@ -0,0 +1,346 @@
+use super::{PrivateDirectory, PrivateFile, PrivateNode, forest::traits::PrivateForest};
+use crate::{
+    diff::{
+        DirectoryIdentity, MoveDetector, PathChange, PathChangeType, metadata_eq_ignoring_mtime,
+    },
+    find::NodeKind,
+};
+use anyhow::Result;
+use async_recursion::async_recursion;
+use async_stream::try_stream;
+use futures::TryStreamExt;
+use std::collections::BTreeSet;
+use wnfs_common::{
+    BlockStore,
+    utils::{Arc, BoxStream, boxed_stream},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Computes the path-level changes between two private directory revisions.
+///
+/// Entries whose content CIDs are equal in both revisions are skipped without being decrypted,
+/// so only the subtrees that actually changed are loaded from the forest.
+/// Both revisions need to be decryptable with the given forest.
+///
+/// Content and metadata modifications are yielded while walking both trees.
+/// Additions, removals and moves are yielded at the end, since a move can only be detected
+/// once both its source and its destination have been found.
+/// A node counts as moved if it was removed from one path and a node of the same kind and
+/// content was added at another path.
+/// Private nodes get re-encrypted when they're moved, so added and removed nodes are
+/// identified by their decrypted content, which means decrypting their whole subtrees.
+/// Files are compared by their decrypted content as well, so rewriting a file with the
+/// same bytes isn't reported as a change to its content.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use futures::TryStreamExt;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     diff::PathChangeType,
+///     private::{
+///         self, PrivateDirectory,
+///         forest::{hamt::HamtForest, traits::PrivateForest},
+///     },
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_trusted_rc(rng);
+///
+///     let old = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     old.mkdir(&["photos".into()], true, Utc::now(), forest, store, rng).await?;
+///     old.as_node().store(forest, store, rng).await?;
+///
+///     let new = &mut old.clone();
+///     new.write(&["notes.md".into()], true, Utc::now(), b"hi".to_vec(), forest, store, rng)
+///         .await?;
+///
+///     let changes = private::diff(old, new, forest, store).try_collect::<Vec<_>>().await?;
+///
+///     assert_eq!(changes.len(), 1);
+///     assert_eq!(changes[0].path, vec!["notes.md".to_string()]);
+///     assert_eq!(changes[0].r#type, PathChangeType::Added);
+///
+///     Ok(())
+/// }
+/// ```
+pub fn diff<'a>(
+    old: &Arc<PrivateDirectory>,
+    new: &Arc<PrivateDirectory>,
+    forest: &'a impl PrivateForest,
+    store: &'a impl BlockStore,
+) -> BoxStream<'a, Result<PathChange>> {
+    let old = Arc::clone(old);
+    let new = Arc::clone(new);
+
+    boxed_stream(try_stream! {
+        let mut moves = MoveDetector::default();
+        let mut stack = vec![(Vec::new(), old, new)];
+
+        while let Some((path, old, new)) = stack.pop() {
+            let names = old
+                .content
+                .entries
+                .keys()
+                .chain(new.content.entries.keys())
+                .collect::<BTreeSet<_>>();
+
+            let old_name = Some(old.header.name.clone());
+            let new_name = Some(new.header.name.clone());
+
+            let mut subdirs = Vec::new();
+            for name in names {
+                let mut child_path = path.clone();
+                child_path.push(name.clone());
+
+                match (old.content.entries.get(name), new.content.entries.get(name)) {
+                    (Some(old_link), None) => {
+                        let node = old_link.resolve_node(forest, store, old_name.clone()).await?;
+                        let (kind, identity) = content_identity(node, forest, store).await?;
+                        moves.removed(child_path, kind, identity);
+                    }
+                    (None, Some(new_link)) => {
+                        let node = new_link.resolve_node(forest, store, new_name.clone()).await?;
+                        let (kind, identity) = content_identity(node, forest, store).await?;
+                        moves.added(child_path, kind, identity);
+                    }
+                    (Some(old_link), Some(new_link)) => {
+                        let old_cid = old_link.get_content_cid();
+                        if old_cid.is_some() && old_cid == new_link.get_content_cid() {
+                            continue;
+                        }
+
+                        let old_node = old_link.resolve_node(forest, store, old_name.clone()).await?;
+                        let new_node = new_link.resolve_node(forest, store, new_name.clone()).await?;
+
+                        match (old_node, new_node) {
+                            (PrivateNode::File(old_file), PrivateNode::File(new_file)) => {
+                                let content_modified = old_file.content.content != new_file.content.content
+                                    && content_hash(old_file, forest, store).await?
+                                        != content_hash(new_file, forest, store).await?;
+                                let r#type = if content_modified {
+                                    PathChangeType::ContentModified
+                                } else if old_file.content.metadata != new_file.content.metadata {
+                                    PathChangeType::MetadataModified
+                                } else {
+                                    continue;
+                                };
+
+                                yield PathChange { r#type, path: child_path, kind: NodeKind::File };
+                            }
+                            (PrivateNode::Dir(old_dir), PrivateNode::Dir(new_dir)) => {
+                                if !metadata_eq_ignoring_mtime(&old_dir.content.metadata, &new_dir.content.metadata) {
+                                    yield PathChange {
+                                        r#type: PathChangeType::MetadataModified,
+                                        path: child_path.clone(),
+                                        kind: NodeKind::Directory,
+                                    };
+                                }
+
+                                subdirs.push((child_path, Arc::clone(old_dir), Arc::clone(new_dir)));
+                            }
+                            (old_node, new_node) => {
+                                let (kind, identity) = content_identity(old_node, forest, store).await?;
+                                moves.removed(child_path.clone(), kind, identity);
+                                let (kind, identity) = content_identity(new_node, forest, store).await?;
+                                moves.added(child_path, kind, identity);
+                            }
+                        }
+                    }
+                    (None, None) => unreachable!(),
+                }
+            }
+
+            stack.extend(subdirs.into_iter().rev());
+        }
+
+        for change in moves.finish() {
+            yield change;
+        }
+    })
+}
+
+/// Returns the kind of a node together with bytes that are equal for nodes of equal content.
+///
+/// Files are identified by a hash of their decrypted content, directories by the names, kinds
+/// and identities of their entries. Neither depends on how or whether the nodes were stored.
+#[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+#[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+async fn content_identity(
+    node: &PrivateNode,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<(NodeKind, Vec<u8>)> {
+    Ok(match node {
+        PrivateNode::File(file) => (NodeKind::File, content_hash(file, forest, store).await?),
+        PrivateNode::Dir(dir) => {
+            let mut identity = DirectoryIdentity::default();
+            for (name, link) in dir.content.entries.iter() {
+                let child = link
+                    .resolve_node(forest, store, Some(dir.header.name.clone()))
+                    .await?;
+                let (kind, entry) = content_identity(child, forest, store).await?;
+                identity.add_entry(name, kind, &entry);
+            }
+
+            (NodeKind::Directory, identity.finish())
+        }
+    })
+}
+
+/// Hashes the decrypted content of a file.
+async fn content_hash(
+    file: &PrivateFile,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<Vec<u8>> {
+    let mut hasher = blake3::Hasher::new();
+    let mut blocks = file.stream_content(0, forest, store);
+    while let Some(block) = blocks.try_next().await? {
+        hasher.update(&block);
+    }
+
+    Ok(hasher.finalize().as_bytes().to_vec())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use chrono::Utc;
+    use futures::TryStreamExt;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn diff_reports_path_changes() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let time = Utc::now();
+        let old = &mut PrivateDirectory::new_rc(&forest.empty_name(), time, rng);
+
+        for (path, content) in [
+            (vec!["docs", "a.md"], "a"),
+            (vec!["docs", "b.md"], "b"),
+            (vec!["untouched", "c.md"], "c"),
+            (vec!["old.txt"], "old"),
+        ] {
+            let path = path.into_iter().map(String::from).collect::<Vec<_>>();
+            old.write(
+                &path,
+                true,
+                time,
+                content.as_bytes().to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        }
+        old.as_node().store(forest, store, rng).await?;
+
+        let new = &mut old.clone();
+        new.write(
+            &["docs".into(), "a.md".into()],
+            true,
+            time,
+            b"A".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        new.basic_mv(
+            &["untouched".into()],
+            &["moved".into()],
+            true,
+            time,
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        new.basic_mv(
+            &["docs".into(), "b.md".into()],
+            &["b.md".into()],
+            true,
+            time,
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        new.rm(&["old.txt".into()], true, forest, store).await?;
+        new.as_node().store(forest, store, rng).await?;
+
+        let changes = diff(old, new, forest, store)
+            .map_ok(|change| (change.path.join("/"), change.r#type))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        assert_eq!(
+            changes,
+            vec![
+                ("docs/a.md".into(), PathChangeType::ContentModified),
+                (
+                    "b.md".into(),
+                    PathChangeType::Moved {
+                        from: vec!["docs".into(), "b.md".into()]
+                    }
+                ),
+                (
+                    "moved".into(),
+                    PathChangeType::Moved {
+                        from: vec!["untouched".into()]
+                    }
+                ),
+                ("old.txt".into(), PathChangeType::Removed),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn rewriting_the_same_content_is_not_a_change() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let time = Utc::now();
+        let path = ["notes.md".to_string()];
+        let old = &mut PrivateDirectory::new_rc(&forest.empty_name(), time, rng);
+        old.write(&path, true, time, b"notes".to_vec(), forest, store, rng)
+            .await?;
+        old.as_node().store(forest, store, rng).await?;
+
+        let new = &mut old.clone();
+        new.write(&path, true, time, b"notes".to_vec(), forest, store, rng)
+            .await?;
+        new.as_node().store(forest, store, rng).await?;
+
+        let changes = diff(old, new, forest, store)
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(changes, []);
+
+        Ok(())
+    }
+}
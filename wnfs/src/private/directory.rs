@@ -1,21 +1,28 @@
 use super::{
     PrivateDirectoryContentSerializable, PrivateFile, PrivateNode, PrivateNodeContentSerializable,
-    PrivateNodeHeader, PrivateRef, TemporalKey, encrypted::Encrypted,
-    forest::traits::PrivateForest, link::PrivateLink,
+    PrivateNodeHeader, PrivateNodeOnPathHistory, PrivateRef, TemporalKey, encrypted::Encrypted,
+    entries::PrivateDirectoryEntries, forest::traits::PrivateForest, link::PrivateLink,
 };
-use crate::{SearchResult, WNFS_VERSION, error::FsError, is_readable_wnfs_version, traits::Id};
-use anyhow::{Result, bail, ensure};
+use crate::{
+    RevisionSelector, SearchResult, WNFS_VERSION,
+    conflict::{Conflict, ConflictPolicy, ConflictResolution, Replica, conflict_copy_name},
+    error::FsError,
+    is_readable_wnfs_version,
+    merge::MergeDriver,
+    traits::Id,
+    utils::modified_at_or_before,
+    walk::{WalkEntry, WalkOptions, walk_tree},
+};
+use anyhow::{Result, anyhow, bail, ensure};
 use async_once_cell::OnceCell;
+use async_recursion::async_recursion;
 use chrono::{DateTime, Utc};
+use futures::{StreamExt, TryStreamExt, stream};
 use rand_core::CryptoRngCore;
-use std::{
-    cmp::Ordering,
-    collections::{BTreeMap, BTreeSet, btree_map::Entry},
-    fmt::Debug,
-};
+use std::{cmp::Ordering, collections::BTreeSet, fmt::Debug};
 use wnfs_common::{
     BlockStore, CODEC_RAW, Cid, Metadata, PathNodes, PathNodesResult,
-    utils::{Arc, CondSend, error},
+    utils::{Arc, BoxStream, CondSend, boxed_fut, error},
 };
 use wnfs_nameaccumulator::{Name, NameSegment};
 
@@ -52,7 +59,7 @@ pub(crate) struct PrivateDirectoryContent {
     pub(crate) persisted_as: OnceCell<Cid>,
     pub(crate) previous: BTreeSet<(usize, Encrypted<Cid>)>,
     pub(crate) metadata: Metadata,
-    pub(crate) entries: BTreeMap<String, PrivateLink>,
+    pub(crate) entries: PrivateDirectoryEntries,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -91,7 +98,7 @@ impl PrivateDirectory {
                 persisted_as: OnceCell::new(),
                 previous: BTreeSet::new(),
                 metadata: Metadata::new(time),
-                entries: BTreeMap::new(),
+                entries: PrivateDirectoryEntries::new(),
             },
         }
     }
@@ -214,6 +221,11 @@ impl PrivateDirectory {
         &self.content.metadata
     }
 
+    /// Returns a mutable reference to this directory's metadata.
+    pub fn get_metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.content.metadata
+    }
+
     /// Looks up a node by its path name in the current directory.
     ///
     /// # Examples
@@ -375,12 +387,12 @@ impl PrivateDirectory {
             SearchResult::Found(dir) => Ok(dir),
             SearchResult::Missing(mut dir, depth) => {
                 for segment in &path_segments[depth..] {
+                    let parent_name = dir.header.name.clone();
                     dir = Arc::make_mut(
                         dir.content
                             .entries
-                            .entry(segment.to_string())
-                            .or_insert_with(|| {
-                                PrivateLink::with_dir(Self::new(&dir.header.name, time, rng))
+                            .get_or_insert_with(segment.to_string(), || {
+                                PrivateLink::with_dir(Self::new(&parent_name, time, rng))
                             })
                             .resolve_node_mut(forest, store, Some(dir.header.name.clone()))
                             .await
@@ -481,6 +493,47 @@ impl PrivateDirectory {
         self.header.update_name(parent_name);
         self.header.reset_ratchet(rng);
         self.content.persisted_as = OnceCell::new();
+        self.content.entries.reset_shards();
+    }
+
+    /// Gives the node at given path and all of its descendants new keys, keeping it in place.
+    ///
+    /// Each node in the subtree is prepared for key rotation like when it's moved,
+    /// so none of their future revisions can be read with access keys to their current ones.
+    pub(crate) async fn rotate_keys(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        time: DateTime<Utc>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        let (path, node_name) = crate::utils::split_last(path_segments)?;
+        let SearchResult::Found(dir) = self
+            .get_leaf_dir_mut(path, search_latest, forest, store)
+            .await?
+        else {
+            bail!(FsError::NotFound)
+        };
+
+        let mut node = match dir.content.entries.remove(node_name) {
+            Some(link) => {
+                link.resolve_owned_node(forest, store, Some(dir.header.name.clone()))
+                    .await?
+            }
+            None => bail!(FsError::NotFound),
+        };
+
+        node.upsert_mtime(time);
+        node.update_ancestry(&dir.header.name, forest, store, rng)
+            .await?;
+
+        dir.content
+            .entries
+            .insert(node_name.clone(), PrivateLink::from(node));
+
+        Ok(())
     }
 
     /// Follows a path and fetches the node at the end of the path.
@@ -859,6 +912,190 @@ impl PrivateDirectory {
             .as_dir()
     }
 
+    /// Like `search_latest_reconciled`, but resolves conflicts between concurrent writes
+    /// with given policy and reports which entries had conflicting writes.
+    ///
+    /// See [`PrivateNode::search_latest_reconciled_with`] for details.
+    pub async fn search_latest_reconciled_with(
+        self: Arc<Self>,
+        policy: &impl ConflictPolicy<PrivateNode>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<(Arc<Self>, Vec<Conflict<PrivateNode>>)> {
+        let (node, conflicts) = PrivateNode::Dir(self)
+            .search_latest_reconciled_with(policy, forest, store)
+            .await?;
+        Ok((node.as_dir()?, conflicts))
+    }
+
+    /// Reconciles this directory and everything below it with concurrent writes,
+    /// merging the contents of concurrently written files with given merge driver.
+    ///
+    /// `past` must be an earlier revision of this directory, e.g. the one this replica
+    /// last synchronized. Common ancestors of concurrent writes are looked up in the history
+    /// between `past` and the latest revision, see
+    /// [`PrivateNode::search_latest_reconciled_merging`]. Subdirectories that didn't change
+    /// since `past` are skipped.
+    ///
+    /// Returns the conflicts the driver couldn't merge, which were resolved with given policy.
+    /// Their paths are relative to this directory. The merged tree still needs to be stored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     conflict::TieBreakPolicy,
+    ///     merge::LineMergeDriver,
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let path = &["todo.txt".into()];
+    ///
+    ///     let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     root.write(path, true, Utc::now(), b"milk\neggs\n".to_vec(), forest, store, rng).await?;
+    ///     root.as_node().store(forest, store, rng).await?;
+    ///     let past = root.clone();
+    ///
+    ///     // Two replicas write to the same file concurrently
+    ///     let theirs = &mut root.clone();
+    ///     let their_forest = &mut forest.clone();
+    ///     root.write(path, true, Utc::now(), b"oat milk\neggs\n".to_vec(), forest, store, rng).await?;
+    ///     root.as_node().store(forest, store, rng).await?;
+    ///     theirs.write(path, true, Utc::now(), b"milk\neggs\nbread\n".to_vec(), their_forest, store, rng).await?;
+    ///     theirs.as_node().store(their_forest, store, rng).await?;
+    ///     *forest = std::sync::Arc::new(forest.merge(their_forest, store).await?);
+    ///
+    ///     let conflicts = root
+    ///         .reconcile_merging(&past, &TieBreakPolicy, &LineMergeDriver, forest, store)
+    ///         .await?;
+    ///
+    ///     assert!(conflicts.is_empty());
+    ///     assert_eq!(root.read(path, false, forest, store).await?, b"oat milk\neggs\nbread\n");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn reconcile_merging(
+        self: &mut Arc<Self>,
+        past: &Arc<Self>,
+        policy: &impl ConflictPolicy<PrivateNode>,
+        driver: &impl MergeDriver,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<Conflict<PrivateNode>>> {
+        let mut conflicts = Vec::new();
+        self.reconcile_merging_helper(past, policy, driver, &[], &mut conflicts, forest, store)
+            .await?;
+        Ok(conflicts)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+    #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+    async fn reconcile_merging_helper<'a>(
+        self: &'a mut Arc<Self>,
+        past: &'a Arc<Self>,
+        policy: &'a impl ConflictPolicy<PrivateNode>,
+        driver: &'a impl MergeDriver,
+        current_path: &'a [String],
+        conflicts: &'a mut Vec<Conflict<PrivateNode>>,
+        forest: &'a mut impl PrivateForest,
+        store: &'a impl BlockStore,
+    ) -> Result<()> {
+        let prefixed = |path: &[String], conflict: Conflict<PrivateNode>| Conflict {
+            path: [path, &conflict.path].concat(),
+            copy: conflict.copy.map(|copy| [path, &copy].concat()),
+            ..conflict
+        };
+
+        let (node, dir_conflicts) = self
+            .as_node()
+            .search_latest_reconciled_merging(&past.as_node(), policy, driver, forest, store)
+            .await?;
+        *self = node.as_dir()?;
+        conflicts.extend(
+            dir_conflicts
+                .into_iter()
+                .map(|conflict| prefixed(current_path, conflict)),
+        );
+
+        let parent_name = Some(self.header.name.clone());
+        let past_parent_name = Some(past.header.name.clone());
+        let mut updates = Vec::new();
+        for (name, link) in self.content.entries.iter() {
+            let past_link = past.content.entries.get(name);
+            if let Some(content_cid) = link.get_content_cid() {
+                if past_link.and_then(PrivateLink::get_content_cid) == Some(content_cid) {
+                    continue;
+                }
+            }
+
+            let node = link
+                .resolve_node(forest, store, parent_name.clone())
+                .await?;
+            let past_node = match past_link {
+                Some(past_link) => Some(
+                    past_link
+                        .resolve_node(forest, store, past_parent_name.clone())
+                        .await?,
+                ),
+                None => None,
+            }
+            .filter(|past_node| past_node.get_header().inumber == node.get_header().inumber)
+            .unwrap_or(node)
+            .clone();
+
+            let path = [current_path, std::slice::from_ref(name)].concat();
+            let reconciled = match (node, &past_node) {
+                (PrivateNode::Dir(dir), PrivateNode::Dir(past_dir)) => {
+                    let mut dir = Arc::clone(dir);
+                    dir.reconcile_merging_helper(
+                        past_dir, policy, driver, &path, conflicts, forest, store,
+                    )
+                    .await?;
+                    PrivateNode::Dir(dir)
+                }
+                (node, past_node) => {
+                    let driver = |_: &[String], base: Option<&[u8]>, ours: &[u8], theirs: &[u8]| {
+                        driver.merge(&path, base, ours, theirs)
+                    };
+                    let (reconciled, file_conflicts) = node
+                        .search_latest_reconciled_merging(past_node, policy, &driver, forest, store)
+                        .await?;
+                    conflicts.extend(
+                        file_conflicts
+                            .into_iter()
+                            .map(|conflict| prefixed(&path, conflict)),
+                    );
+                    reconciled
+                }
+            };
+
+            if reconciled != *node {
+                updates.push((name.clone(), reconciled));
+            }
+        }
+
+        if !updates.is_empty() {
+            let dir = self.prepare_next_revision()?;
+            for (name, node) in updates {
+                dir.content.entries.insert(name, PrivateLink::from(node));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Creates a new directory at the specified path.
     ///
     /// # Examples
@@ -993,12 +1230,137 @@ impl PrivateDirectory {
         }
     }
 
+    /// Recursively walks the directory at given path, returning a stream of all
+    /// nodes below it together with their paths and metadata.
+    ///
+    /// Yielded paths include `path_segments`. Children of each directory are fetched and
+    /// decrypted concurrently, see [`WalkOptions`] for configuring this as well as the
+    /// traversal order, depth limits and pruning.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use futures::TryStreamExt;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    ///     walk::{WalkOptions, WalkOrder},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     root_dir
+    ///         .write(
+    ///             &["code".into(), "src".into(), "hello.py".into()],
+    ///             true,
+    ///             Utc::now(),
+    ///             b"print('hello world')".to_vec(),
+    ///             forest,
+    ///             store,
+    ///             rng
+    ///         )
+    ///         .await?;
+    ///
+    ///     root_dir
+    ///         .mkdir(&["code".into(), "bin".into()], true, Utc::now(), forest, store, rng)
+    ///         .await?;
+    ///
+    ///     let options = WalkOptions::new().order(WalkOrder::BreadthFirst);
+    ///     let paths = root_dir
+    ///         .walk(&["code".into()], true, options, forest, store)
+    ///         .await?
+    ///         .map_ok(|(path, _, _)| path.join("/"))
+    ///         .try_collect::<Vec<_>>()
+    ///         .await?;
+    ///
+    ///     assert_eq!(paths, ["code/bin", "code/src", "code/src/hello.py"]);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn walk<'a>(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        options: WalkOptions,
+        forest: &'a impl PrivateForest,
+        store: &'a impl BlockStore,
+    ) -> Result<BoxStream<'a, Result<WalkEntry<PrivateNode>>>> {
+        let root = match self
+            .get_leaf_dir(path_segments, search_latest, forest, store)
+            .await?
+        {
+            SearchResult::Found(dir) => dir,
+            SearchResult::NotADir(_, _) => bail!(FsError::NotADirectory),
+            _ => bail!(FsError::NotFound),
+        };
+
+        Ok(Self::walk_from(
+            path_segments.to_vec(),
+            root,
+            options,
+            forest,
+            store,
+        ))
+    }
+
+    /// Walks the subtree of `root`, yielding paths prefixed with `prefix`.
+    pub(crate) fn walk_from<'a>(
+        prefix: Vec<String>,
+        root: Arc<Self>,
+        options: WalkOptions,
+        forest: &'a impl PrivateForest,
+        store: &'a impl BlockStore,
+    ) -> BoxStream<'a, Result<WalkEntry<PrivateNode>>> {
+        walk_tree(
+            prefix,
+            PrivateNode::Dir(root),
+            options,
+            move |node, concurrency| {
+                boxed_fut(async move {
+                    let PrivateNode::Dir(dir) = node else {
+                        return Ok(Vec::new());
+                    };
+
+                    let entries = dir
+                        .content
+                        .entries
+                        .iter()
+                        .map(|(name, link)| (name.clone(), link.clone()))
+                        .collect::<Vec<_>>();
+                    let parent_name = &dir.header.name;
+
+                    stream::iter(entries)
+                        .map(|(name, link)| async move {
+                            let node = link
+                                .resolve_owned_node(forest, store, Some(parent_name.clone()))
+                                .await?;
+                            let metadata = node.get_metadata().clone();
+                            Ok((name, node, metadata))
+                        })
+                        .buffered(concurrency)
+                        .try_collect()
+                        .await
+                })
+            },
+        )
+    }
+
     /// Get the names of directory's immediate children.
     ///
     /// Other than [PrivateDirectory::ls] this returns only the names, without loading the
     /// metadata for each node from the store.
     pub fn get_entries(self: &Arc<Self>) -> impl Iterator<Item = &String> {
-        self.content.entries.iter().map(|x| x.0)
+        self.content.entries.keys()
     }
 
     /// Removes a file or directory from the directory.
@@ -1087,7 +1449,7 @@ impl PrivateDirectory {
     ///
     /// Fixes up the subtree bare names to refer to the new parent.
     #[allow(clippy::too_many_arguments)]
-    async fn attach(
+    pub(crate) async fn attach(
         self: &mut Arc<Self>,
         mut node: PrivateNode,
         path_segments: &[String],
@@ -1292,6 +1654,136 @@ impl PrivateDirectory {
         .await
     }
 
+    /// Restores the file at given path to an earlier revision, e.g. one retrieved from
+    /// a [`PrivateNodeOnPathHistory`](super::PrivateNodeOnPathHistory).
+    ///
+    /// This doesn't rewind history: The restored content and metadata are written as a
+    /// new revision on top of the current one, so the restore itself can be undone.
+    ///
+    /// Only files can be restored. Directories link to specific revisions of their children,
+    /// which would be skipped over again by the next lookup that searches for the latest revision.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn restore(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        revision: &PrivateNode,
+        search_latest: bool,
+        time: DateTime<Utc>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let (path, filename) = crate::utils::split_last(path_segments)?;
+        let revision = revision.as_file()?;
+        let dir = match self
+            .get_leaf_dir_mut(path, search_latest, forest, store)
+            .await?
+        {
+            SearchResult::Found(dir) => dir,
+            SearchResult::Missing(_, _) => bail!(FsError::NotFound),
+            SearchResult::NotADir(_, _) => bail!(FsError::NotADirectory),
+        };
+
+        match dir
+            .lookup_node_mut(filename, search_latest, forest, store)
+            .await?
+        {
+            Some(PrivateNode::File(file)) => file.restore_from(&revision, time),
+            Some(PrivateNode::Dir(_)) => bail!(FsError::NotAFile),
+            None => bail!(FsError::NotFound),
+        }
+    }
+
+    /// Restores the file at given path to the revision picked by `revision`,
+    /// so e.g. `RevisionSelector::Back(1)` undoes the last change to the file.
+    ///
+    /// Private history can only be followed back as far as a revision whose keys are known,
+    /// so `past` needs to be an earlier revision of this directory. It bounds how far back
+    /// the history is searched, and `discrepancy_budget` bounds how many revisions
+    /// may lie in between.
+    ///
+    /// The latest revision of the file is restored on top of, just like with [`restore`](Self::restore).
+    /// Fails with `FsError::RevisionNotFound` if the history doesn't go back far enough.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     RevisionSelector,
+    ///     common::MemoryBlockStore,
+    ///     private::{
+    ///         PrivateDirectory,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_trusted_rc(rng);
+    ///     let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let path = &["notes.md".into()];
+    ///
+    ///     root.write(path, true, Utc::now(), b"draft".to_vec(), forest, store, rng).await?;
+    ///     root.as_node().store(forest, store, rng).await?;
+    ///     let past = root.clone();
+    ///
+    ///     root.write(path, true, Utc::now(), b"oops".to_vec(), forest, store, rng).await?;
+    ///     root.as_node().store(forest, store, rng).await?;
+    ///
+    ///     root.restore_revision(path, RevisionSelector::Back(1), &past, 1_000, Utc::now(), forest, store)
+    ///         .await?;
+    ///
+    ///     assert_eq!(root.read(path, true, forest, store).await?, b"draft");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub async fn restore_revision(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        revision: RevisionSelector,
+        past: &Arc<Self>,
+        discrepancy_budget: usize,
+        time: DateTime<Utc>,
+        forest: &(impl PrivateForest + Clone),
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let current = self
+            .get_node(path_segments, true, forest, store)
+            .await?
+            .ok_or(FsError::NotFound)?;
+
+        let history = PrivateNodeOnPathHistory::of(
+            Arc::clone(self),
+            Arc::clone(past),
+            discrepancy_budget,
+            path_segments,
+            true,
+            forest.clone(),
+            store,
+        )
+        .await?;
+
+        let old = match revision {
+            RevisionSelector::Back(0) => Some(current),
+            RevisionSelector::Back(n) => history.nth_previous(n - 1, store).await?,
+            RevisionSelector::AsOf(time) if modified_at_or_before(current.get_metadata(), time) => {
+                Some(current)
+            }
+            RevisionSelector::AsOf(time) => history.previous_as_of(time, store).await?,
+        }
+        .ok_or(FsError::RevisionNotFound)?;
+
+        self.restore(path_segments, &old, true, time, forest, store)
+            .await
+    }
+
     /// Stores this PrivateDirectory in the PrivateForest.
     pub(crate) async fn store(
         &self,
@@ -1309,11 +1801,7 @@ impl PrivateDirectory {
             .await?;
 
         forest
-            .put_encrypted(
-                &name_with_revision,
-                [header_cid, content_cid].into_iter(),
-                store,
-            )
+            .put_encrypted(&name_with_revision, [header_cid, content_cid], store)
             .await?;
 
         Ok(self
@@ -1335,18 +1823,19 @@ impl PrivateDirectory {
             bail!(FsError::UnexpectedVersion(serializable.version));
         }
 
-        let mut entries_decrypted = BTreeMap::new();
-        for (name, private_ref_serializable) in serializable.entries {
-            let private_ref =
-                PrivateRef::from_serializable(private_ref_serializable, temporal_key)?;
-            entries_decrypted.insert(name, PrivateLink::from_ref(private_ref));
-        }
+        let entries = PrivateDirectoryEntries::from_serializable(
+            serializable.entries,
+            serializable.shards,
+            temporal_key,
+            store,
+        )
+        .await?;
 
         let content = PrivateDirectoryContent {
             persisted_as: OnceCell::new_with(cid),
             metadata: serializable.metadata,
             previous: serializable.previous.into_iter().collect(),
-            entries: entries_decrypted,
+            entries,
         };
 
         let header = PrivateNodeHeader::load(
@@ -1372,15 +1861,23 @@ impl PrivateDirectory {
     /// on every level.
     /// Every directory should have a corresponding "identity directory" which is the
     /// empty directory, which when merged, results in no change.
-    /// This function is both commutative and associative.
-    /// If there's a conflict, it prefers keeping the directory, then tie-breaks on
-    /// the private ref.
+    /// With [`TieBreakPolicy`](crate::conflict::TieBreakPolicy), this function is both
+    /// commutative and associative.
+    /// If there's a conflict, it asks given policy how to resolve it. Tie-breaking prefers
+    /// keeping the directory, then tie-breaks on the private ref.
+    /// Two revisions of the same child directory never conflict,
+    /// since they are reconciled on their own level.
+    /// The same goes for two revisions of the same file if `defer_file_merges` is set.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn merge(
         self: &mut Arc<Self>,
         target_header: PrivateNodeHeader,
         our_cid: Cid,
         other: &Arc<Self>,
         other_cid: Cid,
+        policy: &impl ConflictPolicy<PrivateNode>,
+        defer_file_merges: bool,
+        conflicts: &mut Vec<Conflict<PrivateNode>>,
         forest: &impl PrivateForest,
         store: &impl BlockStore,
     ) -> Result<()> {
@@ -1417,53 +1914,103 @@ impl PrivateDirectory {
             .metadata
             .tie_break_with(&other.content.metadata)?;
 
+        let mut copies = Vec::new();
         for (name, other_link) in other.content.entries.iter() {
-            match our.content.entries.entry(name.clone()) {
-                Entry::Vacant(vacant) => {
-                    vacant.insert(other_link.clone());
-                }
-                Entry::Occupied(mut occupied) => {
-                    let our_link = occupied.get_mut();
-
-                    // We just tie-break on the content cid.
-                    // It's assumed both links have been resolved to their
-                    // PrivateRef before, and we can tie-break on their content_cid.
-                    // Otherwise, how would we have gotten `our_cid` and `other_cid`
-                    // in this context? Both of these were gotten from `.store()`ing the
-                    // nodes, which includes resolving the children to `PrivateRef`s.
-                    let our_content_hash = our_link.crdt_tiebreaker()?;
-                    let other_content_hash = other_link.crdt_tiebreaker()?;
-
-                    let ord = our_content_hash.cmp(&other_content_hash);
-                    if ord == Ordering::Equal {
-                        // there's nothing for us to do, they're equal
-                    } else {
-                        let our_node = our_link
-                            .resolve_node_mut(forest, store, parent_name.clone())
-                            .await?;
-
-                        let other_node = other_link
-                            .resolve_node(forest, store, parent_name.clone())
-                            .await?;
-
-                        match (our_node, other_node) {
-                            (PrivateNode::Dir(_), PrivateNode::File(_)) => {
-                                // our node wins, we don't need to do anything.
-                            }
-                            (PrivateNode::File(_), PrivateNode::Dir(_)) => {
-                                // a directory wins over a file
-                                our_link.clone_from(other_link);
-                            }
-                            // file vs. file and dir vs. dir cases
-                            _ => {
-                                // We tie-break as usual
-                                if ord == Ordering::Greater {
-                                    our_link.clone_from(other_link);
-                                }
-                            }
-                        }
-                    }
+            let Some(our_link) = our.content.entries.get_mut(name) else {
+                our.content.entries.insert(name.clone(), other_link.clone());
+                continue;
+            };
+
+            // We just tie-break on the content cid.
+            // It's assumed both links have been resolved to their
+            // PrivateRef before, and we can tie-break on their content_cid.
+            // Otherwise, how would we have gotten `our_cid` and `other_cid`
+            // in this context? Both of these were gotten from `.store()`ing the
+            // nodes, which includes resolving the children to `PrivateRef`s.
+            let our_content_hash = our_link.crdt_tiebreaker()?;
+            let other_content_hash = other_link.crdt_tiebreaker()?;
+
+            let ord = our_content_hash.cmp(&other_content_hash);
+            if ord == Ordering::Equal {
+                // there's nothing for us to do, they're equal
+                continue;
+            }
+
+            let our_node = our_link
+                .resolve_node(forest, store, parent_name.clone())
+                .await?
+                .clone();
+            let other_node = other_link
+                .resolve_node(forest, store, parent_name.clone())
+                .await?
+                .clone();
+
+            let tie_break_winner = match (&our_node, &other_node) {
+                // a directory wins over a file
+                (PrivateNode::Dir(_), PrivateNode::File(_)) => Replica::Ours,
+                (PrivateNode::File(_), PrivateNode::Dir(_)) => Replica::Theirs,
+                // file vs. file and dir vs. dir cases are tie-broken as usual
+                _ if ord == Ordering::Greater => Replica::Theirs,
+                _ => Replica::Ours,
+            };
+
+            let same_node = our_node.get_header().inumber == other_node.get_header().inumber;
+            let same_kind = our_node.is_dir() == other_node.is_dir();
+            if same_node && same_kind && (our_node.is_dir() || defer_file_merges) {
+                if tie_break_winner == Replica::Theirs {
+                    our_link.clone_from(other_link);
                 }
+                continue;
+            }
+
+            let path = vec![name.clone()];
+            let resolution = policy.resolve(&path, &our_node, &other_node);
+            let winner = match resolution {
+                ConflictResolution::KeepOurs => Replica::Ours,
+                ConflictResolution::KeepTheirs => Replica::Theirs,
+                ConflictResolution::TieBreak | ConflictResolution::KeepBoth => tie_break_winner,
+            };
+
+            let loser_link = match winner {
+                Replica::Ours => other_link.clone(),
+                Replica::Theirs => std::mem::replace(our_link, other_link.clone()),
+            };
+
+            let mut copy = None;
+            if resolution == ConflictResolution::KeepBoth {
+                let loser_cid = loser_link.get_content_cid().ok_or_else(|| {
+                    anyhow!("Impossible case: conflict copy of a node that wasn't persisted")
+                })?;
+                let copy_name = conflict_copy_name(name, loser_cid);
+                let copy_link = match (
+                    same_node,
+                    loser_link
+                        .resolve_node(forest, store, parent_name.clone())
+                        .await?,
+                ) {
+                    (true, PrivateNode::File(file)) => PrivateLink::from(PrivateNode::File(
+                        Arc::new(file.conflict_copy(&our.header.name, loser_cid).await?),
+                    )),
+                    _ => loser_link,
+                };
+
+                copy = Some(vec![copy_name.clone()]);
+                copies.push((copy_name, copy_link));
+            }
+
+            conflicts.push(Conflict {
+                path,
+                ours: our_node,
+                theirs: other_node,
+                resolution,
+                winner,
+                copy,
+            });
+        }
+
+        for (copy_name, copy_link) in copies {
+            if !our.content.entries.contains_key(&copy_name) {
+                our.content.entries.insert(copy_name, copy_link);
             }
         }
 
@@ -1498,15 +2045,10 @@ impl PrivateDirectoryContent {
         store: &impl BlockStore,
         rng: &mut (impl CryptoRngCore + CondSend),
     ) -> Result<Vec<u8>> {
-        let mut entries = BTreeMap::new();
-
-        for (name, private_link) in self.entries.iter() {
-            let private_ref_serializable = private_link
-                .resolve_ref(forest, store, rng)
-                .await?
-                .to_serializable(temporal_key)?;
-            entries.insert(name.clone(), private_ref_serializable);
-        }
+        let (entries, shards) = self
+            .entries
+            .to_serializable(temporal_key, forest, store, rng)
+            .await?;
 
         Ok(serde_ipld_dagcbor::to_vec(
             &PrivateNodeContentSerializable::Dir(PrivateDirectoryContentSerializable {
@@ -1515,6 +2057,7 @@ impl PrivateDirectoryContent {
                 header_cid,
                 metadata: self.metadata.clone(),
                 entries,
+                shards,
             }),
         )?)
     }
@@ -1596,9 +2139,14 @@ impl Id for PrivateDirectory {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::private::forest::hamt::HamtForest;
+    use crate::{
+        conflict::{ConflictCopiesPolicy, PreferReplicaPolicy, TieBreakPolicy},
+        merge::LineMergeDriver,
+        private::{PRIVATE_DIRECTORY_SHARDING_THRESHOLD, forest::hamt::HamtForest},
+    };
     use rand_chacha::ChaCha12Rng;
     use rand_core::SeedableRng;
+    use std::sync::Mutex;
     use test_log::test;
     use testresult::TestResult;
     use wnfs_common::MemoryBlockStore;
@@ -2551,6 +3099,300 @@ mod tests {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn test_conflict_reconciliation_keeps_conflict_copies() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut Arc::new(HamtForest::new_rsa_2048(rng));
+        let path = &["file.txt".to_string()];
+        let mut dir =
+            PrivateDirectory::new_and_store(&forest.empty_name(), Utc::now(), forest, store, rng)
+                .await?;
+
+        dir.write(path, true, Utc::now(), b"init".to_vec(), forest, store, rng)
+            .await?;
+        dir.store(forest, store, rng).await?;
+
+        // Another client works on a fork
+        let mut fork = Arc::clone(&dir);
+        let forest_fork = &mut Arc::clone(forest);
+
+        dir.write(
+            path,
+            true,
+            Utc::now(),
+            b"first".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        dir.store(forest, store, rng).await?;
+
+        // concurrent write
+        fork.write(
+            path,
+            true,
+            Utc::now(),
+            b"second".to_vec(),
+            forest_fork,
+            store,
+            rng,
+        )
+        .await?;
+        fork.store(forest_fork, store, rng).await?;
+
+        *forest = Arc::new(forest.merge(forest_fork, store).await?);
+
+        let (merged, conflicts) = Arc::clone(&dir)
+            .search_latest_reconciled_with(&ConflictCopiesPolicy, forest, store)
+            .await?;
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, path.to_vec());
+        assert_eq!(conflicts[0].resolution, ConflictResolution::KeepBoth);
+        let copy = conflicts[0].copy.clone().expect("Expected a conflict copy");
+
+        let mut contents = vec![
+            merged.read(path, false, forest, store).await?,
+            merged.read(&copy, false, forest, store).await?,
+        ];
+        contents.sort();
+        assert_eq!(contents, vec![b"first".to_vec(), b"second".to_vec()]);
+
+        let (_, conflicts) = Arc::clone(&dir)
+            .search_latest_reconciled_with(&PreferReplicaPolicy(Replica::Theirs), forest, store)
+            .await?;
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].winner, Replica::Theirs);
+        assert_eq!(conflicts[0].copy, None);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_reconcile_merging_merges_file_contents() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut Arc::new(HamtForest::new_rsa_2048(rng));
+        let notes = &["docs".to_string(), "notes.txt".to_string()];
+        let todo = &["docs".to_string(), "todo.txt".to_string()];
+        let mut dir =
+            PrivateDirectory::new_and_store(&forest.empty_name(), Utc::now(), forest, store, rng)
+                .await?;
+
+        for path in [notes, todo] {
+            dir.write(
+                path,
+                true,
+                Utc::now(),
+                b"a\nb\nc\n".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        }
+        dir.store(forest, store, rng).await?;
+        let past = Arc::clone(&dir);
+
+        // Another client works on a fork
+        let mut fork = Arc::clone(&dir);
+        let forest_fork = &mut Arc::clone(forest);
+
+        for (path, content) in [(notes, "A\nb\nc\n"), (todo, "a\nb\nmine\n")] {
+            dir.write(path, true, Utc::now(), content.into(), forest, store, rng)
+                .await?;
+        }
+        dir.store(forest, store, rng).await?;
+
+        for (path, content) in [(notes, "a\nb\nC\n"), (todo, "a\nb\nyours\n")] {
+            fork.write(
+                path,
+                true,
+                Utc::now(),
+                content.into(),
+                forest_fork,
+                store,
+                rng,
+            )
+            .await?;
+        }
+        fork.store(forest_fork, store, rng).await?;
+
+        *forest = Arc::new(forest.merge(forest_fork, store).await?);
+
+        let paths = Mutex::new(Vec::new());
+        let driver = |path: &[String], base: Option<&[u8]>, ours: &[u8], theirs: &[u8]| {
+            paths.lock().unwrap().push(path.to_vec());
+            LineMergeDriver.merge(path, base, ours, theirs)
+        };
+
+        let replica = &mut Arc::clone(&dir);
+        let replica_forest = &mut Arc::clone(forest);
+        let conflicts = dir
+            .reconcile_merging(&past, &TieBreakPolicy, &driver, forest, store)
+            .await?;
+        replica
+            .reconcile_merging(
+                &past,
+                &TieBreakPolicy,
+                &LineMergeDriver,
+                replica_forest,
+                store,
+            )
+            .await?;
+
+        let mut paths = paths.into_inner().unwrap();
+        paths.sort();
+        assert_eq!(paths, vec![notes.to_vec(), todo.to_vec()]);
+
+        assert_eq!(dir.read(notes, false, forest, store).await?, b"A\nb\nC\n");
+        let merged_notes = dir.get_node(notes, false, forest, store).await?.unwrap();
+        assert_eq!(merged_notes.get_previous().len(), 2);
+
+        // Replicas merging the same writes end up with the same content
+        let replica_notes = replica
+            .get_node(notes, false, replica_forest, store)
+            .await?
+            .unwrap();
+        assert_eq!(
+            merged_notes.as_file()?.content.content,
+            replica_notes.as_file()?.content.content
+        );
+
+        // Overlapping changes fall back to the conflict policy
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, todo.to_vec());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_conflict_reconciliation_merges_sharded_dirs() -> TestResult {
+        let rng = &mut ChaCha12Rng::from_entropy();
+        let store = &MemoryBlockStore::new();
+        let forest = &mut Arc::new(HamtForest::new_rsa_2048(rng));
+        let mut dir = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let file = PrivateFile::new(&dir.header.name, Utc::now(), rng);
+        let private_ref = file.store(forest, store, rng).await?;
+        for i in 0..PRIVATE_DIRECTORY_SHARDING_THRESHOLD {
+            Arc::make_mut(&mut dir).content.entries.insert(
+                format!("file_{i}.txt"),
+                PrivateLink::from_ref(private_ref.clone()),
+            );
+        }
+
+        dir.store(forest, store, rng).await?;
+
+        // Another client works on a fork
+        let mut fork = Arc::clone(&dir);
+        let forest_fork = &mut Arc::clone(forest);
+
+        dir.write(
+            &["first_client.txt".into()],
+            true,
+            Utc::now(),
+            b"first".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+
+        dir.store(forest, store, rng).await?;
+
+        // concurrent write
+        fork.write(
+            &["second_client.txt".into()],
+            true,
+            Utc::now(),
+            b"second".to_vec(),
+            forest_fork,
+            store,
+            rng,
+        )
+        .await?;
+
+        fork.store(forest_fork, store, rng).await?;
+
+        // we merge the forests
+        *forest = Arc::new(forest.merge(forest_fork, store).await?);
+
+        // This should reconcile the changes
+        dir = dir.search_latest_reconciled(forest, store).await?;
+
+        assert!(dir.content.entries.is_sharded());
+        assert_eq!(
+            dir.get_entries().count(),
+            PRIVATE_DIRECTORY_SHARDING_THRESHOLD + 2
+        );
+        assert_eq!(
+            dir.read(&["second_client.txt".into()], true, forest, store)
+                .await?,
+            b"second"
+        );
+
+        Ok(())
+    }
+
+    #[test(async_std::test)]
+    async fn walk_yields_decrypted_subtree() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        for path in ["docs/notes/a.md", "docs/b.md", "docs/.git/HEAD", "c.txt"] {
+            let path = path.split('/').map(String::from).collect::<Vec<_>>();
+            root_dir
+                .write(
+                    &path,
+                    true,
+                    Utc::now(),
+                    b"content".to_vec(),
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
+        }
+
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+        let root_dir = &PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+
+        let options = WalkOptions::new()
+            .concurrency(2)
+            .prune(|path, _| path.last().is_some_and(|name| name.starts_with('.')));
+        let entries = root_dir
+            .walk(&["docs".into()], true, options, forest, store)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let paths = entries
+            .iter()
+            .map(|(path, _, _)| path.join("/"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            ["docs/.git", "docs/b.md", "docs/notes", "docs/notes/a.md"]
+        );
+
+        let (_, node, metadata) = &entries[3];
+        assert_eq!(
+            node.as_file()?.get_content(forest, store).await?,
+            b"content"
+        );
+        assert_eq!(metadata, node.get_metadata());
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,595 @@
+use super::{
+    PrivateDirectoryShardSerializable, PrivateRef, PrivateRefSerializable, SHARD_KEY_DSI,
+    TemporalKey, forest::traits::PrivateForest, link::PrivateLink,
+};
+use crate::error::FsError;
+use anyhow::Result;
+use async_once_cell::OnceCell;
+use rand_core::CryptoRngCore;
+use std::{
+    collections::{BTreeMap, btree_map},
+    fmt::Debug,
+};
+use wnfs_common::{BlockStore, CODEC_RAW, Cid, utils::CondSend};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of entries a private directory can hold before its listing gets split
+/// into multiple encrypted shard blocks instead of being inlined into the directory block.
+pub const PRIVATE_DIRECTORY_SHARDING_THRESHOLD: usize = 512;
+
+/// The number of entries each shard of a sharded private directory aims to hold.
+///
+/// The actual number of shards is always a power of two, so shards hold
+/// between a half and the full amount of this on average.
+pub const PRIVATE_DIRECTORY_SHARD_SIZE: usize = 256;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The entries of a private directory.
+///
+/// Small directories serialize their entries inline, as part of the directory's content block.
+/// Once a directory grows beyond [`PRIVATE_DIRECTORY_SHARDING_THRESHOLD`] entries, the listing
+/// is split into shards by hashing entry names.
+///
+/// Each shard is encrypted with a key derived from the temporal key of the revision that
+/// wrote it. Later revisions re-use shards that weren't touched since, by wrapping their keys
+/// with their own temporal key. Since the keys can't be derived from later temporal keys,
+/// revisions of a shard that were replaced stay unreadable to holders of later keys.
+pub(crate) struct PrivateDirectoryEntries {
+    map: BTreeMap<String, PrivateLink>,
+    /// Caches the CID and key of each shard since it was last stored or loaded.
+    /// Empty iff the directory is small enough to be stored inline.
+    shards: Vec<OnceCell<(Cid, TemporalKey)>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateDirectoryEntries {
+    /// Creates an empty, unsharded listing.
+    pub(crate) fn new() -> Self {
+        Self {
+            map: BTreeMap::new(),
+            shards: Vec::new(),
+        }
+    }
+
+    /// Whether this listing is going to be stored as multiple shards.
+    pub(crate) fn is_sharded(&self) -> bool {
+        !self.shards.is_empty()
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&PrivateLink> {
+        self.map.get(name)
+    }
+
+    pub(crate) fn contains_key(&self, name: &str) -> bool {
+        self.map.contains_key(name)
+    }
+
+    pub(crate) fn iter(&self) -> btree_map::Iter<'_, String, PrivateLink> {
+        self.map.iter()
+    }
+
+    pub(crate) fn keys(&self) -> btree_map::Keys<'_, String, PrivateLink> {
+        self.map.keys()
+    }
+
+    /// Gets a mutable reference to an entry.
+    ///
+    /// The shard containing the entry is assumed to change.
+    pub(crate) fn get_mut(&mut self, name: &str) -> Option<&mut PrivateLink> {
+        if self.map.contains_key(name) {
+            self.invalidate(name);
+        }
+
+        self.map.get_mut(name)
+    }
+
+    /// Gets a mutable reference to an entry, inserting the result of `default` if it's missing.
+    pub(crate) fn get_or_insert_with(
+        &mut self,
+        name: String,
+        default: impl FnOnce() -> PrivateLink,
+    ) -> &mut PrivateLink {
+        if !self.map.contains_key(&name) {
+            self.map.insert(name.clone(), default());
+        }
+
+        self.invalidate(&name);
+        self.map.get_mut(&name).unwrap()
+    }
+
+    pub(crate) fn insert(&mut self, name: String, link: PrivateLink) -> Option<PrivateLink> {
+        let previous = self.map.insert(name.clone(), link);
+        self.invalidate(&name);
+        previous
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> Option<PrivateLink> {
+        let removed = self.map.remove(name)?;
+        self.invalidate(name);
+        Some(removed)
+    }
+
+    /// Iterates over mutable references to all entries.
+    ///
+    /// All shards are assumed to change.
+    pub(crate) fn values_mut(&mut self) -> btree_map::ValuesMut<'_, String, PrivateLink> {
+        self.shards = Self::empty_shards(self.map.len());
+        self.map.values_mut()
+    }
+
+    /// Forgets all cached shards, so the next store re-encrypts
+    /// the whole listing with keys of the next revision.
+    pub(crate) fn reset_shards(&mut self) {
+        self.shards = Self::empty_shards(self.map.len());
+    }
+
+    /// Marks the shard `name` belongs to as changed.
+    ///
+    /// If the entry count moved the listing across a shard count boundary,
+    /// all shards are marked as changed.
+    fn invalidate(&mut self, name: &str) {
+        let shard_count = Self::shard_count(self.map.len());
+        if self.shards.len() != shard_count {
+            self.shards = Self::empty_shards(self.map.len());
+        } else if shard_count > 0 {
+            self.shards[Self::shard_index(name, shard_count)] = OnceCell::new();
+        }
+    }
+
+    /// The number of shards a listing with `len` entries is split into.
+    ///
+    /// Returns `0` if the listing should be inlined.
+    fn shard_count(len: usize) -> usize {
+        if len <= PRIVATE_DIRECTORY_SHARDING_THRESHOLD {
+            return 0;
+        }
+
+        len.div_ceil(PRIVATE_DIRECTORY_SHARD_SIZE)
+            .next_power_of_two()
+    }
+
+    fn shard_index(name: &str, shard_count: usize) -> usize {
+        let hash = blake3::hash(name.as_bytes());
+        let prefix: [u8; 8] = hash.as_bytes()[..8].try_into().unwrap();
+        (u64::from_le_bytes(prefix) % shard_count as u64) as usize
+    }
+
+    fn empty_shards(len: usize) -> Vec<OnceCell<(Cid, TemporalKey)>> {
+        (0..Self::shard_count(len))
+            .map(|_| OnceCell::new())
+            .collect()
+    }
+
+    /// Derives the key for the shard at given index written by the revision with given
+    /// temporal key.
+    fn derive_shard_key(temporal_key: &TemporalKey, index: usize) -> TemporalKey {
+        let mut input = temporal_key.0.to_vec();
+        input.extend_from_slice(&(index as u64).to_le_bytes());
+        TemporalKey(blake3::derive_key(SHARD_KEY_DSI, &input))
+    }
+
+    /// Serializes the entries.
+    ///
+    /// Small listings are returned inline with all temporal keys wrapped using the
+    /// directory's temporal key.
+    /// Large listings are split into encrypted shards which are put into the block store.
+    /// In that case, entries' temporal keys are wrapped with the key of their shard, which in
+    /// turn is wrapped with the directory's temporal key.
+    pub(crate) async fn to_serializable(
+        &self,
+        temporal_key: &TemporalKey,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<(
+        BTreeMap<String, PrivateRefSerializable>,
+        Option<Vec<PrivateDirectoryShardSerializable>>,
+    )> {
+        if !self.is_sharded() {
+            let mut entries = BTreeMap::new();
+            for (name, private_link) in self.map.iter() {
+                let private_ref_serializable = private_link
+                    .resolve_ref(forest, store, rng)
+                    .await?
+                    .to_serializable(temporal_key)?;
+                entries.insert(name.clone(), private_ref_serializable);
+            }
+
+            return Ok((entries, None));
+        }
+
+        let shard_count = self.shards.len();
+        let mut buckets = vec![Vec::new(); shard_count];
+        for (name, private_link) in self.map.iter() {
+            buckets[Self::shard_index(name, shard_count)].push((name, private_link));
+        }
+
+        let mut shards = Vec::with_capacity(shard_count);
+        for (index, (shard_cache, bucket)) in self.shards.iter().zip(buckets).enumerate() {
+            let (cid, shard_key) = shard_cache
+                .get_or_try_init::<anyhow::Error>(async {
+                    let shard_key = Self::derive_shard_key(temporal_key, index);
+                    let mut entries = BTreeMap::new();
+                    for (name, private_link) in bucket {
+                        let private_ref_serializable = private_link
+                            .resolve_ref(forest, store, rng)
+                            .await?
+                            .to_serializable(&shard_key)?;
+                        entries.insert(name.clone(), private_ref_serializable);
+                    }
+
+                    let bytes = serde_ipld_dagcbor::to_vec(&entries)?;
+                    let block = shard_key.derive_snapshot_key().encrypt(&bytes, rng)?;
+                    let cid = store.put_block(block, CODEC_RAW).await?;
+                    Ok((cid, shard_key))
+                })
+                .await?;
+
+            shards.push(PrivateDirectoryShardSerializable {
+                cid: *cid,
+                snapshot_key: shard_key.derive_snapshot_key(),
+                temporal_key: temporal_key.key_wrap_encrypt(&shard_key.0)?,
+            });
+        }
+
+        Ok((BTreeMap::new(), Some(shards)))
+    }
+
+    /// Deserializes entries, fetching and decrypting shards if the listing was sharded.
+    pub(crate) async fn from_serializable(
+        entries: BTreeMap<String, PrivateRefSerializable>,
+        shards: Option<Vec<PrivateDirectoryShardSerializable>>,
+        temporal_key: &TemporalKey,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let mut map = BTreeMap::new();
+        for (name, private_ref_serializable) in entries {
+            let private_ref =
+                PrivateRef::from_serializable(private_ref_serializable, temporal_key)?;
+            map.insert(name, PrivateLink::from_ref(private_ref));
+        }
+
+        let Some(shards) = shards else {
+            return Ok(Self {
+                shards: Self::empty_shards(map.len()),
+                map,
+            });
+        };
+
+        let mut loaded_shards = Vec::with_capacity(shards.len());
+        for shard in shards {
+            let shard_key = TemporalKey(
+                temporal_key
+                    .key_wrap_decrypt(&shard.temporal_key)?
+                    .try_into()
+                    .map_err(|e: Vec<u8>| {
+                        FsError::InvalidDeserialization(format!(
+                            "Expected 32 bytes for directory shard key, but got {}",
+                            e.len()
+                        ))
+                    })?,
+            );
+
+            let encrypted_bytes = store.get_block(&shard.cid).await?;
+            let bytes = shard_key.derive_snapshot_key().decrypt(&encrypted_bytes)?;
+            let entries: BTreeMap<String, PrivateRefSerializable> =
+                serde_ipld_dagcbor::from_slice(&bytes)?;
+            for (name, private_ref_serializable) in entries {
+                let private_ref =
+                    PrivateRef::from_serializable(private_ref_serializable, &shard_key)?;
+                map.insert(name, PrivateLink::from_ref(private_ref));
+            }
+
+            loaded_shards.push((shard.cid, shard_key));
+        }
+
+        // Only re-use the stored shards if they match the layout we'd produce.
+        let shards = if loaded_shards.len() == Self::shard_count(map.len()) {
+            loaded_shards.into_iter().map(OnceCell::new_with).collect()
+        } else {
+            Self::empty_shards(map.len())
+        };
+
+        Ok(Self { map, shards })
+    }
+}
+
+impl Default for PrivateDirectoryEntries {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for PrivateDirectoryEntries {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.map.fmt(f)
+    }
+}
+
+impl PartialEq for PrivateDirectoryEntries {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl Clone for PrivateDirectoryEntries {
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+            shards: self
+                .shards
+                .iter()
+                .map(|cid| {
+                    cid.get()
+                        .cloned()
+                        .map(OnceCell::new_with)
+                        .unwrap_or_default()
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a PrivateDirectoryEntries {
+    type Item = (&'a String, &'a PrivateLink);
+    type IntoIter = btree_map::Iter<'a, String, PrivateLink>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{
+        PrivateDirectory, PrivateFile, PrivateNode,
+        forest::{hamt::HamtForest, traits::PrivateForest},
+    };
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::{MemoryBlockStore, utils::Arc};
+
+    async fn large_directory(
+        entry_count: usize,
+        forest: &mut Arc<HamtForest>,
+        store: &MemoryBlockStore,
+        rng: &mut ChaCha12Rng,
+    ) -> Result<Arc<PrivateDirectory>> {
+        let mut dir = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let file = PrivateFile::with_content(
+            &dir.header.name,
+            Utc::now(),
+            b"Hello, World!".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let private_ref = file.store(forest, store, rng).await?;
+
+        let entries = &mut Arc::make_mut(&mut dir).content.entries;
+        for i in 0..entry_count {
+            entries.insert(
+                format!("file_{i}.txt"),
+                PrivateLink::from_ref(private_ref.clone()),
+            );
+        }
+
+        Ok(dir)
+    }
+
+    fn cached_shards(dir: &PrivateDirectory) -> Vec<Option<Cid>> {
+        dir.content
+            .entries
+            .shards
+            .iter()
+            .map(|shard| shard.get().map(|(cid, _)| *cid))
+            .collect()
+    }
+
+    fn stored_shards(dir: &PrivateDirectory) -> Vec<(Cid, TemporalKey)> {
+        dir.content
+            .entries
+            .shards
+            .iter()
+            .filter_map(|shard| shard.get().cloned())
+            .collect()
+    }
+
+    #[test]
+    fn shard_count_is_zero_below_threshold() {
+        assert_eq!(PrivateDirectoryEntries::shard_count(0), 0);
+        assert_eq!(
+            PrivateDirectoryEntries::shard_count(PRIVATE_DIRECTORY_SHARDING_THRESHOLD),
+            0
+        );
+        assert!(PrivateDirectoryEntries::shard_count(PRIVATE_DIRECTORY_SHARDING_THRESHOLD + 1) > 1);
+    }
+
+    #[async_std::test]
+    async fn small_directories_are_stored_inline() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = large_directory(10, forest, store, rng).await?;
+
+        assert!(!dir.content.entries.is_sharded());
+
+        let access_key = dir.as_node().store(forest, store, rng).await?;
+        let loaded = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+
+        assert!(!loaded.content.entries.is_sharded());
+        assert_eq!(loaded.get_entries().count(), 10);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn large_directories_are_sharded_and_can_be_loaded() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let entry_count = 4 * PRIVATE_DIRECTORY_SHARDING_THRESHOLD;
+        let dir = large_directory(entry_count, forest, store, rng).await?;
+
+        assert!(dir.content.entries.is_sharded());
+
+        let access_key = dir.as_node().store(forest, store, rng).await?;
+        let loaded = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+
+        assert!(loaded.content.entries.is_sharded());
+        assert_eq!(loaded.get_entries().count(), entry_count);
+        assert_eq!(cached_shards(&loaded), cached_shards(&dir));
+
+        let content = loaded
+            .read(&["file_1337.txt".into()], false, forest, store)
+            .await?;
+
+        assert_eq!(content, b"Hello, World!");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn sharded_directories_only_rewrite_changed_shards() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir =
+            &mut large_directory(4 * PRIVATE_DIRECTORY_SHARDING_THRESHOLD, forest, store, rng)
+                .await?;
+
+        dir.as_node().store(forest, store, rng).await?;
+        let shards_before = cached_shards(dir);
+
+        dir.write(
+            &["file_0.txt".into()],
+            false,
+            Utc::now(),
+            b"Changed".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        dir.as_node().store(forest, store, rng).await?;
+        let shards_after = cached_shards(dir);
+
+        assert_eq!(shards_before.len(), shards_after.len());
+        assert!(shards_after.iter().all(Option::is_some));
+        assert_eq!(
+            shards_before
+                .iter()
+                .zip(shards_after.iter())
+                .filter(|(before, after)| before != after)
+                .count(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn later_revisions_cant_decrypt_replaced_shards() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir =
+            &mut large_directory(4 * PRIVATE_DIRECTORY_SHARDING_THRESHOLD, forest, store, rng)
+                .await?;
+
+        dir.as_node().store(forest, store, rng).await?;
+        let shards_before = stored_shards(dir);
+
+        dir.write(
+            &["file_0.txt".into()],
+            false,
+            Utc::now(),
+            b"Changed".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let access_key = dir.as_node().store(forest, store, rng).await?;
+        let later = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        let shards_after = stored_shards(&later);
+
+        // All keys a holder of the later temporal key can get to
+        let temporal_key = later.header.derive_temporal_key();
+        let mut later_keys = shards_after
+            .iter()
+            .map(|(_, key)| key.clone())
+            .collect::<Vec<_>>();
+        later_keys.extend(
+            (0..shards_after.len())
+                .map(|index| PrivateDirectoryEntries::derive_shard_key(&temporal_key, index)),
+        );
+
+        let replaced = shards_before
+            .iter()
+            .filter(|shard| !shards_after.contains(shard))
+            .collect::<Vec<_>>();
+        assert_eq!(replaced.len(), 1);
+        for (cid, key) in replaced {
+            let block = store.get_block(cid).await?;
+            assert!(key.derive_snapshot_key().decrypt(&block).is_ok());
+            for later_key in later_keys.iter() {
+                assert!(later_key.derive_snapshot_key().decrypt(&block).is_err());
+            }
+        }
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn directories_shrinking_below_threshold_are_stored_inline_again() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir =
+            &mut large_directory(PRIVATE_DIRECTORY_SHARDING_THRESHOLD + 1, forest, store, rng)
+                .await?;
+
+        dir.as_node().store(forest, store, rng).await?;
+        assert!(dir.content.entries.is_sharded());
+
+        dir.rm(&["file_0.txt".into()], false, forest, store).await?;
+        assert!(!dir.content.entries.is_sharded());
+
+        let access_key = dir.as_node().store(forest, store, rng).await?;
+        let loaded = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+
+        assert!(!loaded.content.entries.is_sharded());
+        assert_eq!(
+            loaded.get_entries().count(),
+            PRIVATE_DIRECTORY_SHARDING_THRESHOLD
+        );
+
+        Ok(())
+    }
+}
@@ -1,10 +1,16 @@
 use super::{
-    AUTHENTICATION_TAG_SIZE, BLOCK_SEGMENT_DSI, HIDING_SEGMENT_DSI, NONCE_SIZE,
-    PrivateFileContentSerializable, PrivateNode, PrivateNodeContentSerializable, PrivateNodeHeader,
-    PrivateRef, SnapshotKey, TemporalKey, encrypted::Encrypted, forest::traits::PrivateForest,
+    AUTHENTICATION_TAG_SIZE, BLOCK_SEGMENT_DSI, CONFLICT_COPY_DSI, HIDING_SEGMENT_DSI,
+    MERGED_CONTENT_DSI, NONCE_SIZE, PrivateFileContentSerializable, PrivateNode,
+    PrivateNodeContentSerializable, PrivateNodeHeader, PrivateRef, SnapshotAccessKey, SnapshotKey,
+    TemporalKey, encrypted::Encrypted, forest::traits::PrivateForest,
 };
 use crate::{
-    WNFS_VERSION, error::FsError, is_readable_wnfs_version, traits::Id, utils::OnceCellDebug,
+    WNFS_VERSION,
+    conflict::{ConflictResolution, Replica},
+    error::FsError,
+    is_readable_wnfs_version,
+    traits::Id,
+    utils::OnceCellDebug,
 };
 use anyhow::{Result, bail};
 use async_once_cell::OnceCell;
@@ -15,7 +21,8 @@ use ipld_core::{
     ipld::Ipld,
     serde::{from_ipld, to_ipld},
 };
-use rand_core::CryptoRngCore;
+use rand_chacha::ChaCha12Rng;
+use rand_core::{CryptoRngCore, SeedableRng};
 use serde::{Deserialize, Serialize};
 use std::{cmp::Ordering, collections::BTreeSet, iter};
 use wnfs_common::{
@@ -753,6 +760,22 @@ impl PrivateFile {
         Ok(cloned)
     }
 
+    /// Replaces the content and metadata of this file with the ones of an earlier revision,
+    /// as a new revision of this file.
+    ///
+    /// The content keys of the earlier revision are kept, so no content needs to be re-encrypted.
+    pub(crate) fn restore_from(
+        self: &mut Arc<Self>,
+        revision: &PrivateFile,
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        let file = self.prepare_next_revision()?;
+        file.content.content.clone_from(&revision.content.content);
+        file.content.metadata.clone_from(&revision.content.metadata);
+        file.content.metadata.upsert_mtime(time);
+        Ok(())
+    }
+
     /// Call this function to prepare this file for conflict reconciliation merge changes.
     /// Advances this node to the revision given in `target_header`.
     /// Generates another previous link, unless this node is already a merge node, then this
@@ -817,6 +840,52 @@ impl PrivateFile {
         Ok(())
     }
 
+    /// Creates a copy of this file revision under a new identity in given parent,
+    /// with no history. Used for keeping both versions of concurrently written files.
+    ///
+    /// The new identity is derived from this revision's temporal key and CID, so every
+    /// replica that resolves the same conflict ends up with the same copy.
+    pub(crate) async fn conflict_copy(&self, parent_name: &Name, cid: &Cid) -> Result<Self> {
+        let temporal_key = self.header.derive_temporal_key();
+        let seed = blake3::derive_key(
+            CONFLICT_COPY_DSI,
+            &[temporal_key.0.as_slice(), &cid.to_bytes()].concat(),
+        );
+
+        let mut copy = self.clone();
+        copy.prepare_key_rotation(parent_name, &mut ChaCha12Rng::from_seed(seed))
+            .await?;
+        copy.content.previous = BTreeSet::new();
+        Ok(copy)
+    }
+
+    /// Replaces the content of this file with content a merge driver produced.
+    ///
+    /// The content key is derived from this revision's temporal key and the merged content,
+    /// so every replica that merges the same writes ends up with the same content.
+    pub(crate) async fn set_merged_content(
+        &mut self,
+        content: Vec<u8>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let temporal_key = self.header.derive_temporal_key();
+        let seed = blake3::derive_key(
+            MERGED_CONTENT_DSI,
+            &[temporal_key.0.as_slice(), blake3::hash(&content).as_bytes()].concat(),
+        );
+
+        self.content.content = Self::prepare_content(
+            self.header.get_name(),
+            content,
+            forest,
+            store,
+            &mut ChaCha12Rng::from_seed(seed),
+        )
+        .await?;
+        Ok(())
+    }
+
     /// Stores this PrivateFile in the PrivateForest.
     pub(crate) async fn store(
         &self,
@@ -875,6 +944,49 @@ impl PrivateFile {
         Ok(Self { header, content })
     }
 
+    /// Loads the revision of a file that given snapshot access key points to.
+    ///
+    /// Snapshot keys can't decrypt the file's header, so the loaded file gets a placeholder
+    /// header derived from its content CID. Its content can be read, but it can't be
+    /// stored or followed to later revisions.
+    pub(crate) async fn load_snapshot(
+        access_key: &SnapshotAccessKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let cid = access_key.content_cid;
+        match forest
+            .get_encrypted_by_hash(&access_key.label, store)
+            .await?
+        {
+            Some(cids) if cids.contains(&cid) => {}
+            _ => bail!(FsError::NotFound),
+        };
+
+        let bytes = access_key
+            .snapshot_key
+            .decrypt(&store.get_block(&cid).await?)?;
+        let PrivateNodeContentSerializable::File(serializable) =
+            serde_ipld_dagcbor::from_slice(&bytes)?
+        else {
+            bail!(FsError::NotAFile)
+        };
+        if !is_readable_wnfs_version(&serializable.version) {
+            bail!(FsError::UnexpectedVersion(serializable.version));
+        }
+
+        let rng = &mut ChaCha12Rng::from_seed(blake3::hash(&cid.to_bytes()).into());
+        Ok(Self {
+            header: PrivateNodeHeader::new(&forest.empty_name(), rng),
+            content: PrivateFileContent {
+                persisted_as: OnceCell::new_with(cid),
+                previous: serializable.previous.into_iter().collect(),
+                metadata: serializable.metadata,
+                content: serializable.content,
+            },
+        })
+    }
+
     /// Wraps the file in a [`PrivateNode`].
     pub fn as_node(self: &Arc<Self>) -> PrivateNode {
         PrivateNode::File(Arc::clone(self))
@@ -883,16 +995,21 @@ impl PrivateFile {
     /// Merges two private files together.
     /// The files must have been stored before (that's the CIDs that
     /// are passed in).
-    /// This function is both commutative and associative.
+    /// With [`ConflictResolution::TieBreak`], this function is both commutative and associative.
+    /// Conflict copies can't be made for a single file, so [`ConflictResolution::KeepBoth`]
+    /// tie-breaks as well.
+    ///
+    /// Returns the replica whose content was kept.
     pub(crate) fn merge(
         self: &mut Arc<Self>,
         target_header: PrivateNodeHeader,
         our_cid: Cid,
         other: &Arc<Self>,
         other_cid: Cid,
-    ) -> Result<()> {
+        resolution: ConflictResolution,
+    ) -> Result<Replica> {
         if our_cid == other_cid {
-            return Ok(());
+            return Ok(Replica::Ours);
         }
 
         let other_ratchet_diff = target_header.ratchet_diff_for_merge(&other.header)?;
@@ -918,25 +1035,36 @@ impl PrivateFile {
             ));
         }
 
+        match resolution {
+            ConflictResolution::KeepOurs => return Ok(Replica::Ours),
+            ConflictResolution::KeepTheirs => {
+                our.content.content.clone_from(&other.content.content);
+                our.content.metadata.clone_from(&other.content.metadata);
+                return Ok(Replica::Theirs);
+            }
+            ConflictResolution::TieBreak | ConflictResolution::KeepBoth => {}
+        }
+
         let our_hash = our.content.content.crdt_tiebreaker()?;
         let other_hash = other.content.content.crdt_tiebreaker()?;
 
-        match our_hash.cmp(&other_hash) {
+        Ok(match our_hash.cmp(&other_hash) {
             Ordering::Greater => {
                 our.content.content.clone_from(&other.content.content);
                 our.content.metadata.clone_from(&other.content.metadata);
+                Replica::Theirs
             }
             Ordering::Equal => {
                 our.content
                     .metadata
                     .tie_break_with(&other.content.metadata)?;
+                Replica::Ours
             }
             Ordering::Less => {
                 // we take ours
+                Replica::Ours
             }
-        }
-
-        Ok(())
+        })
     }
 }
 
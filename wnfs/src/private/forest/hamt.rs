@@ -5,7 +5,7 @@ use quick_cache::sync::Cache;
 use rand_core::CryptoRngCore;
 use semver::Version;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::{collections::BTreeSet, convert::Infallible};
 use wnfs_common::{
     BlockStore, Cid, HashOutput, Link, Storable, impl_storable_from_serde,
     utils::{Arc, CondSend},
@@ -238,13 +238,12 @@ impl PrivateForest for HamtForest {
     }
 
     fn get_proven_name(&self, name: &Name) -> (NameAccumulator, ElementsProof) {
-        match self
-            .name_cache
-            .get_or_insert_with(name, || Ok(name.into_proven_accumulator(&self.accumulator)))
-        {
-            // Neat trick to avoid .unwrap():
+        let result = self.name_cache.get_or_insert_with(name, || {
+            Ok::<_, Infallible>(name.into_proven_accumulator(&self.accumulator))
+        });
+        match result {
             Ok(r) => r,
-            Err(r) => r,
+            Err(never) => match never {},
         }
     }
 
@@ -1,7 +1,9 @@
 //! Primitives for working with the private file system.
 
+mod diff;
 mod directory;
 mod encrypted;
+mod entries;
 mod file;
 pub mod forest;
 mod keys;
@@ -10,7 +12,9 @@ mod node;
 mod previous;
 pub mod share;
 
+pub use diff::*;
 pub use directory::*;
+pub use entries::{PRIVATE_DIRECTORY_SHARD_SIZE, PRIVATE_DIRECTORY_SHARDING_THRESHOLD};
 pub use file::*;
 pub use keys::*;
 pub use node::*;
@@ -49,6 +49,21 @@ pub(crate) const TEMPORAL_KEY_DSI: &str = "wnfs/1.0/temporal derivation from rat
 /// used for salting the hashing function when deriving
 /// the snapshot key from the temporal key.
 pub(crate) const SNAPSHOT_KEY_DSI: &str = "wnfs/1.0/snapshot key derivation from temporal";
+/// The conflict copy derivation domain separation info
+/// used for salting the hashing function when deriving the identity of a
+/// conflict copy from the temporal key and CID of the losing file revision.
+/// This domain separation string is not part of the standard.
+pub(crate) const CONFLICT_COPY_DSI: &str = "wnfs/1.0/conflict copy derivation from temporal";
+/// The shard key derivation domain separation info
+/// used for salting the hashing function when deriving the key of a
+/// directory listing shard from the temporal key of the revision writing it.
+/// This domain separation string is not part of the standard.
+pub(crate) const SHARD_KEY_DSI: &str = "wnfs/1.0/directory shard key derivation from temporal";
+/// The merged content derivation domain separation info
+/// used for salting the hashing function when deriving the key of content a merge driver
+/// produced from the temporal key of the merge revision and the merged content.
+/// This domain separation string is not part of the standard.
+pub(crate) const MERGED_CONTENT_DSI: &str = "wnfs/1.0/merged content derivation from temporal";
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
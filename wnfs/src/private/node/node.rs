@@ -1,10 +1,14 @@
 use super::{PrivateNodeHeader, TemporalKey};
 use crate::{
+    conflict::{Conflict, ConflictPolicy, ConflictResolution, TieBreakPolicy},
     error::FsError,
+    merge::{MergeDriver, MergeOutcome},
     private::{
         AccessKey, PrivateDirectory, PrivateFile, PrivateNodeContentSerializable, PrivateRef,
         encrypted::Encrypted, forest::traits::PrivateForest, link::PrivateLink,
+        previous::common_ancestor,
     },
+    public::{PublicDirectory, PublicFile, PublicLink, PublicNode, Reconciliation},
     traits::Id,
 };
 use anyhow::{Result, bail};
@@ -19,11 +23,18 @@ use std::{
     fmt::Debug,
 };
 use wnfs_common::{
-    BlockStore, Cid,
+    BlockStore, Cid, Metadata,
     utils::{Arc, CondSend},
 };
 use wnfs_nameaccumulator::Name;
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How many revisions the search for the base of a content merge may skip over.
+const MERGE_BASE_DISCREPANCY_BUDGET: usize = 1_000_000;
+
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
 //--------------------------------------------------------------------------------------------------
@@ -106,6 +117,79 @@ impl PrivateNode {
         }
     }
 
+    /// Encrypts a public subtree into new private nodes below a directory with given name.
+    ///
+    /// The latest revision of every node is copied together with its metadata. History isn't.
+    #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+    #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+    pub(crate) async fn from_public(
+        node: &PublicNode,
+        parent_name: &Name,
+        time: DateTime<Utc>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<Self> {
+        Ok(match node {
+            PublicNode::File(file) => {
+                let content = file.get_content(store).await?;
+                let mut private =
+                    PrivateFile::with_content(parent_name, time, content, forest, store, rng)
+                        .await?;
+                private.content.metadata.clone_from(file.get_metadata());
+                Self::File(Arc::new(private))
+            }
+            PublicNode::Dir(dir) => {
+                let mut private = PrivateDirectory::new(parent_name, time, rng);
+                private.content.metadata.clone_from(dir.get_metadata());
+                for (name, link) in dir.userland.iter() {
+                    let child = link.resolve_value(store).await?;
+                    let child =
+                        Self::from_public(child, &private.header.name, time, forest, store, rng)
+                            .await?;
+                    private
+                        .content
+                        .entries
+                        .insert(name.clone(), PrivateLink::from(child));
+                }
+                Self::Dir(Arc::new(private))
+            }
+        })
+    }
+
+    /// Decrypts the latest revision of this subtree into new public nodes,
+    /// copying the metadata of every node.
+    #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+    #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+    pub(crate) async fn to_public(
+        &self,
+        time: DateTime<Utc>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<PublicNode> {
+        Ok(match self {
+            Self::File(file) => {
+                let content = file.get_content(forest, store).await?;
+                let mut public = PublicFile::with_content(time, content, store).await?;
+                public.get_metadata_mut().clone_from(&file.content.metadata);
+                PublicNode::File(Arc::new(public))
+            }
+            Self::Dir(dir) => {
+                let mut public = PublicDirectory::new(time);
+                public.get_metadata_mut().clone_from(&dir.content.metadata);
+                for name in dir.content.entries.keys() {
+                    let child = dir
+                        .lookup_node(name, true, forest, store)
+                        .await?
+                        .ok_or(FsError::NotFound)?;
+                    let child = child.to_public(time, forest, store).await?;
+                    public.userland.insert(name.clone(), PublicLink::new(child));
+                }
+                PublicNode::Dir(Arc::new(public))
+            }
+        })
+    }
+
     /// Updates bare name ancestry of private sub tree.
     #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
     #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
@@ -123,18 +207,18 @@ impl PrivateNode {
             }
             Self::Dir(dir_rc) => {
                 let dir = Arc::make_mut(dir_rc);
+                let previous_name = dir.header.name.clone();
+                dir.prepare_key_rotation(parent_name, rng);
 
                 for private_link in &mut dir.content.entries.values_mut() {
                     let mut node = private_link
-                        .resolve_node(forest, store, Some(dir.header.name.clone()))
+                        .resolve_node(forest, store, Some(previous_name.clone()))
                         .await?
                         .clone();
                     node.update_ancestry(&dir.header.name, forest, store, rng)
                         .await?;
                     *private_link = PrivateLink::from(node);
                 }
-
-                dir.prepare_key_rotation(parent_name, rng);
             }
         }
         Ok(())
@@ -312,6 +396,14 @@ impl PrivateNode {
         matches!(self, Self::File(_))
     }
 
+    /// Gets the metadata of the underlying file or directory.
+    pub fn get_metadata(&self) -> &Metadata {
+        match self {
+            Self::File(file) => file.get_metadata(),
+            Self::Dir(dir) => &dir.content.metadata,
+        }
+    }
+
     /// Gets the latest version of the node using exponential search.
     ///
     /// # Examples
@@ -395,37 +487,217 @@ impl PrivateNode {
     /// Will reconcile this node with any newer changes fetched from the
     /// PrivateForest. But will overwrite any in-memory changes that haven't been
     /// persisted yet.
+    ///
+    /// Conflicts are resolved with the [`TieBreakPolicy`].
+    /// Use [`Self::search_latest_reconciled_with`] to pick a different policy
+    /// and find out which conflicts were resolved.
     pub async fn search_latest_reconciled(
         &self,
         forest: &impl PrivateForest,
         store: &impl BlockStore,
     ) -> Result<PrivateNode> {
+        let (node, _) = self
+            .search_latest_reconciled_with(&TieBreakPolicy, forest, store)
+            .await?;
+        Ok(node)
+    }
+
+    /// Like `search_latest_reconciled`, but resolves conflicts between concurrent
+    /// writes with given policy and reports every conflict that came up.
+    ///
+    /// Concurrent writes are merged pair-wise, starting with the one with the lowest CID.
+    /// In each step, "ours" is the merge result so far and "theirs" is the next write.
+    ///
+    /// Merges of directories are shallow, so conflict paths are relative to this node
+    /// and either empty (when this node is a file) or a single entry name.
+    pub async fn search_latest_reconciled_with(
+        &self,
+        policy: &impl ConflictPolicy<PrivateNode>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<(PrivateNode, Vec<Conflict<PrivateNode>>)> {
+        let (node, reconciliation) = self
+            .search_latest_reconciliation(policy, forest, store)
+            .await?;
+
+        let conflicts = match reconciliation {
+            Reconciliation::Merged { conflicts } => conflicts,
+            _ => Vec::new(),
+        };
+
+        Ok((node, conflicts))
+    }
+
+    /// Like `search_latest_reconciled_with`, but also reports whether this node was
+    /// already the latest revision, could be fast-forwarded or concurrent writes had to be merged.
+    ///
+    /// This node is only recognized as the latest revision if it was stored.
+    pub async fn search_latest_reconciliation(
+        &self,
+        policy: &impl ConflictPolicy<PrivateNode>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<(PrivateNode, Reconciliation<PrivateNode>)> {
         let mut header = self.get_header().clone();
         let mut unmerged_heads = header.seek_unmerged_heads(forest, store).await?;
 
-        match unmerged_heads.pop_first() {
-            Some((cid, head)) => {
-                if unmerged_heads.is_empty() {
-                    // There was only one unmerged head, we can fast forward
-                    Ok(head)
+        Ok(match unmerged_heads.pop_first() {
+            Some((cid, head)) if unmerged_heads.is_empty() => {
+                // There was only one unmerged head, we can fast forward
+                if self.get_persisted_as().get() == Some(&cid) {
+                    (head, Reconciliation::AlreadyAhead)
                 } else {
-                    // We need to create a merge node
-                    Self::merge(header, (cid, head), unmerged_heads, forest, store).await
+                    (head, Reconciliation::FastForward)
                 }
             }
-            _ => {
+            Some((cid, head)) => {
+                // We need to create a merge node
+                let mut conflicts = Vec::new();
+                let node = Self::merge(
+                    header,
+                    (cid, head),
+                    unmerged_heads,
+                    policy,
+                    false,
+                    &mut conflicts,
+                    forest,
+                    store,
+                )
+                .await?;
+                (node, Reconciliation::Merged { conflicts })
+            }
+            None => {
                 // If None, then there's nothing to merge in (and this node was never stored)
-                Ok(self.clone())
+                (self.clone(), Reconciliation::AlreadyAhead)
             }
-        }
+        })
+    }
+
+    /// Like `search_latest_reconciled_with`, but merges the contents of concurrent writes
+    /// to a file with given merge driver.
+    ///
+    /// The driver gets the plaintext of both writes and of the latest revision they were
+    /// both based on. That revision is found by walking back the history of both writes,
+    /// which is only possible down to the revision of `past`, an earlier revision of this node.
+    /// If there's no common revision after `past`, the driver gets no base.
+    ///
+    /// The merged content is stored in given forest and becomes the content of the merge node.
+    /// Only writes the driver can't merge are resolved with given policy and reported as conflicts.
+    ///
+    /// Concurrent writes to directories are merged like with `search_latest_reconciled_with`,
+    /// except that two revisions of the same file don't conflict, since they're expected to be
+    /// merged on their own level.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_latest_reconciled_merging(
+        &self,
+        past: &PrivateNode,
+        policy: &impl ConflictPolicy<PrivateNode>,
+        driver: &impl MergeDriver,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<(PrivateNode, Vec<Conflict<PrivateNode>>)> {
+        let mut header = self.get_header().clone();
+        let mut unmerged_heads = header.seek_unmerged_heads(forest, store).await?;
+        let mut conflicts = Vec::new();
+
+        let Some((cid, head)) = unmerged_heads.pop_first() else {
+            return Ok((self.clone(), conflicts));
+        };
+
+        let node = match head {
+            _ if unmerged_heads.is_empty() => head,
+            PrivateNode::File(file) => {
+                // They *should* all be files, but we won't error out if they're not.
+                let files = unmerged_heads
+                    .into_iter()
+                    .filter_map(|(cid, node)| node.as_file().ok().map(|file| (cid, file)))
+                    .collect::<BTreeMap<_, _>>();
+
+                let mut merged = Arc::clone(&file);
+                for (other_cid, other_file) in files {
+                    let base = common_ancestor(
+                        &PrivateNode::File(Arc::clone(&merged)),
+                        &PrivateNode::File(Arc::clone(&other_file)),
+                        &past.get_header().ratchet,
+                        MERGE_BASE_DISCREPANCY_BUDGET,
+                        forest,
+                        store,
+                    )
+                    .await?;
+                    let base = match base {
+                        Some(PrivateNode::File(base)) => {
+                            Some(base.get_content(forest, store).await?)
+                        }
+                        _ => None,
+                    };
+                    let ours = merged.get_content(forest, store).await?;
+                    let theirs = other_file.get_content(forest, store).await?;
+
+                    if let MergeOutcome::Merged(content) =
+                        driver.merge(&[], base.as_deref(), &ours, &theirs)
+                    {
+                        merged.merge(
+                            header.clone(),
+                            cid,
+                            &other_file,
+                            other_cid,
+                            ConflictResolution::TieBreak,
+                        )?;
+                        Arc::make_mut(&mut merged)
+                            .set_merged_content(content, forest, store)
+                            .await?;
+                        continue;
+                    }
+
+                    let ours = PrivateNode::File(Arc::clone(&merged));
+                    let theirs = PrivateNode::File(Arc::clone(&other_file));
+                    let resolution = policy.resolve(&[], &ours, &theirs);
+                    let winner =
+                        merged.merge(header.clone(), cid, &other_file, other_cid, resolution)?;
+
+                    conflicts.push(Conflict {
+                        path: Vec::new(),
+                        ours,
+                        theirs,
+                        resolution,
+                        winner,
+                        copy: None,
+                    });
+                }
+
+                PrivateNode::File(merged)
+            }
+            PrivateNode::Dir(_) => {
+                Self::merge(
+                    header,
+                    (cid, head),
+                    unmerged_heads,
+                    policy,
+                    true,
+                    &mut conflicts,
+                    forest,
+                    store,
+                )
+                .await?
+            }
+        };
+
+        Ok((node, conflicts))
     }
 
     /// Merges a non-empty set of conflicting private nodes together
     /// by merging them pair-wise.
+    ///
+    /// If `defer_file_merges` is set, directories are merged without conflicts
+    /// between two revisions of the same file.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn merge(
         header: PrivateNodeHeader,
         (cid, node): (Cid, PrivateNode),
         nodes: BTreeMap<Cid, PrivateNode>,
+        policy: &impl ConflictPolicy<PrivateNode>,
+        defer_file_merges: bool,
+        conflicts: &mut Vec<Conflict<PrivateNode>>,
         forest: &impl PrivateForest,
         store: &impl BlockStore,
     ) -> Result<PrivateNode> {
@@ -438,7 +710,20 @@ impl PrivateNode {
                     .collect::<BTreeMap<_, _>>();
 
                 for (other_cid, other_file) in files {
-                    file.merge(header.clone(), cid, &other_file, other_cid)?;
+                    let ours = PrivateNode::File(Arc::clone(&file));
+                    let theirs = PrivateNode::File(Arc::clone(&other_file));
+                    let resolution = policy.resolve(&[], &ours, &theirs);
+                    let winner =
+                        file.merge(header.clone(), cid, &other_file, other_cid, resolution)?;
+
+                    conflicts.push(Conflict {
+                        path: Vec::new(),
+                        ours,
+                        theirs,
+                        resolution,
+                        winner,
+                        copy: None,
+                    });
                 }
 
                 Ok(PrivateNode::File(file))
@@ -454,8 +739,18 @@ impl PrivateNode {
                     // Need to pass in rng & mutable forest access
                     // for the cases where we haven't yet written a node to
                     // the forest, but need its hash for tie-breaking.
-                    dir.merge(header.clone(), cid, &other_dir, other_cid, forest, store)
-                        .await?;
+                    dir.merge(
+                        header.clone(),
+                        cid,
+                        &other_dir,
+                        other_cid,
+                        policy,
+                        defer_file_merges,
+                        conflicts,
+                        forest,
+                        store,
+                    )
+                    .await?;
                 }
 
                 Ok(PrivateNode::Dir(dir))
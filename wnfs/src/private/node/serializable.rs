@@ -38,6 +38,17 @@ pub(crate) struct PrivateDirectoryContentSerializable {
     pub header_cid: Cid,
     pub metadata: Metadata,
     pub entries: BTreeMap<String, PrivateRefSerializable>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shards: Option<Vec<PrivateDirectoryShardSerializable>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PrivateDirectoryShardSerializable {
+    pub cid: Cid,
+    pub snapshot_key: SnapshotKey,
+    #[serde(with = "serde_bytes")]
+    pub temporal_key: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -2,8 +2,9 @@ use super::{
     PrivateDirectory, PrivateFile, PrivateNode, PrivateNodeHeader, TemporalKey,
     encrypted::Encrypted, forest::traits::PrivateForest,
 };
-use crate::error::FsError;
+use crate::{error::FsError, utils::modified_at_or_before};
 use anyhow::{Result, bail};
+use chrono::{DateTime, Utc};
 use skip_ratchet::{PreviousIterator, Ratchet};
 use std::collections::BTreeSet;
 use wnfs_common::{BlockStore, Cid, PathNodes, PathNodesResult, utils::Arc};
@@ -112,41 +113,21 @@ impl<F: PrivateForest> PrivateNodeHistory<F> {
             return Ok(None);
         };
 
-        let Some(previous_cid) = self.resolve_previous_cid(&previous_ratchet)? else {
-            return Ok(None);
-        };
-
-        self.header.update_ratchet(previous_ratchet);
-
-        let previous_node = PrivateNode::from_private_ref(
-            &self
-                .header
-                .derive_revision_ref(&self.forest)
-                .into_private_ref(previous_cid),
+        let Some((_, previous_node)) = load_previous_revision(
+            &self.header,
+            &self.previous,
+            &previous_ratchet,
             &self.forest,
             store,
-            self.header.name.parent(),
         )
-        .await?;
-
-        self.previous.clone_from(previous_node.get_previous());
-        Ok(Some(previous_node))
-    }
-
-    fn resolve_previous_cid(&self, previous_ratchet: &Ratchet) -> Result<Option<Cid>> {
-        // TODO(matheus23): Once we have private merge: Support walking forked history paths.
-        // That would need an additional API that allows 'selecting' one of the forks before moving on.
-        // Then this function would derive the nth-previous ratchet by "peeking" ahead the current
-        // self.ratchets iterator for n (the "# of revisions back" usize attached to the previous pointer)
-        let temporal_key = TemporalKey::new(previous_ratchet);
-        let Some((_, first_backpointer)) = self
-            .previous
-            .iter()
-            .find(|(revisions_back, _)| *revisions_back == 1)
+        .await?
         else {
             return Ok(None);
         };
-        Ok(Some(*first_backpointer.resolve_value(&temporal_key)?))
+
+        self.header.update_ratchet(previous_ratchet);
+        self.previous.clone_from(previous_node.get_previous());
+        Ok(Some(previous_node))
     }
 
     /// Like `previous_node`, but attempts to resolve a directory.
@@ -393,6 +374,37 @@ impl<F: PrivateForest + Clone> PrivateNodeOnPathHistory<F> {
         self.target.get_previous_node(store).await
     }
 
+    /// Returns the `n`th earlier revision, where `0` is the revision returned
+    /// by the first call to `get_previous`.
+    pub async fn nth_previous(
+        mut self,
+        n: usize,
+        store: &impl BlockStore,
+    ) -> Result<Option<PrivateNode>> {
+        for _ in 0..n {
+            if self.get_previous(store).await?.is_none() {
+                return Ok(None);
+            }
+        }
+
+        self.get_previous(store).await
+    }
+
+    /// Returns the newest earlier revision that was modified at or before given time.
+    pub async fn previous_as_of(
+        mut self,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<Option<PrivateNode>> {
+        while let Some(node) = self.get_previous(store).await? {
+            if modified_at_or_before(node.get_metadata(), time) {
+                return Ok(Some(node));
+            }
+        }
+
+        Ok(None)
+    }
+
     /// Pops off elements from the path segment history stack until a
     /// path segment history is found which has history entries.
     /// Then this will put the previous directory on that stack and return
@@ -492,6 +504,100 @@ impl<F: PrivateForest + Clone> PrivateNodeOnPathHistory<F> {
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Finds the latest revision that two concurrent revisions of a node were both based on.
+///
+/// Both revisions must be at the same ratchet. Their histories are walked back in lockstep,
+/// following only links to the directly preceding revision, until both reach the same revision.
+/// The walk stops at `past_ratchet`, since earlier ratchets can't be derived.
+///
+/// Returns `None` if there's no such revision after `past_ratchet`,
+/// or if `past_ratchet` isn't within `discrepancy_budget` of the revisions.
+pub(crate) async fn common_ancestor(
+    ours: &PrivateNode,
+    theirs: &PrivateNode,
+    past_ratchet: &Ratchet,
+    discrepancy_budget: usize,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<Option<PrivateNode>> {
+    let mut header = ours.get_header().clone();
+    if header.ratchet != theirs.get_header().ratchet {
+        return Ok(None);
+    }
+
+    let Ok(ratchets) = header.ratchet.previous(past_ratchet, discrepancy_budget) else {
+        return Ok(None);
+    };
+
+    let mut our_previous = ours.get_previous().clone();
+    let mut their_previous = theirs.get_previous().clone();
+    for previous_ratchet in ratchets {
+        let ours = load_previous_revision(&header, &our_previous, &previous_ratchet, forest, store)
+            .await?;
+        let theirs =
+            load_previous_revision(&header, &their_previous, &previous_ratchet, forest, store)
+                .await?;
+
+        let (Some((our_cid, ours)), Some((their_cid, theirs))) = (ours, theirs) else {
+            return Ok(None);
+        };
+
+        if our_cid == their_cid {
+            return Ok(Some(ours));
+        }
+
+        header.update_ratchet(previous_ratchet);
+        our_previous.clone_from(ours.get_previous());
+        their_previous.clone_from(theirs.get_previous());
+    }
+
+    Ok(None)
+}
+
+/// Loads the revision at `previous_ratchet` that a node with given header and
+/// previous links was based on, together with its CID.
+///
+/// Returns `None` if the node doesn't link to the directly preceding revision.
+async fn load_previous_revision(
+    header: &PrivateNodeHeader,
+    previous: &BTreeSet<(usize, Encrypted<Cid>)>,
+    previous_ratchet: &Ratchet,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<Option<(Cid, PrivateNode)>> {
+    // TODO(matheus23): Once we have private merge: Support walking forked history paths.
+    // That would need an additional API that allows 'selecting' one of the forks before moving on.
+    // Then this function would derive the nth-previous ratchet by "peeking" ahead the current
+    // ratchets iterator for n (the "# of revisions back" usize attached to the previous pointer)
+    let temporal_key = TemporalKey::new(previous_ratchet);
+    let Some((_, first_backpointer)) = previous
+        .iter()
+        .find(|(revisions_back, _)| *revisions_back == 1)
+    else {
+        return Ok(None);
+    };
+    let previous_cid = *first_backpointer.resolve_value(&temporal_key)?;
+
+    let mut header = header.clone();
+    header.update_ratchet(previous_ratchet.clone());
+
+    let previous_node = PrivateNode::from_private_ref(
+        &header
+            .derive_revision_ref(forest)
+            .into_private_ref(previous_cid),
+        forest,
+        store,
+        header.name.parent(),
+    )
+    .await?;
+
+    Ok(Some((previous_cid, previous_node)))
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
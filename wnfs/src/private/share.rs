@@ -9,7 +9,8 @@
 // Constants
 //--------------------------------------------------------------------------------------------------
 
-const EXCHANGE_KEY_NAME: &str = "v1.exchange_key";
+/// The name of the file holding a device's exchange key, in a directory named after the device.
+pub const EXCHANGE_KEY_NAME: &str = "v1.exchange_key";
 
 //--------------------------------------------------------------------------------------------------
 // Functions
@@ -151,6 +152,21 @@ pub mod recipient {
         forest: &impl PrivateForest,
         store: &impl BlockStore,
     ) -> Result<PrivateNode> {
+        let access_key = receive_access_key(share_label, recipient_key, forest, store).await?;
+
+        // Use decrypted key to get cid to encrypted node in sharer's forest.
+        PrivateNode::from_private_ref(&access_key.derive_private_ref()?, forest, store, None).await
+    }
+
+    /// Decrypts the access key of a share, without loading the shared node.
+    ///
+    /// Unlike [`receive_share`], this also works for snapshot access keys.
+    pub async fn receive_access_key(
+        share_label: &Name,
+        recipient_key: &impl PrivateKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<AccessKey> {
         // Get cid to encrypted payload from sharer's forest using share_label
         let access_key_cid = forest
             .get_encrypted_by_hash(
@@ -166,11 +182,9 @@ pub mod recipient {
         let encrypted_access_key = store.get_block(access_key_cid).await?.to_vec();
 
         // Decrypt access key using recipient's private key and decode it.
-        let access_key: AccessKey =
-            serde_ipld_dagcbor::from_slice(&recipient_key.decrypt(&encrypted_access_key).await?)?;
-
-        // Use decrypted key to get cid to encrypted node in sharer's forest.
-        PrivateNode::from_private_ref(&access_key.derive_private_ref()?, forest, store, None).await
+        Ok(serde_ipld_dagcbor::from_slice(
+            &recipient_key.decrypt(&encrypted_access_key).await?,
+        )?)
     }
 }
 
@@ -0,0 +1,378 @@
+use super::{PublicDirectory, PublicFile, PublicNode};
+use crate::{
+    diff::{
+        DirectoryIdentity, MoveDetector, PathChange, PathChangeType, metadata_eq_ignoring_mtime,
+    },
+    find::NodeKind,
+};
+use anyhow::Result;
+use async_stream::try_stream;
+use std::collections::BTreeSet;
+use wnfs_common::{
+    BlockStore, Cid, Storable, StoreIpld,
+    utils::{Arc, BoxFuture, BoxStream, boxed_fut, boxed_stream},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Computes the path-level changes between two public directory revisions.
+///
+/// Subtrees that are identical in both revisions are detected by their CID and skipped
+/// without being loaded. Nothing is written to the store, so subtrees that weren't stored
+/// yet are compared by their content instead. Added and removed nodes are always identified
+/// by their content, so whether they were stored doesn't affect which moves are detected.
+/// Content and metadata modifications are yielded while walking both trees.
+/// Additions, removals and moves are yielded at the end, since a move can only be detected
+/// once both its source and its destination have been found.
+///
+/// A node counts as moved if it was removed from one path and a node of the same kind and
+/// content was added at another path, and no other removed or added node has that content.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use futures::TryStreamExt;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     diff::PathChangeType,
+///     public::{self, PublicDirectory},
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let old = &mut PublicDirectory::new_rc(Utc::now());
+///     old.write(&["notes.md".into()], b"hi".to_vec(), Utc::now(), store).await?;
+///
+///     let new = &mut old.clone();
+///     new.basic_mv(&["notes.md".into()], &["readme.md".into()], Utc::now(), store).await?;
+///
+///     let changes = public::diff(old, new, store).try_collect::<Vec<_>>().await?;
+///
+///     assert_eq!(changes.len(), 1);
+///     assert_eq!(changes[0].path, vec!["readme.md".to_string()]);
+///     assert_eq!(changes[0].r#type, PathChangeType::Moved { from: vec!["notes.md".into()] });
+///
+///     Ok(())
+/// }
+/// ```
+pub fn diff<'a>(
+    old: &Arc<PublicDirectory>,
+    new: &Arc<PublicDirectory>,
+    store: &'a impl BlockStore,
+) -> BoxStream<'a, Result<PathChange>> {
+    let old = Arc::clone(old);
+    let new = Arc::clone(new);
+
+    boxed_stream(try_stream! {
+        let mut moves = MoveDetector::default();
+        let mut stack = vec![(Vec::new(), old, new)];
+
+        while let Some((path, old, new)) = stack.pop() {
+            let names = old
+                .userland
+                .iter()
+                .chain(new.userland.iter())
+                .map(|(name, _)| name)
+                .collect::<BTreeSet<_>>();
+
+            let mut subdirs = Vec::new();
+            for name in names {
+                let mut child_path = path.clone();
+                child_path.push(name.clone());
+
+                match (old.userland.get(name), new.userland.get(name)) {
+                    (Some(old_link), None) => {
+                        let node = old_link.resolve_value(store).await?;
+                        let (kind, identity) = content_identity(node, store).await?;
+                        moves.removed(child_path, kind, identity);
+                    }
+                    (None, Some(new_link)) => {
+                        let node = new_link.resolve_value(store).await?;
+                        let (kind, identity) = content_identity(node, store).await?;
+                        moves.added(child_path, kind, identity);
+                    }
+                    (Some(old_link), Some(new_link)) => {
+                        let old_cid = old_link.get_cid();
+                        if old_cid.is_some() && old_cid == new_link.get_cid() {
+                            continue;
+                        }
+
+                        match (old_link.resolve_value(store).await?, new_link.resolve_value(store).await?) {
+                            (PublicNode::File(old_file), PublicNode::File(new_file)) => {
+                                let r#type = if content_cid(old_file, store).await?
+                                    != content_cid(new_file, store).await?
+                                {
+                                    PathChangeType::ContentModified
+                                } else if old_file.metadata != new_file.metadata {
+                                    PathChangeType::MetadataModified
+                                } else {
+                                    continue;
+                                };
+
+                                yield PathChange { r#type, path: child_path, kind: NodeKind::File };
+                            }
+                            (PublicNode::Dir(old_dir), PublicNode::Dir(new_dir)) => {
+                                if !metadata_eq_ignoring_mtime(&old_dir.metadata, &new_dir.metadata) {
+                                    yield PathChange {
+                                        r#type: PathChangeType::MetadataModified,
+                                        path: child_path.clone(),
+                                        kind: NodeKind::Directory,
+                                    };
+                                }
+
+                                subdirs.push((child_path, Arc::clone(old_dir), Arc::clone(new_dir)));
+                            }
+                            (old_node, new_node) => {
+                                let (kind, identity) = content_identity(old_node, store).await?;
+                                moves.removed(child_path.clone(), kind, identity);
+                                let (kind, identity) = content_identity(new_node, store).await?;
+                                moves.added(child_path, kind, identity);
+                            }
+                        }
+                    }
+                    (None, None) => unreachable!(),
+                }
+            }
+
+            stack.extend(subdirs.into_iter().rev());
+        }
+
+        for change in moves.finish() {
+            yield change;
+        }
+    })
+}
+
+/// Returns the kind of a node together with bytes that are equal for nodes of equal content.
+async fn content_identity(
+    node: &PublicNode,
+    store: &impl BlockStore,
+) -> Result<(NodeKind, Vec<u8>)> {
+    Ok(match node {
+        PublicNode::File(file) => (NodeKind::File, content_cid(file, store).await?.to_bytes()),
+        PublicNode::Dir(dir) => (NodeKind::Directory, directory_identity(dir, store).await?),
+    })
+}
+
+/// Hashes the names, kinds and content identities of a directory's entries.
+///
+/// Stored entries are loaded to get their content identity, since their CIDs
+/// also cover their metadata and previous revisions.
+fn directory_identity<'a>(
+    dir: &'a PublicDirectory,
+    store: &'a impl BlockStore,
+) -> BoxFuture<'a, Result<Vec<u8>>> {
+    // Boxing the future due to recursion
+    boxed_fut(async move {
+        let mut identity = DirectoryIdentity::default();
+        for (name, link) in dir.userland.iter() {
+            let (kind, entry) = content_identity(link.resolve_value(store).await?, store).await?;
+            identity.add_entry(name, kind, &entry);
+        }
+
+        Ok(identity.finish())
+    })
+}
+
+/// Gets the CID of a file's content without storing it.
+async fn content_cid(file: &PublicFile, store: &impl BlockStore) -> Result<Cid> {
+    if let Some(cid) = file.userland.get_cid() {
+        return Ok(*cid);
+    }
+
+    let content = file.userland.resolve_value(store).await?;
+    let (bytes, codec) = content.to_serializable(store).await?.encode_ipld()?;
+    Ok(store.create_cid(&bytes, codec)?)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use chrono::Utc;
+    use futures::TryStreamExt;
+    use ipld_core::ipld::Ipld;
+    use testresult::TestResult;
+    use wnfs_common::{BlockStoreError, MemoryBlockStore, Storable, utils::CondSend};
+
+    /// A block store that fails all writes.
+    struct ReadOnlyStore<'a>(&'a MemoryBlockStore);
+
+    impl BlockStore for ReadOnlyStore<'_> {
+        async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+            self.0.get_block(cid).await
+        }
+
+        async fn put_block_keyed(
+            &self,
+            _cid: Cid,
+            _bytes: impl Into<Bytes> + CondSend,
+        ) -> Result<(), BlockStoreError> {
+            Err(anyhow::anyhow!("Unexpected write to a read-only store").into())
+        }
+
+        async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+            self.0.has_block(cid).await
+        }
+    }
+
+    #[async_std::test]
+    async fn diff_reports_path_changes() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let time = Utc::now();
+        let old = &mut PublicDirectory::new_rc(time);
+
+        for (path, content) in [
+            (vec!["docs", "a.md"], "a"),
+            (vec!["docs", "b.md"], "b"),
+            (vec!["docs", "c.md"], "c"),
+            (vec!["untouched", "d.md"], "d"),
+            (vec!["old.txt"], "old"),
+        ] {
+            let path = path.into_iter().map(String::from).collect::<Vec<_>>();
+            old.write(&path, content.as_bytes().to_vec(), time, store)
+                .await?;
+        }
+        old.store(store).await?;
+
+        let new = &mut old.clone();
+        new.write(&["docs".into(), "a.md".into()], b"A".to_vec(), time, store)
+            .await?;
+        new.open_file_mut(&["docs".into(), "b.md".into()], time, store)
+            .await?
+            .get_metadata_mut()
+            .put("tag", Ipld::String("draft".into()));
+        new.basic_mv(
+            &["docs".into(), "c.md".into()],
+            &["c.md".into()],
+            time,
+            store,
+        )
+        .await?;
+        new.rm(&["old.txt".into()], store).await?;
+        new.mkdir(&["new".into()], time, store).await?;
+
+        let changes = diff(old, new, store)
+            .map_ok(|change| (change.path.join("/"), change.r#type))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        assert_eq!(
+            changes,
+            vec![
+                ("docs/a.md".into(), PathChangeType::ContentModified),
+                ("docs/b.md".into(), PathChangeType::MetadataModified),
+                (
+                    "c.md".into(),
+                    PathChangeType::Moved {
+                        from: vec!["docs".into(), "c.md".into()]
+                    }
+                ),
+                ("new".into(), PathChangeType::Added),
+                ("old.txt".into(), PathChangeType::Removed),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn diff_doesnt_write_and_only_reports_unambiguous_moves() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let time = Utc::now();
+        let old = &mut PublicDirectory::new_rc(time);
+        for path in [vec!["docs", "a.md"], vec!["one.txt"], vec!["two.txt"]] {
+            let path = path.into_iter().map(String::from).collect::<Vec<_>>();
+            old.write(&path, b"same".to_vec(), time, store).await?;
+        }
+        old.store(store).await?;
+
+        // Nothing of the new revision is stored
+        let new = &mut old.clone();
+        new.basic_mv(&["docs".into()], &["archive".into()], time, store)
+            .await?;
+        new.write(&["archive".into(), "b.md".into()], vec![], time, store)
+            .await?;
+        new.rm(&["one.txt".into()], store).await?;
+        new.rm(&["two.txt".into()], store).await?;
+        new.write(&["three.txt".into()], b"same".to_vec(), time, store)
+            .await?;
+
+        let changes = diff(old, new, &ReadOnlyStore(store))
+            .map_ok(|change| (change.path.join("/"), change.r#type))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        assert_eq!(
+            changes,
+            vec![
+                ("archive".into(), PathChangeType::Added),
+                ("docs".into(), PathChangeType::Removed),
+                ("one.txt".into(), PathChangeType::Removed),
+                ("three.txt".into(), PathChangeType::Added),
+                ("two.txt".into(), PathChangeType::Removed),
+            ]
+        );
+
+        // Diffing doesn't mark any of the new revision as stored
+        let cid = new.store(store).await?;
+        let loaded = PublicDirectory::load(&cid, store).await?;
+        assert_eq!(
+            loaded
+                .read(&["archive".into(), "b.md".into()], store)
+                .await?,
+            b""
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn diff_of_identical_trees_is_empty() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let dir = &mut PublicDirectory::new_rc(Utc::now());
+        dir.write(&["a".into(), "b".into()], b"b".to_vec(), Utc::now(), store)
+            .await?;
+
+        let changes = diff(dir, dir, store).try_collect::<Vec<_>>().await?;
+
+        assert!(changes.is_empty());
+
+        Ok(())
+    }
+    #[async_std::test]
+    async fn moves_are_detected_whether_or_not_nodes_were_stored() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let time = Utc::now();
+        let old = &mut PublicDirectory::new_rc(time);
+        old.write(&["docs".into(), "a.md".into()], b"a".to_vec(), time, store)
+            .await?;
+
+        let new = &mut old.clone();
+        new.basic_mv(&["docs".into()], &["archive".into()], time, store)
+            .await?;
+        new.store(store).await?;
+
+        let changes = diff(old, new, store).try_collect::<Vec<_>>().await?;
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, vec!["archive".to_string()]);
+        assert_eq!(
+            changes[0].r#type,
+            PathChangeType::Moved {
+                from: vec!["docs".into()]
+            }
+        );
+
+        Ok(())
+    }
+}
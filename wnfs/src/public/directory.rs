@@ -1,26 +1,30 @@
 //! Public fs directory node.
 
 use super::{
-    PublicDirectorySerializable, PublicFile, PublicLink, PublicNode, PublicNodeSerializable,
+    Clock, PublicDirectorySerializable, PublicFile, PublicLink, PublicNode, PublicNodeHistory,
+    PublicNodeSerializable, entries::PublicDirectoryEntries,
 };
 use crate::{
-    SearchResult, WNFS_VERSION,
+    RevisionSelector, SearchResult, WNFS_VERSION,
+    conflict::{
+        Conflict, ConflictPolicy, ConflictResolution, Replica, TieBreakPolicy, conflict_copy_name,
+    },
     error::FsError,
     is_readable_wnfs_version,
+    merge::MergeDriver,
     traits::Id,
     utils::{self, OnceCellDebug},
+    walk::{WalkEntry, WalkOptions, walk_tree},
 };
 use anyhow::{Result, bail, ensure};
 use async_once_cell::OnceCell;
 use async_recursion::async_recursion;
 use chrono::{DateTime, Utc};
-use std::{
-    cmp::Ordering,
-    collections::{BTreeMap, BTreeSet, btree_map::Entry},
-};
+use futures::{StreamExt, TryStreamExt, stream};
+use std::{cmp::Ordering, collections::BTreeSet};
 use wnfs_common::{
-    BlockStore, Cid, Metadata, NodeType, Storable,
-    utils::{Arc, boxed_fut, error},
+    BlockStore, Cid, LoadIpld, Metadata, NodeType, Storable,
+    utils::{Arc, BoxStream, boxed_fut, error},
 };
 
 //--------------------------------------------------------------------------------------------------
@@ -42,19 +46,20 @@ use wnfs_common::{
 pub struct PublicDirectory {
     persisted_as: OnceCell<Cid>,
     pub(crate) metadata: Metadata,
-    pub(crate) userland: BTreeMap<String, PublicLink>,
+    pub(crate) userland: PublicDirectoryEntries,
     pub(crate) previous: BTreeSet<Cid>,
 }
 
 /// Different types of reconciliation results we can detect
+///
+/// Private reconciliation reports conflicts between private nodes, see
+/// [`PrivateNode::search_latest_reconciliation`](crate::private::PrivateNode::search_latest_reconciliation).
 #[derive(Debug, Clone)]
-pub enum Reconciliation {
-    /// A merge was necessary, there was a conflict and we had to tie-break on given list of file paths.
-    /// If the list of file paths is empty, then we were able to simply merge directories together and
-    /// there were no destructive conflicts.
-    Merged {
-        file_tie_breaks: BTreeSet<Vec<String>>,
-    },
+pub enum Reconciliation<N = PublicNode> {
+    /// A merge was necessary. Any conflicts that came up were resolved by the conflict policy
+    /// and are listed here. If the list is empty, then we were able to simply merge directories
+    /// together and there were no destructive conflicts.
+    Merged { conflicts: Vec<Conflict<N>> },
     /// A merge wasn't necessary: We could update to the other node's state.
     FastForward,
     /// A merge wasn't necessary: The other node is already part of our history.
@@ -82,7 +87,7 @@ impl PublicDirectory {
         Self {
             persisted_as: OnceCell::new(),
             metadata: Metadata::new(time),
-            userland: BTreeMap::new(),
+            userland: PublicDirectoryEntries::new(),
             previous: BTreeSet::new(),
         }
     }
@@ -209,7 +214,7 @@ impl PublicDirectory {
         Ok(SearchResult::Found(working_dir))
     }
 
-    async fn get_leaf_dir_mut<'a>(
+    pub(crate) async fn get_leaf_dir_mut<'a>(
         self: &'a mut Arc<Self>,
         path_segments: &[String],
         store: &impl BlockStore,
@@ -251,8 +256,9 @@ impl PublicDirectory {
                 for segment in &path_segments[depth..] {
                     dir = Arc::make_mut(
                         dir.userland
-                            .entry(segment.to_string())
-                            .or_insert_with(|| PublicLink::with_dir(Self::new(time)))
+                            .get_or_insert_with(segment.to_string(), || {
+                                PublicLink::with_dir(Self::new(time))
+                            })
                             .resolve_value_mut(store)
                             .await
                             .unwrap()
@@ -361,9 +367,10 @@ impl PublicDirectory {
             .get_or_create_leaf_dir_mut(path, time, store)
             .await?
             .userland
-            .entry(filename.clone())
             // Create a file, if it doesn't exist yet
-            .or_insert_with(|| PublicLink::with_file(PublicFile::new(time)))
+            .get_or_insert_with(filename.clone(), || {
+                PublicLink::with_file(PublicFile::new(time))
+            })
             // Get a mutable ref out of the directory entry
             .resolve_value_mut(store)
             .await?
@@ -635,6 +642,104 @@ impl PublicDirectory {
         }
     }
 
+    /// Recursively walks the directory at given path, returning a stream of all
+    /// nodes below it together with their paths and metadata.
+    ///
+    /// Yielded paths include `path_segments`. See [`WalkOptions`] for configuring
+    /// the traversal order, depth limits, pruning and concurrency.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use futures::TryStreamExt;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     public::PublicDirectory,
+    ///     walk::WalkOptions,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let store = &MemoryBlockStore::new();
+    ///
+    ///     dir.write(&["pictures".into(), "cats".into(), "tabby.png".into()], vec![], Utc::now(), store)
+    ///         .await?;
+    ///
+    ///     let paths = dir
+    ///         .walk(&["pictures".into()], WalkOptions::new(), store)
+    ///         .await?
+    ///         .map_ok(|(path, _, _)| path.join("/"))
+    ///         .try_collect::<Vec<_>>()
+    ///         .await?;
+    ///
+    ///     assert_eq!(paths, vec!["pictures/cats", "pictures/cats/tabby.png"]);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn walk<'a>(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        options: WalkOptions,
+        store: &'a impl BlockStore,
+    ) -> Result<BoxStream<'a, Result<WalkEntry<PublicNode>>>> {
+        let root = if path_segments.is_empty() {
+            Arc::clone(self)
+        } else {
+            self.get_node(path_segments, store)
+                .await?
+                .ok_or(FsError::NotFound)?
+                .as_dir()?
+        };
+
+        Ok(Self::walk_from(
+            path_segments.to_vec(),
+            root,
+            options,
+            store,
+        ))
+    }
+
+    /// Walks the subtree of `root`, yielding paths prefixed with `prefix`.
+    pub(crate) fn walk_from<'a>(
+        prefix: Vec<String>,
+        root: Arc<Self>,
+        options: WalkOptions,
+        store: &'a impl BlockStore,
+    ) -> BoxStream<'a, Result<WalkEntry<PublicNode>>> {
+        walk_tree(
+            prefix,
+            PublicNode::Dir(root),
+            options,
+            move |node, concurrency| {
+                boxed_fut(async move {
+                    let PublicNode::Dir(dir) = node else {
+                        return Ok(Vec::new());
+                    };
+
+                    let entries = dir
+                        .userland
+                        .iter()
+                        .map(|(name, link)| (name.clone(), link.clone()))
+                        .collect::<Vec<_>>();
+
+                    stream::iter(entries)
+                        .map(|(name, link)| async move {
+                            let node = link.resolve_owned_value(store).await?;
+                            let metadata = node.get_metadata().clone();
+                            Ok((name, node, metadata))
+                        })
+                        .buffered(concurrency)
+                        .try_collect()
+                        .await
+                })
+            },
+        )
+    }
+
     /// Removes a file or directory from the directory.
     ///
     /// # Examples
@@ -753,24 +858,9 @@ impl PublicDirectory {
         time: DateTime<Utc>,
         store: &impl BlockStore,
     ) -> Result<()> {
-        let (path, filename) = utils::split_last(path_segments_to)?;
-        let mut removed_node = self.rm(path_segments_from, store).await?;
-
-        let SearchResult::Found(dir) = self.get_leaf_dir_mut(path, store).await? else {
-            bail!(FsError::NotFound);
-        };
-
-        ensure!(
-            !dir.userland.contains_key(filename),
-            FsError::FileAlreadyExists
-        );
-
-        removed_node.upsert_mtime(time);
-
-        dir.userland
-            .insert(filename.clone(), PublicLink::new(removed_node));
-
-        Ok(())
+        let removed_node = self.rm(path_segments_from, store).await?;
+        self.attach(removed_node, path_segments_to, time, store)
+            .await
     }
 
     /// Copies a file or directory from one path to another.
@@ -824,11 +914,22 @@ impl PublicDirectory {
         time: DateTime<Utc>,
         store: &impl BlockStore,
     ) -> Result<()> {
-        let (path, filename) = utils::split_last(path_segments_to)?;
-        let Some(mut node) = self.get_node(path_segments_from, store).await?.cloned() else {
+        let Some(node) = self.get_node(path_segments_from, store).await?.cloned() else {
             bail!(FsError::NotFound);
         };
 
+        self.attach(node, path_segments_to, time, store).await
+    }
+
+    /// Attaches a node at given path. Nothing may exist at that path yet.
+    pub(crate) async fn attach(
+        self: &mut Arc<Self>,
+        mut node: PublicNode,
+        path_segments: &[String],
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let (path, filename) = utils::split_last(path_segments)?;
         let SearchResult::Found(dir) = self.get_leaf_dir_mut(path, store).await? else {
             bail!(FsError::NotFound);
         };
@@ -845,6 +946,117 @@ impl PublicDirectory {
         Ok(())
     }
 
+    /// Restores the node at given path to an earlier revision, e.g. one retrieved from
+    /// a [`PublicNodeHistory`](super::PublicNodeHistory).
+    ///
+    /// This doesn't rewind history: The restored content and metadata are written as a
+    /// new revision on top of the current one. An empty path restores this directory itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     common::{MemoryBlockStore, Storable},
+    ///     public::{PublicDirectory, PublicNodeHistory},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let root = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let path = &["notes.md".into()];
+    ///
+    ///     root.write(path, b"draft".to_vec(), Utc::now(), store).await?;
+    ///     root.store(store).await?;
+    ///     root.write(path, b"oops".to_vec(), Utc::now(), store).await?;
+    ///
+    ///     let history = PublicNodeHistory::of_path(root, path, store).await?;
+    ///     let draft = history.nth_previous(0, store).await?.unwrap();
+    ///     root.restore(path, &draft, Utc::now(), store).await?;
+    ///
+    ///     assert_eq!(root.read(path, store).await?, b"draft");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn restore(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        revision: &PublicNode,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let Some((name, path)) = path_segments.split_last() else {
+            let mut node = PublicNode::Dir(Arc::clone(self));
+            node.restore_from(revision, time)?;
+            *self = node.as_dir()?;
+            return Ok(());
+        };
+
+        let SearchResult::Found(dir) = self.get_leaf_dir_mut(path, store).await? else {
+            bail!(FsError::NotFound);
+        };
+
+        let Some(node) = dir.lookup_node_mut(name, store).await? else {
+            bail!(FsError::NotFound);
+        };
+
+        node.restore_from(revision, time)
+    }
+
+    /// Restores the node at given path to the revision picked by `revision`.
+    ///
+    /// Like [`restore`](Self::restore), this writes a new revision with the old content and
+    /// metadata on top of the current one, so restoring can be undone the same way.
+    /// Fails with `FsError::RevisionNotFound` if the history doesn't go back far enough.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     RevisionSelector,
+    ///     common::{MemoryBlockStore, Storable},
+    ///     public::PublicDirectory,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let root = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let path = &["notes.md".into()];
+    ///
+    ///     root.write(path, b"draft".to_vec(), Utc::now(), store).await?;
+    ///     root.store(store).await?;
+    ///     root.write(path, b"oops".to_vec(), Utc::now(), store).await?;
+    ///
+    ///     root.restore_revision(path, RevisionSelector::Back(1), Utc::now(), store)
+    ///         .await?;
+    ///
+    ///     assert_eq!(root.read(path, store).await?, b"draft");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn restore_revision(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        revision: RevisionSelector,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let old = PublicNodeHistory::of_path(self, path_segments, store)
+            .await?
+            .select(revision, store)
+            .await?
+            .ok_or(FsError::RevisionNotFound)?;
+
+        self.restore(path_segments, &old, time, store).await
+    }
+
     /// Comparing the merkle clocks of this directory to the other directory
     pub async fn causal_compare(
         self: Arc<Self>,
@@ -863,12 +1075,126 @@ impl PublicDirectory {
     /// public WNFS directory and want to merge any possibly new changes into
     /// this directory.
     ///
+    /// Conflicts are resolved with the [`TieBreakPolicy`]. Use [`Self::reconcile_with`]
+    /// to choose a different policy.
+    ///
     /// The return value can give information about what exactly happened.
     /// See the documentation for the `Reconciliation` enum for more information.
     pub async fn reconcile(
         self: &mut Arc<Self>,
         other: &Arc<Self>,
         store: &impl BlockStore,
+    ) -> Result<Reconciliation> {
+        self.reconcile_with(other, &TieBreakPolicy, store).await
+    }
+
+    /// Reconcile this node with another node, resolving conflicts with given policy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     common::{MemoryBlockStore, Storable},
+    ///     conflict::{ConflictCopiesPolicy, ConflictResolution},
+    ///     public::{PublicDirectory, Reconciliation},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let path = &["notes.md".into()];
+    ///     let ours = &mut PublicDirectory::new_rc(Utc::now());
+    ///     ours.store(store).await?;
+    ///
+    ///     let theirs = &mut ours.clone();
+    ///     ours.write(path, b"ours".to_vec(), Utc::now(), store).await?;
+    ///     theirs.write(path, b"theirs".to_vec(), Utc::now(), store).await?;
+    ///
+    ///     let Reconciliation::Merged { conflicts } =
+    ///         ours.reconcile_with(theirs, &ConflictCopiesPolicy, store).await?
+    ///     else {
+    ///         unreachable!()
+    ///     };
+    ///
+    ///     assert_eq!(conflicts.len(), 1);
+    ///     assert_eq!(conflicts[0].resolution, ConflictResolution::KeepBoth);
+    ///     assert_eq!(ours.ls(&[], store).await?.len(), 2);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn reconcile_with(
+        self: &mut Arc<Self>,
+        other: &Arc<Self>,
+        policy: &impl ConflictPolicy<PublicNode>,
+        store: &impl BlockStore,
+    ) -> Result<Reconciliation> {
+        self.reconcile_merging(other, policy, None, store).await
+    }
+
+    /// Reconcile this node with another node, merging the contents of concurrently
+    /// modified files with given merge driver.
+    ///
+    /// The driver gets the contents of both files and of their common ancestor.
+    /// Files it merges successfully become merge nodes with the merged content and aren't
+    /// reported as conflicts. All other conflicts are resolved with given policy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     common::{MemoryBlockStore, Storable},
+    ///     conflict::TieBreakPolicy,
+    ///     merge::LineMergeDriver,
+    ///     public::{PublicDirectory, Reconciliation},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let path = &["todo.txt".into()];
+    ///     let ours = &mut PublicDirectory::new_rc(Utc::now());
+    ///     ours.write(path, b"milk\neggs\n".to_vec(), Utc::now(), store).await?;
+    ///     ours.store(store).await?;
+    ///
+    ///     let theirs = &mut ours.clone();
+    ///     ours.write(path, b"oat milk\neggs\n".to_vec(), Utc::now(), store).await?;
+    ///     theirs.write(path, b"milk\neggs\nbread\n".to_vec(), Utc::now(), store).await?;
+    ///
+    ///     let Reconciliation::Merged { conflicts } = ours
+    ///         .reconcile_with_driver(theirs, &TieBreakPolicy, &LineMergeDriver, store)
+    ///         .await?
+    ///     else {
+    ///         unreachable!()
+    ///     };
+    ///
+    ///     assert!(conflicts.is_empty());
+    ///     assert_eq!(ours.read(path, store).await?, b"oat milk\neggs\nbread\n");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn reconcile_with_driver(
+        self: &mut Arc<Self>,
+        other: &Arc<Self>,
+        policy: &impl ConflictPolicy<PublicNode>,
+        driver: &impl MergeDriver,
+        store: &impl BlockStore,
+    ) -> Result<Reconciliation> {
+        self.reconcile_merging(other, policy, Some(driver), store)
+            .await
+    }
+
+    async fn reconcile_merging(
+        self: &mut Arc<Self>,
+        other: &Arc<Self>,
+        policy: &impl ConflictPolicy<PublicNode>,
+        driver: Option<&dyn MergeDriver>,
+        store: &impl BlockStore,
     ) -> Result<Reconciliation> {
         let causal_order = self.clone().causal_compare(other.clone(), store).await?;
 
@@ -880,10 +1206,10 @@ impl PublicDirectory {
                 Reconciliation::FastForward
             }
             None => {
-                let mut file_tie_breaks = BTreeSet::new();
-                self.reconcile_helper(other, store, &[], &mut file_tie_breaks)
+                let mut conflicts = Vec::new();
+                self.reconcile_helper(other, policy, driver, store, &[], &mut conflicts)
                     .await?;
-                Reconciliation::Merged { file_tie_breaks }
+                Reconciliation::Merged { conflicts }
             }
         })
     }
@@ -893,9 +1219,11 @@ impl PublicDirectory {
     async fn reconcile_helper<'a>(
         self: &'a mut Arc<Self>,
         other: &'a Arc<Self>,
+        policy: &'a impl ConflictPolicy<PublicNode>,
+        driver: Option<&'a dyn MergeDriver>,
         store: &'a impl BlockStore,
         current_path: &[String],
-        file_tie_breaks: &mut BTreeSet<Vec<String>>,
+        conflicts: &mut Vec<Conflict<PublicNode>>,
     ) -> Result<()> {
         let our_cid = self.store(store).await?;
         let other_cid = other.store(store).await?;
@@ -916,12 +1244,16 @@ impl PublicDirectory {
 
         for (name, other_link) in other.userland.iter() {
             let other_node = other_link.resolve_value(store).await?;
-            match dir.userland.entry(name.clone()) {
-                Entry::Vacant(vacant) => {
-                    vacant.insert(other_link.clone());
+            let mut path = current_path.to_vec();
+            path.push(name.clone());
+
+            let copy = match dir.userland.get_mut(name) {
+                None => {
+                    dir.userland.insert(name.clone(), other_link.clone());
+                    continue;
                 }
-                Entry::Occupied(mut occupied) => {
-                    let our_node = occupied.get_mut().resolve_value_mut(store).await?;
+                Some(our_link) => {
+                    let our_node = our_link.resolve_value_mut(store).await?;
 
                     match our_node.causal_compare(other_node, store).await? {
                         Some(Ordering::Equal) => {
@@ -937,35 +1269,192 @@ impl PublicDirectory {
                         None => {}
                     };
 
-                    match (our_node, other_node) {
-                        (PublicNode::File(our_file), PublicNode::File(other_file)) => {
-                            if our_file.merge(other_file, store).await? {
-                                let mut path = current_path.to_vec();
-                                path.push(name.clone());
-                                file_tie_breaks.insert(path);
-                            }
+                    if let (PublicNode::Dir(dir), PublicNode::Dir(other_dir)) =
+                        (&mut *our_node, other_node)
+                    {
+                        dir.reconcile_helper(other_dir, policy, driver, store, &path, conflicts)
+                            .await?;
+                        continue;
+                    }
+
+                    if let (PublicNode::File(our_file), PublicNode::File(other_file)) =
+                        (&mut *our_node, other_node)
+                    {
+                        let our_content_cid = our_file.userland.resolve_cid(store).await?;
+                        let other_content_cid = other_file.userland.resolve_cid(store).await?;
+                        if our_content_cid == other_content_cid {
+                            // Both sides wrote the same content, so there's nothing to resolve
+                            our_file.merge(other_file, store).await?;
+                            continue;
                         }
-                        (node @ PublicNode::File(_), PublicNode::Dir(other_dir)) => {
-                            // directories have priority
-                            // we don't add previous links
-                            *node = PublicNode::Dir(other_dir.clone());
+                    }
+
+                    if let (
+                        Some(driver),
+                        PublicNode::File(our_file),
+                        PublicNode::File(other_file),
+                    ) = (driver, &mut *our_node, other_node)
+                    {
+                        if our_file
+                            .merge_contents(other_file, &path, driver, store)
+                            .await?
+                        {
+                            continue;
                         }
-                        (PublicNode::Dir(_), PublicNode::File(_)) => {
-                            // directories have priority, no changes necessary
+                    }
+
+                    let ours = our_node.clone();
+                    let resolution = policy.resolve(&path, &ours, other_node);
+                    let tie_break_winner = Self::tie_break_winner(&ours, other_node, store).await?;
+                    let winner = match resolution {
+                        ConflictResolution::KeepOurs => Replica::Ours,
+                        ConflictResolution::KeepTheirs => Replica::Theirs,
+                        ConflictResolution::TieBreak | ConflictResolution::KeepBoth => {
+                            tie_break_winner.unwrap_or(Replica::Ours)
                         }
-                        (PublicNode::Dir(dir), PublicNode::Dir(other_dir)) => {
-                            let mut path = current_path.to_vec();
-                            path.push(name.clone());
-                            dir.reconcile_helper(other_dir, store, &path, file_tie_breaks)
-                                .await?;
+                    };
+
+                    match (&mut *our_node, other_node) {
+                        (PublicNode::File(our_file), PublicNode::File(other_file)) => {
+                            our_file.merge(other_file, store).await?;
+
+                            if matches!(
+                                resolution,
+                                ConflictResolution::KeepOurs | ConflictResolution::KeepTheirs
+                            ) {
+                                let chosen = match (winner, &ours) {
+                                    (Replica::Ours, PublicNode::File(file)) => file,
+                                    _ => other_file,
+                                };
+                                let file = Arc::make_mut(our_file);
+                                file.userland.clone_from(&chosen.userland);
+                                file.metadata.clone_from(&chosen.metadata);
+                            }
+                        }
+                        (node, _) => {
+                            // We don't add previous links when replacing a node of a different type
+                            if winner == Replica::Theirs {
+                                *node = other_node.clone();
+                            }
                         }
                     }
+
+                    let copy = match (resolution, tie_break_winner) {
+                        (ConflictResolution::KeepBoth, Some(_)) => {
+                            let loser = match winner {
+                                Replica::Ours => other_node.clone(),
+                                Replica::Theirs => ours.clone(),
+                            };
+                            let copy_name = conflict_copy_name(name, &loser.store(store).await?);
+                            Some((copy_name, loser))
+                        }
+                        _ => None,
+                    };
+
+                    conflicts.push(Conflict {
+                        path,
+                        ours,
+                        theirs: other_node.clone(),
+                        resolution,
+                        winner,
+                        copy: copy.as_ref().map(|(copy_name, _)| {
+                            [current_path, std::slice::from_ref(copy_name)].concat()
+                        }),
+                    });
+
+                    copy
+                }
+            };
+
+            if let Some((copy_name, node)) = copy {
+                if !dir.userland.contains_key(&copy_name) {
+                    dir.userland.insert(copy_name, PublicLink::new(node));
                 }
             }
         }
 
         Ok(())
     }
+
+    /// Determines which of two conflicting nodes wins under the built-in tie-breaking rules.
+    ///
+    /// Directories win over files, and between files the one with the lower content hash wins.
+    /// Returns `None` if two files have the same content.
+    async fn tie_break_winner(
+        ours: &PublicNode,
+        theirs: &PublicNode,
+        store: &impl BlockStore,
+    ) -> Result<Option<Replica>> {
+        Ok(match (ours, theirs) {
+            (PublicNode::File(our_file), PublicNode::File(other_file)) => {
+                let our_content_cid = our_file.userland.resolve_cid(store).await?;
+                let other_content_cid = other_file.userland.resolve_cid(store).await?;
+                match our_content_cid
+                    .hash()
+                    .digest()
+                    .cmp(other_content_cid.hash().digest())
+                {
+                    Ordering::Greater => Some(Replica::Theirs),
+                    Ordering::Equal => None,
+                    Ordering::Less => Some(Replica::Ours),
+                }
+            }
+            (PublicNode::File(_), PublicNode::Dir(_)) => Some(Replica::Theirs),
+            (PublicNode::Dir(_), _) => Some(Replica::Ours),
+        })
+    }
+
+    /// Deserializes a directory, fetching its entries from the store if they were sharded.
+    ///
+    /// Unlike [`Storable::from_serializable`], this works for directories of any size.
+    pub async fn from_serializable_with_store(
+        cid: Option<&Cid>,
+        serializable: PublicNodeSerializable,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let serializable = Self::check_serializable(serializable)?;
+        let userland = PublicDirectoryEntries::from_serializable(
+            serializable.userland,
+            serializable.shards,
+            store,
+        )
+        .await?;
+
+        Ok(Self::from_parts(
+            cid,
+            serializable.metadata,
+            userland,
+            serializable.previous,
+        ))
+    }
+
+    fn check_serializable(
+        serializable: PublicNodeSerializable,
+    ) -> Result<PublicDirectorySerializable> {
+        let PublicNodeSerializable::Dir(serializable) = serializable else {
+            bail!(FsError::UnexpectedNodeType(NodeType::PublicFile));
+        };
+
+        if !is_readable_wnfs_version(&serializable.version) {
+            bail!(FsError::UnexpectedVersion(serializable.version))
+        }
+
+        Ok(serializable)
+    }
+
+    fn from_parts(
+        cid: Option<&Cid>,
+        metadata: Metadata,
+        userland: PublicDirectoryEntries,
+        previous: Vec<Cid>,
+    ) -> Self {
+        Self {
+            persisted_as: cid.cloned().map(OnceCell::new_with).unwrap_or_default(),
+            metadata,
+            userland,
+            previous: previous.into_iter().collect(),
+        }
+    }
 }
 
 impl std::fmt::Debug for PublicDirectory {
@@ -1023,47 +1512,44 @@ impl Storable for PublicDirectory {
     type Serializable = PublicNodeSerializable;
 
     async fn to_serializable(&self, store: &impl BlockStore) -> Result<Self::Serializable> {
-        let userland = {
-            let mut map = BTreeMap::new();
-            for (name, link) in self.userland.iter() {
-                // Boxing the future due to recursion
-                map.insert(name.clone(), boxed_fut(link.resolve_cid(store)).await?);
-            }
-            map
-        };
+        let (userland, shards) = self.userland.to_serializable(store).await?;
 
         Ok(PublicNodeSerializable::Dir(PublicDirectorySerializable {
             version: WNFS_VERSION,
             metadata: self.metadata.clone(),
             userland,
+            shards,
             previous: self.previous.iter().cloned().collect(),
+            clock: Clock::new(store).index(&self.previous).await?,
         }))
     }
 
+    /// Deserializes a directory without a block store.
+    ///
+    /// This fails with [`FsError::InvalidDeserialization`] for directories with more than
+    /// [`PUBLIC_DIRECTORY_SHARDING_THRESHOLD`](super::PUBLIC_DIRECTORY_SHARDING_THRESHOLD)
+    /// entries, since their listing is stored in separate blocks. Use
+    /// [`Self::from_serializable_with_store`] or [`Storable::load`] instead.
     async fn from_serializable(
         cid: Option<&Cid>,
         serializable: Self::Serializable,
     ) -> Result<Self> {
-        let PublicNodeSerializable::Dir(serializable) = serializable else {
-            bail!(FsError::UnexpectedNodeType(NodeType::PublicFile));
-        };
+        let serializable = Self::check_serializable(serializable)?;
+        let userland =
+            PublicDirectoryEntries::from_inline(serializable.userland, serializable.shards)?;
 
-        if !is_readable_wnfs_version(&serializable.version) {
-            bail!(FsError::UnexpectedVersion(serializable.version))
-        }
-
-        let userland = serializable
-            .userland
-            .into_iter()
-            .map(|(name, cid)| (name, PublicLink::from_cid(cid)))
-            .collect();
-
-        Ok(Self {
-            persisted_as: cid.cloned().map(OnceCell::new_with).unwrap_or_default(),
-            metadata: serializable.metadata,
+        Ok(Self::from_parts(
+            cid,
+            serializable.metadata,
             userland,
-            previous: serializable.previous.iter().cloned().collect(),
-        })
+            serializable.previous,
+        ))
+    }
+
+    async fn load(cid: &Cid, store: &impl BlockStore) -> Result<Self> {
+        let bytes = store.get_block(cid).await?;
+        let serializable = PublicNodeSerializable::decode_ipld(cid, bytes)?;
+        Self::from_serializable_with_store(Some(cid), serializable, store).await
     }
 
     fn persisted_as(&self) -> Option<&OnceCell<Cid>> {
@@ -1078,6 +1564,12 @@ impl Storable for PublicDirectory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        conflict::{ConflictCopiesPolicy, LastWriterWinsPolicy, PreferReplicaPolicy},
+        merge::LineMergeDriver,
+        walk::WalkOrder,
+    };
+    use chrono::TimeZone;
     use ipld_core::ipld::Ipld;
     use testresult::TestResult;
     use wnfs_common::MemoryBlockStore;
@@ -1507,6 +1999,292 @@ mod tests {
 
         Ok(())
     }
+
+    async fn forked_writes(
+        ours_content: &[u8],
+        theirs_content: &[u8],
+        store: &impl BlockStore,
+    ) -> Result<(Arc<PublicDirectory>, Arc<PublicDirectory>)> {
+        let path = &["notes.md".into()];
+        let ours = &mut PublicDirectory::new_rc(Utc.timestamp_opt(0, 0).unwrap());
+        ours.store(store).await?;
+
+        let theirs = &mut Arc::clone(ours);
+        ours.write(
+            path,
+            ours_content.to_vec(),
+            Utc.timestamp_opt(20, 0).unwrap(),
+            store,
+        )
+        .await?;
+        theirs
+            .write(
+                path,
+                theirs_content.to_vec(),
+                Utc.timestamp_opt(10, 0).unwrap(),
+                store,
+            )
+            .await?;
+
+        Ok((Arc::clone(ours), Arc::clone(theirs)))
+    }
+
+    #[async_std::test]
+    async fn reconcile_with_policies_resolves_file_conflicts() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let path = &["notes.md".to_string()];
+
+        let (mut ours, theirs) = forked_writes(b"ours", b"theirs", store).await?;
+        ours.reconcile_with(&theirs, &PreferReplicaPolicy(Replica::Theirs), store)
+            .await?;
+        assert_eq!(ours.read(path, store).await?, b"theirs");
+        assert_eq!(ours.get_previous().len(), 2);
+
+        let (mut ours, theirs) = forked_writes(b"ours", b"theirs", store).await?;
+        ours.reconcile_with(&theirs, &LastWriterWinsPolicy, store)
+            .await?;
+        assert_eq!(ours.read(path, store).await?, b"ours");
+
+        let (mut ours, theirs) = forked_writes(b"ours", b"theirs", store).await?;
+        let policy = |path: &[String], _: &PublicNode, _: &PublicNode| {
+            if path.last().is_some_and(|name| name.ends_with(".md")) {
+                ConflictResolution::KeepTheirs
+            } else {
+                ConflictResolution::TieBreak
+            }
+        };
+        let reconciliation = ours.reconcile_with(&theirs, &policy, store).await?;
+        assert_eq!(ours.read(path, store).await?, b"theirs");
+
+        let Reconciliation::Merged { conflicts } = reconciliation else {
+            panic!("Expected a merge, got {reconciliation:?}");
+        };
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, path);
+        assert_eq!(conflicts[0].resolution, ConflictResolution::KeepTheirs);
+        assert_eq!(conflicts[0].winner, Replica::Theirs);
+        assert_eq!(conflicts[0].copy, None);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reconcile_doesnt_report_identical_writes() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let path = &["notes.md".to_string()];
+
+        let (mut ours, theirs) = forked_writes(b"same", b"same", store).await?;
+        let reconciliation = ours
+            .reconcile_with(&theirs, &ConflictCopiesPolicy, store)
+            .await?;
+
+        assert!(
+            matches!(reconciliation, Reconciliation::Merged { conflicts } if conflicts.is_empty())
+        );
+        assert_eq!(ours.read(path, store).await?, b"same");
+        assert_eq!(ours.ls(&[], store).await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reconcile_with_conflict_copies_is_commutative() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let (ours, theirs) = forked_writes(b"ours", b"theirs", store).await?;
+
+        let one_way = &mut Arc::clone(&ours);
+        let reconciliation = one_way
+            .reconcile_with(&theirs, &ConflictCopiesPolicy, store)
+            .await?;
+        let other_way = &mut Arc::clone(&theirs);
+        other_way
+            .reconcile_with(&ours, &ConflictCopiesPolicy, store)
+            .await?;
+
+        assert_eq!(one_way.store(store).await?, other_way.store(store).await?);
+
+        let Reconciliation::Merged { conflicts } = reconciliation else {
+            panic!("Expected a merge, got {reconciliation:?}");
+        };
+        let copy = conflicts[0].copy.clone().expect("Expected a conflict copy");
+        assert!(copy[0].starts_with("notes (conflict "));
+
+        let mut contents = vec![
+            one_way.read(&["notes.md".into()], store).await?,
+            one_way.read(&copy, store).await?,
+        ];
+        contents.sort();
+        assert_eq!(contents, vec![b"ours".to_vec(), b"theirs".to_vec()]);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reconcile_with_driver_merges_file_contents() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let path = &["notes.md".into()];
+        let time = |secs| Utc.timestamp_opt(secs, 0).unwrap();
+        let base = &mut PublicDirectory::new_rc(time(0));
+        base.write(path, b"a\nb\nc\n".to_vec(), time(0), store)
+            .await?;
+        base.store(store).await?;
+
+        let ours = &mut Arc::clone(base);
+        ours.write(path, b"A\nb\nc\n".to_vec(), time(10), store)
+            .await?;
+        let theirs = &mut Arc::clone(base);
+        theirs
+            .write(path, b"a\nb\nC\n".to_vec(), time(20), store)
+            .await?;
+
+        let one_way = &mut Arc::clone(ours);
+        let reconciliation = one_way
+            .reconcile_with_driver(theirs, &TieBreakPolicy, &LineMergeDriver, store)
+            .await?;
+        let other_way = &mut Arc::clone(theirs);
+        other_way
+            .reconcile_with_driver(ours, &TieBreakPolicy, &LineMergeDriver, store)
+            .await?;
+
+        assert!(
+            matches!(reconciliation, Reconciliation::Merged { conflicts } if conflicts.is_empty())
+        );
+        assert_eq!(one_way.store(store).await?, other_way.store(store).await?);
+        assert_eq!(one_way.read(path, store).await?, b"A\nb\nC\n");
+        assert_eq!(
+            one_way
+                .get_node(path, store)
+                .await?
+                .unwrap()
+                .get_previous()
+                .len(),
+            2
+        );
+
+        // Overlapping changes fall back to the conflict policy
+        theirs
+            .write(path, b"a2\nb\nC\n".to_vec(), time(30), store)
+            .await?;
+        let Reconciliation::Merged { conflicts } = ours
+            .reconcile_with_driver(theirs, &ConflictCopiesPolicy, &LineMergeDriver, store)
+            .await?
+        else {
+            panic!("Expected a merge");
+        };
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(ours.ls(&[], store).await?.len(), 2);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reconcile_reports_file_directory_conflicts() -> TestResult {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::new();
+        let ours = &mut PublicDirectory::new_rc(time);
+        ours.store(store).await?;
+
+        let theirs = &mut Arc::clone(ours);
+        ours.write(&["a".into()], b"file".to_vec(), time, store)
+            .await?;
+        theirs.mkdir(&["a".into(), "b".into()], time, store).await?;
+
+        let Reconciliation::Merged { conflicts } = ours.reconcile(theirs, store).await? else {
+            panic!("Expected a merge");
+        };
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].winner, Replica::Theirs);
+        assert!(ours.get_node(&["a".into()], store).await?.unwrap().is_dir());
+
+        Ok(())
+    }
+
+    async fn walk_paths(
+        dir: &Arc<PublicDirectory>,
+        options: WalkOptions,
+        store: &impl BlockStore,
+    ) -> Result<Vec<String>> {
+        dir.walk(&[], options, store)
+            .await?
+            .map_ok(|(path, _, _)| path.join("/"))
+            .try_collect()
+            .await
+    }
+
+    #[async_std::test]
+    async fn walk_respects_order_depth_and_pruning() -> TestResult {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::new();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+        for path in ["a/b/c.txt", "a/d.txt", "e/f.txt", "g.txt"] {
+            let path = path.split('/').map(String::from).collect::<Vec<_>>();
+            root_dir.write(&path, vec![], time, store).await?;
+        }
+
+        // Make sure walks work on directories that have to be loaded from the store
+        let root_dir =
+            &Arc::new(PublicDirectory::load(&root_dir.store(store).await?, store).await?);
+
+        assert_eq!(
+            walk_paths(root_dir, WalkOptions::new(), store).await?,
+            ["a", "a/b", "a/b/c.txt", "a/d.txt", "e", "e/f.txt", "g.txt"]
+        );
+
+        assert_eq!(
+            walk_paths(
+                root_dir,
+                WalkOptions::new().order(WalkOrder::BreadthFirst),
+                store
+            )
+            .await?,
+            ["a", "e", "g.txt", "a/b", "a/d.txt", "e/f.txt", "a/b/c.txt"]
+        );
+
+        assert_eq!(
+            walk_paths(root_dir, WalkOptions::new().max_depth(1), store).await?,
+            ["a", "e", "g.txt"]
+        );
+
+        assert_eq!(
+            walk_paths(
+                root_dir,
+                WalkOptions::new()
+                    .concurrency(1)
+                    .prune(|path, _| path == ["a"]),
+                store
+            )
+            .await?,
+            ["a", "e", "e/f.txt", "g.txt"]
+        );
+
+        let entries = root_dir
+            .walk(&["a".into()], WalkOptions::new(), store)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].0, ["a", "b"]);
+        assert!(entries[0].1.is_dir());
+        assert_eq!(entries[2].0, ["a", "d.txt"]);
+        assert!(entries[2].1.is_file());
+        assert_eq!(&entries[2].2, entries[2].1.get_metadata());
+
+        assert!(
+            root_dir
+                .walk(&["g.txt".into()], WalkOptions::new(), store)
+                .await
+                .is_err()
+        );
+        assert!(
+            root_dir
+                .walk(&["x".into()], WalkOptions::new(), store)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -1517,6 +2295,7 @@ mod proptests {
         collection::{btree_map, vec},
         prelude::*,
     };
+    use std::collections::BTreeMap;
     use test_strategy::proptest;
     use wnfs_common::MemoryBlockStore;
 
@@ -1540,15 +2319,12 @@ mod proptests {
             btree_map(vec(simple_string(), 1..10), simple_string(), 0..40),
         )
             .prop_map(|(mut files, dirs)| {
-                files = files
-                    .into_iter()
-                    .filter(|(file_path, _)| {
-                        // We filter out file paths that are prefixes of directory paths in advance
-                        !dirs
-                            .iter()
-                            .any(|(dir_path, _)| dir_path.starts_with(&file_path))
-                    })
-                    .collect();
+                files.retain(|file_path, _| {
+                    // We filter out file paths that are prefixes of directory paths in advance
+                    !dirs
+                        .iter()
+                        .any(|(dir_path, _)| dir_path.starts_with(file_path))
+                });
                 FileSystem { files, dirs }
             })
             .prop_filter("file overwritten by directory", valid_fs)
@@ -1563,12 +2339,12 @@ mod proptests {
             // File paths must not be prefixes of directory paths
             !fs.dirs
                 .iter()
-                .any(|(dir_path, _)| dir_path.starts_with(&file_path))
+                .any(|(dir_path, _)| dir_path.starts_with(file_path))
                 // file paths must not be prefixes of other file paths
                 && !fs
                     .files
                     .iter()
-                    .any(|(other_path, _)| file_path != other_path && other_path.starts_with(&file_path))
+                    .any(|(other_path, _)| file_path != other_path && other_path.starts_with(file_path))
         })
     }
 
@@ -1592,9 +2368,10 @@ mod proptests {
             dir.get_or_create_leaf_dir_mut(path, time, store)
                 .await?
                 .userland
-                .entry(filename.clone())
-                // Create a file, if it doesn't exist yet
-                .or_insert_with(|| PublicLink::with_dir(PublicDirectory::new(time)))
+                // Create a directory, if it doesn't exist yet
+                .get_or_insert_with(filename.clone(), || {
+                    PublicLink::with_dir(PublicDirectory::new(time))
+                })
                 // Get a mutable ref out of the directory entry
                 .resolve_value_mut(store)
                 .await?
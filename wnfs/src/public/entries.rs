@@ -0,0 +1,486 @@
+use super::PublicLink;
+use crate::error::FsError;
+use anyhow::{Result, bail};
+use async_once_cell::OnceCell;
+use std::{
+    collections::{BTreeMap, BTreeSet, btree_map},
+    fmt::Debug,
+};
+use wnfs_common::{
+    BlockStore, Cid, Storable,
+    utils::{Arc, boxed_fut},
+};
+use wnfs_hamt::Node;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of entries a public directory can hold before its listing gets stored
+/// as a HAMT instead of being inlined into the directory block.
+pub const PUBLIC_DIRECTORY_SHARDING_THRESHOLD: usize = 512;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The root node of a HAMT mapping entry names to the CIDs of public nodes.
+type EntriesHamt = Arc<Node<String, Cid>>;
+
+/// The entries of a public directory.
+///
+/// Small directories serialize their entries inline, as part of the directory block.
+/// Once a directory grows beyond [`PUBLIC_DIRECTORY_SHARDING_THRESHOLD`] entries, the listing
+/// is stored as a HAMT instead. The HAMT the listing was last loaded from or stored as is kept
+/// around, so storing a new revision only needs to re-write the HAMT nodes on the paths to
+/// changed entries.
+///
+/// Loading a sharded listing reads the whole HAMT once, and keeps a name to CID mapping
+/// of all entries in memory. The kept HAMT itself only loads nodes on the paths to changed
+/// entries when storing.
+pub(crate) struct PublicDirectoryEntries {
+    map: BTreeMap<String, PublicLink>,
+    /// The HAMT this listing was last loaded from or stored as, if any.
+    hamt: Option<EntriesHamt>,
+    /// Names of entries that changed relative to `hamt`.
+    changed: BTreeSet<String>,
+    /// The HAMT produced by the last store, if the listing was stored in sharded form.
+    stored: OnceCell<(Cid, EntriesHamt)>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PublicDirectoryEntries {
+    /// Creates an empty, unsharded listing.
+    pub(crate) fn new() -> Self {
+        Self {
+            map: BTreeMap::new(),
+            hamt: None,
+            changed: BTreeSet::new(),
+            stored: OnceCell::new(),
+        }
+    }
+
+    /// Whether this listing is going to be stored as a HAMT.
+    pub(crate) fn is_sharded(&self) -> bool {
+        self.map.len() > PUBLIC_DIRECTORY_SHARDING_THRESHOLD
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&PublicLink> {
+        self.map.get(name)
+    }
+
+    pub(crate) fn contains_key(&self, name: &str) -> bool {
+        self.map.contains_key(name)
+    }
+
+    pub(crate) fn iter(&self) -> btree_map::Iter<'_, String, PublicLink> {
+        self.map.iter()
+    }
+
+    /// Gets a mutable reference to an entry.
+    ///
+    /// The entry is assumed to change.
+    pub(crate) fn get_mut(&mut self, name: &str) -> Option<&mut PublicLink> {
+        if self.map.contains_key(name) {
+            self.invalidate(name);
+        }
+
+        self.map.get_mut(name)
+    }
+
+    /// Gets a mutable reference to an entry, inserting the result of `default` if it's missing.
+    pub(crate) fn get_or_insert_with(
+        &mut self,
+        name: String,
+        default: impl FnOnce() -> PublicLink,
+    ) -> &mut PublicLink {
+        self.invalidate(&name);
+        self.map.entry(name).or_insert_with(default)
+    }
+
+    pub(crate) fn insert(&mut self, name: String, link: PublicLink) -> Option<PublicLink> {
+        self.invalidate(&name);
+        self.map.insert(name, link)
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> Option<PublicLink> {
+        let removed = self.map.remove(name)?;
+        self.invalidate(name);
+        Some(removed)
+    }
+
+    /// Records that the entry `name` changed since the listing was last stored.
+    fn invalidate(&mut self, name: &str) {
+        // Changes are tracked relative to the latest stored HAMT.
+        if let Some((_, stored)) = self.stored.take() {
+            self.hamt = Some(stored);
+            self.changed.clear();
+        }
+
+        // Without a HAMT to update, the whole listing gets written anyways.
+        if self.hamt.is_some() {
+            self.changed.insert(name.to_string());
+        }
+    }
+
+    /// Serializes the entries.
+    ///
+    /// Small listings are returned inline as a map from names to CIDs.
+    /// Large listings are put into the block store as a HAMT, and only the root's CID is returned.
+    pub(crate) async fn to_serializable(
+        &self,
+        store: &impl BlockStore,
+    ) -> Result<(BTreeMap<String, Cid>, Option<Cid>)> {
+        if !self.is_sharded() {
+            let mut map = BTreeMap::new();
+            for (name, link) in self.map.iter() {
+                // Boxing the future due to recursion
+                map.insert(name.clone(), boxed_fut(link.resolve_cid(store)).await?);
+            }
+
+            return Ok((map, None));
+        }
+
+        let (cid, _) = self
+            .stored
+            .get_or_try_init::<anyhow::Error>(async {
+                let hamt = match &self.hamt {
+                    Some(hamt) => self.apply_changes(Arc::clone(hamt), store).await?,
+                    None => self.build_hamt(store).await?,
+                };
+
+                let cid = hamt.store(store).await?;
+                Ok((cid, hamt))
+            })
+            .await?;
+
+        Ok((BTreeMap::new(), Some(*cid)))
+    }
+
+    /// Updates a previously loaded or stored HAMT with all entries that changed since.
+    async fn apply_changes(
+        &self,
+        mut hamt: EntriesHamt,
+        store: &impl BlockStore,
+    ) -> Result<EntriesHamt> {
+        for name in self.changed.iter() {
+            match self.map.get(name) {
+                Some(link) => {
+                    let cid = boxed_fut(link.resolve_cid(store)).await?;
+                    hamt.set(name.clone(), cid, store).await?;
+                }
+                None => {
+                    hamt.remove(name, store).await?;
+                }
+            }
+        }
+
+        Ok(hamt)
+    }
+
+    async fn build_hamt(&self, store: &impl BlockStore) -> Result<EntriesHamt> {
+        let mut hamt = Arc::new(Node::default());
+        for (name, link) in self.map.iter() {
+            let cid = boxed_fut(link.resolve_cid(store)).await?;
+            hamt.set(name.clone(), cid, store).await?;
+        }
+
+        Ok(hamt)
+    }
+
+    /// Deserializes an inline listing.
+    ///
+    /// Fails if the listing was stored as a HAMT, since that requires a block store to load.
+    pub(crate) fn from_inline(entries: BTreeMap<String, Cid>, shards: Option<Cid>) -> Result<Self> {
+        if shards.is_some() {
+            bail!(FsError::InvalidDeserialization(
+                "Sharded directory listings need to be loaded from a block store".into()
+            ));
+        }
+
+        Ok(Self {
+            map: entries
+                .into_iter()
+                .map(|(name, cid)| (name, PublicLink::from_cid(cid)))
+                .collect(),
+            ..Self::new()
+        })
+    }
+
+    /// Deserializes entries, fetching the HAMT from the store if the listing was sharded.
+    pub(crate) async fn from_serializable(
+        entries: BTreeMap<String, Cid>,
+        shards: Option<Cid>,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let Some(root_cid) = shards else {
+            return Self::from_inline(entries, None);
+        };
+
+        // Listing all entries loads every HAMT node, so we list them from a separate copy
+        // to avoid keeping all nodes in memory alongside the listing.
+        let hamt = EntriesHamt::load(&root_cid, store).await?;
+        let listing = EntriesHamt::load(&root_cid, store).await?;
+
+        // We never write inline entries alongside a HAMT, but if there are any,
+        // they're not part of the HAMT yet.
+        let changed = entries.keys().cloned().collect();
+        let map = listing
+            .to_hashmap(store)
+            .await?
+            .into_iter()
+            .chain(entries)
+            .map(|(name, cid)| (name, PublicLink::from_cid(cid)))
+            .collect();
+
+        Ok(Self {
+            map,
+            hamt: Some(hamt),
+            changed,
+            stored: OnceCell::new(),
+        })
+    }
+}
+
+impl Default for PublicDirectoryEntries {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for PublicDirectoryEntries {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.map.fmt(f)
+    }
+}
+
+impl PartialEq for PublicDirectoryEntries {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl Clone for PublicDirectoryEntries {
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+            hamt: self.hamt.clone(),
+            changed: self.changed.clone(),
+            stored: self
+                .stored
+                .get()
+                .cloned()
+                .map(OnceCell::new_with)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a PublicDirectoryEntries {
+    type Item = (&'a String, &'a PublicLink);
+    type IntoIter = btree_map::Iter<'a, String, PublicLink>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::public::{PublicDirectory, PublicFile, PublicNode, PublicNodeSerializable};
+    use chrono::Utc;
+    use testresult::TestResult;
+    use wnfs_common::{LoadIpld, MemoryBlockStore};
+
+    async fn large_directory(
+        entry_count: usize,
+        store: &MemoryBlockStore,
+    ) -> Result<Arc<PublicDirectory>> {
+        let mut dir = PublicDirectory::new_rc(Utc::now());
+        let file = PublicFile::with_content(Utc::now(), b"Hello, World!".to_vec(), store).await?;
+        let file_cid = file.store(store).await?;
+
+        let entries = &mut Arc::make_mut(&mut dir).userland;
+        for i in 0..entry_count {
+            entries.insert(format!("file_{i}.txt"), PublicLink::from_cid(file_cid));
+        }
+
+        Ok(dir)
+    }
+
+    async fn load_serializable(
+        cid: &Cid,
+        store: &MemoryBlockStore,
+    ) -> Result<(BTreeMap<String, Cid>, Option<Cid>)> {
+        let bytes = store.get_block(cid).await?;
+        let PublicNodeSerializable::Dir(dir) = PublicNodeSerializable::decode_ipld(cid, bytes)?
+        else {
+            bail!("Expected a directory");
+        };
+
+        Ok((dir.userland, dir.shards))
+    }
+
+    #[async_std::test]
+    async fn small_directories_are_stored_inline() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let dir = large_directory(PUBLIC_DIRECTORY_SHARDING_THRESHOLD, store).await?;
+        assert!(!dir.userland.is_sharded());
+
+        let cid = dir.store(store).await?;
+        let (userland, shards) = load_serializable(&cid, store).await?;
+        assert_eq!(userland.len(), PUBLIC_DIRECTORY_SHARDING_THRESHOLD);
+        assert!(shards.is_none());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn large_directories_round_trip_through_a_hamt() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let entry_count = PUBLIC_DIRECTORY_SHARDING_THRESHOLD + 100;
+        let dir = large_directory(entry_count, store).await?;
+        assert!(dir.userland.is_sharded());
+
+        let cid = dir.store(store).await?;
+        let (userland, shards) = load_serializable(&cid, store).await?;
+        assert!(userland.is_empty());
+        assert!(shards.is_some());
+
+        let loaded = Arc::new(PublicDirectory::load(&cid, store).await?);
+        assert_eq!(loaded, dir);
+        assert_eq!(loaded.ls(&[], store).await?.len(), entry_count);
+        assert_eq!(
+            loaded.read(&["file_42.txt".into()], store).await?,
+            b"Hello, World!".to_vec()
+        );
+
+        // Loading through a node or an Arc works the same way.
+        let loaded_via_node = PublicNode::load(&cid, store).await?.as_dir()?;
+        assert_eq!(loaded_via_node, dir);
+        let loaded_via_arc = Arc::<PublicDirectory>::load(&cid, store).await?;
+        assert_eq!(loaded_via_arc, dir);
+
+        // Deserializing requires the store for sharded listings.
+        let serializable = PublicNodeSerializable::decode_ipld(&cid, store.get_block(&cid).await?)?;
+        let error = PublicNode::from_serializable(Some(&cid), serializable.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(FsError::InvalidDeserialization(_))
+        ));
+        let deserialized =
+            PublicNode::from_serializable_with_store(Some(&cid), serializable, store).await?;
+        assert_eq!(deserialized.as_dir()?, dir);
+
+        // Re-storing an unmodified directory gives the same CID.
+        assert_eq!(loaded.store(store).await?, cid);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn modified_sharded_directories_only_update_changed_entries() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let entry_count = PUBLIC_DIRECTORY_SHARDING_THRESHOLD + 100;
+        let dir = large_directory(entry_count, store).await?;
+        let cid = dir.store(store).await?;
+
+        let dir = &mut Arc::new(PublicDirectory::load(&cid, store).await?);
+        let time = Utc::now();
+        dir.write(&["new.txt".into()], b"New".to_vec(), time, store)
+            .await?;
+        dir.rm(&["file_0.txt".into()], store).await?;
+        assert_eq!(dir.userland.changed.len(), 2);
+
+        let cid = dir.store(store).await?;
+
+        // Changes after storing are tracked relative to the newly stored HAMT.
+        let next = &mut Arc::new(dir.prepare_next_revision().clone());
+        next.rm(&["file_1.txt".into()], store).await?;
+        assert_eq!(next.userland.changed.len(), 1);
+        let next_cid = next.store(store).await?;
+
+        let loaded = Arc::new(PublicDirectory::load(&cid, store).await?);
+        assert_eq!(loaded.ls(&[], store).await?.len(), entry_count);
+        assert_eq!(loaded.read(&["new.txt".into()], store).await?, b"New");
+        assert!(
+            loaded
+                .get_node(&["file_0.txt".into()], store)
+                .await?
+                .is_none()
+        );
+
+        let loaded = Arc::new(PublicDirectory::load(&next_cid, store).await?);
+        assert_eq!(loaded.ls(&[], store).await?.len(), entry_count - 1);
+        assert!(
+            loaded
+                .get_node(&["file_1.txt".into()], store)
+                .await?
+                .is_none()
+        );
+
+        // The incrementally updated HAMT is the same as one built from scratch.
+        let (stored_cid, _) = next.userland.stored.get().unwrap();
+        let from_scratch = loaded.userland.build_hamt(store).await?;
+        assert_eq!(from_scratch.store(store).await?, *stored_cid);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn shrinking_directories_get_inlined_again() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let dir = &mut large_directory(PUBLIC_DIRECTORY_SHARDING_THRESHOLD + 1, store).await?;
+        let cid = dir.store(store).await?;
+        assert!(load_serializable(&cid, store).await?.1.is_some());
+
+        dir.rm(&["file_0.txt".into()], store).await?;
+        assert!(!dir.userland.is_sharded());
+
+        let cid = dir.store(store).await?;
+        let (userland, shards) = load_serializable(&cid, store).await?;
+        assert_eq!(userland.len(), PUBLIC_DIRECTORY_SHARDING_THRESHOLD);
+        assert!(shards.is_none());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reconcile_merges_sharded_directories() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let time = Utc::now();
+        let entry_count = PUBLIC_DIRECTORY_SHARDING_THRESHOLD + 100;
+        let dir = &mut large_directory(entry_count, store).await?;
+        dir.store(store).await?;
+
+        let fork = &mut Arc::clone(dir);
+        fork.write(&["fork.txt".into()], b"fork".to_vec(), time, store)
+            .await?;
+        let fork_cid = fork.store(store).await?;
+        let fork = &Arc::new(PublicDirectory::load(&fork_cid, store).await?);
+
+        dir.write(&["ours.txt".into()], b"ours".to_vec(), time, store)
+            .await?;
+        dir.store(store).await?;
+
+        dir.reconcile(fork, store).await?;
+        let cid = dir.store(store).await?;
+
+        let loaded = Arc::new(PublicDirectory::load(&cid, store).await?);
+        assert_eq!(loaded.read(&["fork.txt".into()], store).await?, b"fork");
+        assert_eq!(loaded.read(&["ours.txt".into()], store).await?, b"ours");
+        assert_eq!(loaded.ls(&[], store).await?.len(), entry_count + 2);
+
+        Ok(())
+    }
+}
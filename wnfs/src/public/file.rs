@@ -1,8 +1,13 @@
 //! Public fs file node.
 
-use super::{PublicFileSerializable, PublicNodeSerializable};
+use super::{Clock, PublicFileSerializable, PublicNode, PublicNodeSerializable};
 use crate::{
-    WNFS_VERSION, error::FsError, is_readable_wnfs_version, traits::Id, utils::OnceCellDebug,
+    WNFS_VERSION,
+    error::FsError,
+    is_readable_wnfs_version,
+    merge::{MergeDriver, MergeOutcome},
+    traits::Id,
+    utils::OnceCellDebug,
 };
 use anyhow::{Result, anyhow, bail};
 use async_once_cell::OnceCell;
@@ -451,6 +456,25 @@ impl PublicFile {
         Ok(())
     }
 
+    /// Like `set_content`, but allows streaming in the content.
+    pub async fn set_content_streaming<'a>(
+        &mut self,
+        content: impl AsyncRead + CondSend + 'a,
+        time: DateTime<Utc>,
+        store: &'a impl BlockStore,
+    ) -> Result<()> {
+        let content_cid = FileBuilder::new()
+            .content_reader(FuturesAsyncReadCompatExt::compat(content))
+            .build()?
+            .store(store)
+            .await?;
+
+        self.metadata.upsert_mtime(time);
+        self.userland = Link::from_cid(content_cid);
+
+        Ok(())
+    }
+
     /// Gets the content cid of the file.
     pub async fn get_raw_content_cid(&self, store: &impl BlockStore) -> Cid {
         let content_cid: Result<Cid> = self.userland.resolve_cid(store).await;
@@ -543,6 +567,45 @@ impl PublicFile {
         // Returning true to indicate that we needed to tie-break
         Ok(true)
     }
+
+    /// Merges the other file into this one, combining their contents with given merge driver.
+    ///
+    /// The content of the files' common ancestor is passed to the driver as the base.
+    /// Like [`Self::merge`] this creates a merge node, but its content is the driver's result.
+    /// Returns `false` and leaves this file unchanged if the driver reported a conflict.
+    pub(crate) async fn merge_contents(
+        self: &mut Arc<Self>,
+        other: &Arc<Self>,
+        path: &[String],
+        driver: &dyn MergeDriver,
+        store: &impl BlockStore,
+    ) -> Result<bool> {
+        let base = PublicNode::File(Arc::clone(self))
+            .common_ancestor(&PublicNode::File(Arc::clone(other)), store)
+            .await?;
+        let base = match base {
+            Some(PublicNode::File(file)) => Some(file.get_content(store).await?),
+            _ => None,
+        };
+        let ours = self.get_content(store).await?;
+        let theirs = other.get_content(store).await?;
+
+        let MergeOutcome::Merged(content) = driver.merge(path, base.as_deref(), &ours, &theirs)
+        else {
+            return Ok(false);
+        };
+
+        let content_cid = FileBuilder::new()
+            .content_bytes(content)
+            .build()?
+            .store(store)
+            .await?;
+
+        self.merge(other, store).await?;
+        Arc::make_mut(self).userland = Link::from_cid(content_cid);
+
+        Ok(true)
+    }
 }
 
 impl std::fmt::Debug for PublicFile {
@@ -575,6 +638,7 @@ impl Storable for PublicFile {
             metadata: self.metadata.clone(),
             userland: self.userland.resolve_cid(store).await?,
             previous: self.previous.iter().cloned().collect(),
+            clock: Clock::new(store).index(&self.previous).await?,
         }))
     }
 
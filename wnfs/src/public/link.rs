@@ -2,7 +2,7 @@
 
 use super::{PublicDirectory, PublicFile, PublicNode};
 use anyhow::Result;
-use wnfs_common::{BlockStore, Cid, Link, utils::Arc};
+use wnfs_common::{BlockStore, Cid, Link, Storable, utils::Arc};
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
@@ -73,6 +73,14 @@ impl PublicLink {
         self.0.resolve_owned_value(store).await
     }
 
+    /// Gets the CID of the linked node, if it's known without storing the node.
+    pub(crate) fn get_cid(&self) -> Option<Cid> {
+        self.0
+            .get_cid()
+            .or_else(|| self.0.get_value()?.persisted_as()?.get())
+            .cloned()
+    }
+
     /// Compares two links for equality. Attempts to get them from store if they are not already cached.
     #[inline]
     pub async fn deep_eq(&self, other: &Self, store: &impl BlockStore) -> Result<bool> {
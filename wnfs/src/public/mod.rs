@@ -1,11 +1,17 @@
 //! Primitives for working with the public file system.
 
+mod diff;
 mod directory;
+mod entries;
 mod file;
 mod link;
 mod node;
+mod previous;
 
+pub use diff::*;
 pub use directory::*;
+pub use entries::PUBLIC_DIRECTORY_SHARDING_THRESHOLD;
 pub use file::*;
 pub use link::*;
 pub use node::*;
+pub use previous::*;
@@ -0,0 +1,526 @@
+//! An index over the causal history of public nodes.
+
+use super::PublicNodeSerializable;
+use crate::error::FsError;
+use anyhow::{Result, ensure};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+};
+use wnfs_common::{BlockStore, Cid, LoadIpld};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// An index into the causal history of a public node, stored next to its `previous` links.
+///
+/// The height of a node is one more than the highest height of its previous revisions,
+/// so heights strictly increase along history. The highest previous revision (tie-breaking on
+/// the lowest CID) is the node's *first parent*. First parents form a chain that visits every
+/// height down to the very first revision. The index adds two shortcuts into that chain:
+///
+/// - `jump` is a skip link to an earlier node on the chain. Jump targets are picked like in a
+///   skew-binary random access list, so the node at any height is found in a logarithmic
+///   number of steps.
+/// - `fork` links to the nearest earlier node on the chain at which other history may join,
+///   i.e. one with more than one previous revision, or one without an index.
+///
+/// Nodes without previous revisions have height zero and don't store an index.
+/// Nodes written before this index existed don't have one either,
+/// so their history is walked linearly instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockIndex {
+    pub(crate) height: u64,
+    pub(crate) jump: ClockLink,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) fork: Option<ClockLink>,
+}
+
+/// A link to an earlier revision, together with its height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockLink {
+    pub(crate) height: u64,
+    pub(crate) cid: Cid,
+}
+
+/// The parts of a stored public node that are relevant for its causal history.
+#[derive(Debug, Clone)]
+struct ClockNode {
+    previous: Vec<Cid>,
+    index: Option<ClockIndex>,
+}
+
+/// Caches the history nodes and heights that were looked up while answering causal queries.
+pub(crate) struct Clock<'a, B: BlockStore> {
+    store: &'a B,
+    nodes: BTreeMap<Cid, ClockNode>,
+    heights: BTreeMap<Cid, u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ClockNode {
+    /// Whether this node has previous revisions, but no index.
+    fn is_unindexed(&self) -> bool {
+        self.index.is_none() && !self.previous.is_empty()
+    }
+
+    /// Whether other history may join the first-parent chain at this node.
+    fn is_fork(&self) -> bool {
+        self.previous.len() > 1 || self.is_unindexed()
+    }
+}
+
+impl<'a, B: BlockStore> Clock<'a, B> {
+    pub(crate) fn new(store: &'a B) -> Self {
+        Self {
+            store,
+            nodes: BTreeMap::new(),
+            heights: BTreeMap::new(),
+        }
+    }
+
+    /// Computes the index for a new node with given previous revisions.
+    ///
+    /// Returns `None` for nodes without previous revisions.
+    pub(crate) async fn index(&mut self, previous: &BTreeSet<Cid>) -> Result<Option<ClockIndex>> {
+        let Some(parent) = self.first_parent_of(previous.iter().copied()).await? else {
+            return Ok(None);
+        };
+
+        let parent_node = self.get(&parent.cid).await?;
+
+        // Skew-binary jumps: If the parent's jump spans as many revisions as the jump after it,
+        // the new node jumps across both. Otherwise it jumps to its parent.
+        let parent_jump = self.jump_of(parent).await?;
+        let parent_jump_jump = self.jump_of(parent_jump).await?;
+        let jump =
+            if parent.height - parent_jump.height == parent_jump.height - parent_jump_jump.height {
+                parent_jump_jump
+            } else {
+                parent
+            };
+
+        let fork = if parent_node.is_fork() {
+            Some(parent)
+        } else {
+            parent_node.index.and_then(|index| index.fork)
+        };
+
+        Ok(Some(ClockIndex {
+            height: parent.height + 1,
+            jump,
+            fork,
+        }))
+    }
+
+    /// Returns the indexed height of given node, if it has one.
+    ///
+    /// Nodes without previous revisions have height zero, nodes without an index have none.
+    pub(crate) async fn indexed_height(&mut self, cid: &Cid) -> Result<Option<u64>> {
+        let node = self.get(cid).await?;
+        Ok(match node.index {
+            Some(index) => Some(index.height),
+            None if node.previous.is_empty() => Some(0),
+            None => None,
+        })
+    }
+
+    /// Returns the height of given node.
+    ///
+    /// For nodes without an index, this walks all of their history that isn't indexed.
+    pub(crate) async fn height(&mut self, cid: &Cid) -> Result<u64> {
+        if let Some(height) = self.indexed_height(cid).await? {
+            return Ok(height);
+        }
+
+        // Iterative post-order walk, so long histories don't overflow the stack
+        let mut stack = vec![(*cid, false)];
+        while let Some((cid, expanded)) = stack.pop() {
+            if self.heights.contains_key(&cid) {
+                continue;
+            }
+
+            if let Some(height) = self.indexed_height(&cid).await? {
+                self.heights.insert(cid, height);
+                continue;
+            }
+
+            let previous = self.get(&cid).await?.previous;
+            if expanded {
+                let height = previous
+                    .iter()
+                    .map(|cid| self.heights[cid])
+                    .max()
+                    .unwrap_or_default();
+                self.heights.insert(cid, height + 1);
+            } else {
+                stack.push((cid, true));
+                stack.extend(previous.into_iter().map(|cid| (cid, false)));
+            }
+        }
+
+        Ok(self.heights[cid])
+    }
+
+    /// Whether `ancestor` is a proper ancestor of `descendant`.
+    ///
+    /// Linear stretches of indexed history are skipped using jumps, so this is logarithmic
+    /// in the length of history, times the number of merges that need to be followed.
+    pub(crate) async fn is_ancestor(
+        &mut self,
+        ancestor: ClockLink,
+        descendant: &Cid,
+    ) -> Result<bool> {
+        let mut stack = vec![*descendant];
+        let mut visited = BTreeSet::new();
+
+        while let Some(cid) = stack.pop() {
+            if cid == ancestor.cid {
+                return Ok(cid != *descendant);
+            }
+
+            if !visited.insert(cid) {
+                continue;
+            }
+
+            let node = self.get(&cid).await?;
+            let Some(index) = node.index else {
+                // Without an index, all we can do is walk every previous revision
+                stack.extend(node.previous);
+                continue;
+            };
+
+            if index.height <= ancestor.height {
+                continue;
+            }
+
+            if node.previous.len() > 1 {
+                stack.extend(node.previous);
+                continue;
+            }
+
+            match index.fork {
+                Some(fork) if fork.height > ancestor.height => stack.push(fork.cid),
+                _ => {
+                    // No other history joins the chain above the ancestor's height,
+                    // so it's an ancestor exactly if it's on the chain.
+                    let link = ClockLink {
+                        height: index.height,
+                        cid,
+                    };
+                    if self.chain_at(link, ancestor.height).await? == ancestor.cid {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Finds the latest common ancestor of two nodes, which may be one of the nodes themselves.
+    ///
+    /// If neither node is an ancestor of the other, this searches both first-parent chains for
+    /// the highest node that's an ancestor of the other node, so it won't find common ancestors
+    /// that only live on side branches of both histories.
+    pub(crate) async fn common_ancestor(&mut self, a: &Cid, b: &Cid) -> Result<Option<Cid>> {
+        if a == b {
+            return Ok(Some(*a));
+        }
+
+        let a = ClockLink {
+            height: self.height(a).await?,
+            cid: *a,
+        };
+        let b = ClockLink {
+            height: self.height(b).await?,
+            cid: *b,
+        };
+
+        if a.height < b.height && self.is_ancestor(a, &b.cid).await? {
+            return Ok(Some(a.cid));
+        }
+
+        if b.height < a.height && self.is_ancestor(b, &a.cid).await? {
+            return Ok(Some(b.cid));
+        }
+
+        let ours = self.highest_common_on_chain(a, b).await?;
+        let theirs = self.highest_common_on_chain(b, a).await?;
+
+        Ok(ours
+            .into_iter()
+            .chain(theirs)
+            .max_by_key(|link| (link.height, Reverse(link.cid)))
+            .map(|link| link.cid))
+    }
+
+    /// Binary searches the first-parent chain of `chain` for the highest node that's an
+    /// ancestor of `other`. Being an ancestor is monotonic along the chain.
+    async fn highest_common_on_chain(
+        &mut self,
+        chain: ClockLink,
+        other: ClockLink,
+    ) -> Result<Option<ClockLink>> {
+        let Some(mut high) = other.height.checked_sub(1) else {
+            return Ok(None);
+        };
+        high = high.min(chain.height);
+        let mut low = 0;
+        let mut found = None;
+
+        while low <= high {
+            let height = low + (high - low) / 2;
+            let link = ClockLink {
+                height,
+                cid: self.chain_at(chain, height).await?,
+            };
+
+            if self.is_ancestor(link, &other.cid).await? {
+                found = Some(link);
+                low = height + 1;
+            } else if height == 0 {
+                break;
+            } else {
+                high = height - 1;
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Returns the node at given height on the first-parent chain of `from`.
+    ///
+    /// Fails with `FsError::InvalidClockIndex` if the chain doesn't strictly descend,
+    /// which can only happen for nodes with made-up indices.
+    async fn chain_at(&mut self, from: ClockLink, height: u64) -> Result<Cid> {
+        let mut current = from;
+        while current.height > height {
+            let node = self.get(&current.cid).await?;
+            let next = match node.index {
+                Some(ClockIndex { jump, .. }) if jump.height >= height => jump,
+                _ => self
+                    .first_parent_of(node.previous.into_iter())
+                    .await?
+                    .ok_or(FsError::InvalidClockIndex(current.cid))?,
+            };
+
+            ensure!(
+                next.height < current.height,
+                FsError::InvalidClockIndex(current.cid)
+            );
+            current = next;
+        }
+
+        Ok(current.cid)
+    }
+
+    /// Returns the highest of given previous revisions, tie-breaking on the lowest CID.
+    async fn first_parent_of(
+        &mut self,
+        previous: impl Iterator<Item = Cid>,
+    ) -> Result<Option<ClockLink>> {
+        let mut first_parent: Option<ClockLink> = None;
+        for cid in previous {
+            let height = self.height(&cid).await?;
+            let is_first = first_parent
+                .is_none_or(|first| (height, Reverse(cid)) > (first.height, Reverse(first.cid)));
+
+            if is_first {
+                first_parent = Some(ClockLink { height, cid });
+            }
+        }
+
+        Ok(first_parent)
+    }
+
+    /// Returns the jump target of the node at given link, or the link itself for nodes without an index.
+    async fn jump_of(&mut self, link: ClockLink) -> Result<ClockLink> {
+        Ok(match self.get(&link.cid).await?.index {
+            Some(index) => index.jump,
+            None => link,
+        })
+    }
+
+    async fn get(&mut self, cid: &Cid) -> Result<ClockNode> {
+        if let Some(node) = self.nodes.get(cid) {
+            return Ok(node.clone());
+        }
+
+        let bytes = self.store.get_block(cid).await?;
+        let node = match PublicNodeSerializable::decode_ipld(cid, bytes)? {
+            PublicNodeSerializable::File(file) => ClockNode {
+                previous: file.previous,
+                index: file.clock,
+            },
+            PublicNodeSerializable::Dir(dir) => ClockNode {
+                previous: dir.previous,
+                index: dir.clock,
+            },
+        };
+
+        self.nodes.insert(*cid, node.clone());
+        Ok(node)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::public::PublicFile;
+    use chrono::Utc;
+    use wnfs_common::{CODEC_DAG_CBOR, MemoryBlockStore, Storable, utils::Arc};
+
+    #[async_std::test]
+    async fn jumps_find_chain_nodes_at_every_height() -> Result<()> {
+        let store = &MemoryBlockStore::new();
+        let file = &mut PublicFile::new_rc(Utc::now());
+        let mut cids = vec![file.store(store).await?];
+        for _ in 0..100 {
+            file.prepare_next_revision();
+            cids.push(file.store(store).await?);
+        }
+
+        let clock = &mut Clock::new(store);
+        let head = ClockLink {
+            height: 100,
+            cid: cids[100],
+        };
+        assert_eq!(clock.height(&cids[100]).await?, 100);
+
+        for (height, cid) in cids.iter().enumerate() {
+            assert_eq!(clock.chain_at(head, height as u64).await?, *cid);
+        }
+
+        // Going all the way back only needs to look at a logarithmic number of nodes
+        let clock = &mut Clock::new(store);
+        assert_eq!(clock.chain_at(head, 0).await?, cids[0]);
+        assert!(clock.nodes.len() <= 16);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn ancestry_across_merges_and_unindexed_history() -> Result<()> {
+        let store = &MemoryBlockStore::new();
+
+        // History written before the index existed
+        let mut old = PublicFile::new_rc(Utc::now()).store(store).await?;
+        let mut old_history = vec![old];
+        for _ in 0..5 {
+            let PublicNodeSerializable::File(mut file) =
+                PublicFile::new(Utc::now()).to_serializable(store).await?
+            else {
+                unreachable!()
+            };
+            file.previous = vec![old];
+            let bytes = serde_ipld_dagcbor::to_vec(&PublicNodeSerializable::File(file))?;
+            old = store.put_block(bytes, CODEC_DAG_CBOR).await?;
+            old_history.push(old);
+        }
+
+        // Forked and merged history on top of it
+        let base = &Arc::new(PublicFile::load(&old, store).await?);
+        let ours = &mut Arc::clone(base);
+        ours.prepare_next_revision()
+            .set_content(b"ours".to_vec(), Utc::now(), store)
+            .await?;
+        let our_cid = ours.store(store).await?;
+        let theirs = &mut Arc::clone(base);
+        theirs
+            .prepare_next_revision()
+            .set_content(b"theirs".to_vec(), Utc::now(), store)
+            .await?;
+        let their_cid = theirs.store(store).await?;
+
+        let head = &mut Arc::clone(ours);
+        head.merge(theirs, store).await?;
+        let merge_cid = head.store(store).await?;
+        let mut head_cid = merge_cid;
+        for _ in 0..5 {
+            head.prepare_next_revision();
+            head_cid = head.store(store).await?;
+        }
+
+        let clock = &mut Clock::new(store);
+        let index = clock.get(&our_cid).await?.index.unwrap();
+        assert_eq!(index.height, 6);
+        assert_eq!(index.fork.map(|fork| fork.cid), Some(old));
+        assert_eq!(clock.height(&head_cid).await?, 12);
+
+        for cid in old_history.iter().chain([&our_cid, &their_cid, &merge_cid]) {
+            let link = ClockLink {
+                height: clock.height(cid).await?,
+                cid: *cid,
+            };
+            assert!(clock.is_ancestor(link, &head_cid).await?);
+        }
+
+        let ours = ClockLink {
+            height: 6,
+            cid: our_cid,
+        };
+        assert!(!clock.is_ancestor(ours, &their_cid).await?);
+        assert!(!clock.is_ancestor(ours, &our_cid).await?);
+
+        assert_eq!(
+            clock.common_ancestor(&our_cid, &their_cid).await?,
+            Some(old)
+        );
+        assert_eq!(
+            clock.common_ancestor(&head_cid, &old_history[2]).await?,
+            Some(old_history[2])
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn missing_history_and_invalid_indices() -> Result<()> {
+        let store = &MemoryBlockStore::new();
+        let base = PublicFile::new_rc(Utc::now()).store(store).await?;
+
+        // Indices only depend on the history, so it has to be available
+        let missing = MemoryBlockStore::new()
+            .put_block(b"missing".to_vec(), CODEC_DAG_CBOR)
+            .await?;
+        let clock = &mut Clock::new(store);
+        assert!(clock.index(&BTreeSet::from([missing])).await.is_err());
+
+        // An index whose jump doesn't descend is rejected instead of followed
+        let PublicNodeSerializable::File(mut file) =
+            PublicFile::new(Utc::now()).to_serializable(store).await?
+        else {
+            unreachable!()
+        };
+        file.previous = vec![base];
+        file.clock = Some(ClockIndex {
+            height: 5,
+            jump: ClockLink {
+                height: 5,
+                cid: base,
+            },
+            fork: None,
+        });
+        let bytes = serde_ipld_dagcbor::to_vec(&PublicNodeSerializable::File(file))?;
+        let cid = store.put_block(bytes, CODEC_DAG_CBOR).await?;
+
+        let head = ClockLink { height: 5, cid };
+        let error = clock.chain_at(head, 0).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(FsError::InvalidClockIndex(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
+mod clock;
 #[allow(clippy::module_inception)]
 mod node;
 mod serializable;
 
+pub(crate) use clock::*;
 pub use node::*;
 pub(crate) use serializable::*;
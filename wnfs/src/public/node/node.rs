@@ -1,6 +1,6 @@
 //! Public node system in-memory representation.
 
-use super::PublicNodeSerializable;
+use super::{Clock, ClockLink, PublicNodeSerializable};
 use crate::{
     error::FsError,
     public::{PublicDirectory, PublicFile},
@@ -10,7 +10,7 @@ use anyhow::{Result, bail};
 use async_once_cell::OnceCell;
 use chrono::{DateTime, Utc};
 use std::{cmp::Ordering, collections::BTreeSet};
-use wnfs_common::{BlockStore, Cid, Storable, utils::Arc};
+use wnfs_common::{BlockStore, Cid, LoadIpld, Metadata, Storable, utils::Arc};
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
@@ -228,6 +228,53 @@ impl PublicNode {
         matches!(self, Self::File(_))
     }
 
+    /// Gets the metadata of the underlying file or directory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::public::{PublicFile, PublicNode};
+    /// use chrono::Utc;
+    ///
+    /// let file = PublicFile::new_rc(Utc::now());
+    /// let node = PublicNode::File(file.clone());
+    ///
+    /// assert_eq!(node.get_metadata(), file.get_metadata());
+    /// ```
+    pub fn get_metadata(&self) -> &Metadata {
+        match self {
+            Self::File(file) => file.get_metadata(),
+            Self::Dir(dir) => &dir.metadata,
+        }
+    }
+
+    /// Replaces the content and metadata of this node with the ones of an earlier revision,
+    /// as a new revision of this node.
+    pub(crate) fn restore_from(
+        &mut self,
+        revision: &PublicNode,
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        match (self, revision) {
+            (Self::File(file), Self::File(old_file)) => {
+                let file = file.prepare_next_revision();
+                file.userland.clone_from(&old_file.userland);
+                file.metadata.clone_from(&old_file.metadata);
+                file.metadata.upsert_mtime(time);
+            }
+            (Self::Dir(dir), Self::Dir(old_dir)) => {
+                let dir = dir.prepare_next_revision();
+                dir.userland.clone_from(&old_dir.userland);
+                dir.metadata.clone_from(&old_dir.metadata);
+                dir.metadata.upsert_mtime(time);
+            }
+            (Self::File(_), Self::Dir(_)) => bail!(FsError::NotAFile),
+            (Self::Dir(_), Self::File(_)) => bail!(FsError::NotADirectory),
+        }
+
+        Ok(())
+    }
+
     /// Comparing the merkle clocks of this node to the other node.
     ///
     /// This gives you information about which node is "ahead" of which other node
@@ -238,6 +285,10 @@ impl PublicNode {
     /// - `Ok(Some(Ordering::Less))`: The other node is "further ahead" in history than this node.
     /// - `Ok(Some(Ordering::Greater))`: This node is "further ahead".
     /// - `Err(_)`: Something went wrong during deserialization/in the blockstore.
+    ///
+    /// Nodes store an index of their history next to their previous links, which makes this
+    /// take a logarithmic number of steps. History written before the index existed is walked
+    /// breadth-first.
     pub async fn causal_compare(
         &self,
         other: &Self,
@@ -270,6 +321,32 @@ impl PublicNode {
             return Ok(Some(Ordering::Equal));
         }
 
+        let clock = &mut Clock::new(store);
+        if let (Some(our_height), Some(other_height)) = (
+            clock.indexed_height(&our_root).await?,
+            clock.indexed_height(&other_root).await?,
+        ) {
+            // Heights strictly increase along history, so only the lower node can be the ancestor
+            let ours = ClockLink {
+                height: our_height,
+                cid: our_root,
+            };
+            let other = ClockLink {
+                height: other_height,
+                cid: other_root,
+            };
+
+            return Ok(match our_height.cmp(&other_height) {
+                Ordering::Less if clock.is_ancestor(ours, &other_root).await? => {
+                    Some(Ordering::Less)
+                }
+                Ordering::Greater if clock.is_ancestor(other, &our_root).await? => {
+                    Some(Ordering::Greater)
+                }
+                _ => None,
+            });
+        }
+
         let mut our_previous_set = self.get_previous().clone();
         let mut other_previous_set = other.get_previous().clone();
 
@@ -305,6 +382,86 @@ impl PublicNode {
             }
         }
     }
+
+    /// Finds the latest common ancestor of this node and the other node,
+    /// e.g. to use as the base of a three-way merge.
+    ///
+    /// If one node is an ancestor of the other, that node is returned.
+    /// Otherwise this returns the highest node on either node's first-parent line,
+    /// i.e. the line of previous revisions with the longest history, that's an ancestor
+    /// of the other node.
+    /// Returns `None` if the nodes don't share any history.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     common::{MemoryBlockStore, Storable},
+    ///     public::{PublicFile, PublicNode},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let base = PublicFile::with_content_rc(Utc::now(), b"base".to_vec(), store).await?;
+    ///     base.store(store).await?;
+    ///
+    ///     let ours = &mut base.clone();
+    ///     ours.prepare_next_revision().set_content(b"ours".to_vec(), Utc::now(), store).await?;
+    ///     let theirs = &mut base.clone();
+    ///     theirs.prepare_next_revision().set_content(b"theirs".to_vec(), Utc::now(), store).await?;
+    ///
+    ///     let ancestor = PublicNode::File(ours.clone())
+    ///         .common_ancestor(&PublicNode::File(theirs.clone()), store)
+    ///         .await?
+    ///         .unwrap();
+    ///
+    ///     assert_eq!(ancestor.as_file()?.get_content(store).await?, b"base");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn common_ancestor(
+        &self,
+        other: &Self,
+        store: &impl BlockStore,
+    ) -> Result<Option<PublicNode>> {
+        let our_root = self.store(store).await?;
+        let other_root = other.store(store).await?;
+
+        match Clock::new(store)
+            .common_ancestor(&our_root, &other_root)
+            .await?
+        {
+            Some(cid) => Ok(Some(PublicNode::load(&cid, store).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Deserializes a node, fetching the entries of sharded directories from the store.
+    ///
+    /// Unlike [`Storable::from_serializable`], this works for directories of any size.
+    pub async fn from_serializable_with_store(
+        cid: Option<&Cid>,
+        serializable: PublicNodeSerializable,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        Ok(match serializable {
+            PublicNodeSerializable::File(file) => Self::File(Arc::new(
+                PublicFile::from_serializable(cid, PublicNodeSerializable::File(file)).await?,
+            )),
+            PublicNodeSerializable::Dir(dir) => Self::Dir(Arc::new(
+                PublicDirectory::from_serializable_with_store(
+                    cid,
+                    PublicNodeSerializable::Dir(dir),
+                    store,
+                )
+                .await?,
+            )),
+        })
+    }
 }
 
 impl Id for PublicNode {
@@ -352,6 +509,10 @@ impl Storable for PublicNode {
         })
     }
 
+    /// Deserializes a node without a block store.
+    ///
+    /// This fails for sharded directories, see [`PublicDirectory`]'s implementation.
+    /// Use [`PublicNode::from_serializable_with_store`] or [`Storable::load`] instead.
     async fn from_serializable(
         cid: Option<&Cid>,
         serializable: Self::Serializable,
@@ -367,6 +528,12 @@ impl Storable for PublicNode {
         })
     }
 
+    async fn load(cid: &Cid, store: &impl BlockStore) -> Result<Self> {
+        let bytes = store.get_block(cid).await?;
+        let serializable = PublicNodeSerializable::decode_ipld(cid, bytes)?;
+        Self::from_serializable_with_store(Some(cid), serializable, store).await
+    }
+
     fn persisted_as(&self) -> Option<&OnceCell<Cid>> {
         match self {
             PublicNode::File(file) => file.as_ref().persisted_as(),
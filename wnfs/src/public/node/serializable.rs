@@ -1,3 +1,4 @@
+use super::ClockIndex;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -20,6 +21,9 @@ pub struct PublicFileSerializable {
     pub version: Version,
     pub metadata: Metadata,
     pub previous: Vec<Cid>,
+    /// Missing for nodes without previous revisions and nodes written before it was introduced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<ClockIndex>,
     pub userland: Cid,
 }
 
@@ -28,5 +32,11 @@ pub struct PublicDirectorySerializable {
     pub version: Version,
     pub metadata: Metadata,
     pub previous: Vec<Cid>,
+    /// Missing for nodes without previous revisions and nodes written before it was introduced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<ClockIndex>,
     pub userland: BTreeMap<String, Cid>,
+    /// The root of a HAMT holding the directory's entries, for directories too large to inline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shards: Option<Cid>,
 }
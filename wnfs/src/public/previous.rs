@@ -0,0 +1,302 @@
+use super::{PublicDirectory, PublicNode};
+use crate::{RevisionSelector, error::FsError, utils::modified_at_or_before};
+use anyhow::{Result, bail};
+use async_stream::try_stream;
+use chrono::{DateTime, Utc};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, BinaryHeap},
+};
+use wnfs_common::{
+    BlockStore, Cid, Storable,
+    utils::{Arc, BoxStream, boxed_stream},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// This represents the state of an iterator through the history of a public node.
+///
+/// Public nodes link to the CIDs of their previous revisions. Merge nodes link to more
+/// than one previous revision, so the history is a graph rather than a list.
+/// The iterator visits every earlier revision exactly once, newest first by modification time,
+/// and tie-breaks on the CID.
+pub struct PublicNodeHistory {
+    /// The node that the history was created for.
+    node: PublicNode,
+    /// Revisions that were discovered, but not yet returned.
+    queue: BinaryHeap<QueuedRevision>,
+    /// CIDs of all revisions that were discovered so far.
+    visited: BTreeSet<Cid>,
+}
+
+struct QueuedRevision {
+    modified: Option<DateTime<Utc>>,
+    cid: Cid,
+    node: PublicNode,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PublicNodeHistory {
+    /// Creates a history iterator for given node.
+    pub async fn of(node: &PublicNode, store: &impl BlockStore) -> Result<Self> {
+        let mut history = Self {
+            node: node.clone(),
+            queue: BinaryHeap::new(),
+            visited: BTreeSet::new(),
+        };
+
+        history.enqueue_previous(node, store).await?;
+        Ok(history)
+    }
+
+    /// Creates a history iterator for the node at given path relative to `root`.
+    ///
+    /// An empty path refers to `root` itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::{Duration, Utc};
+    /// use wnfs::{
+    ///     common::{MemoryBlockStore, Storable},
+    ///     public::{PublicDirectory, PublicNodeHistory},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let root = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let path = &["notes.md".into()];
+    ///     let earlier = Utc::now() - Duration::hours(1);
+    ///
+    ///     root.write(path, b"draft".to_vec(), earlier, store).await?;
+    ///     root.store(store).await?;
+    ///     root.write(path, b"final".to_vec(), Utc::now(), store).await?;
+    ///
+    ///     let history = PublicNodeHistory::of_path(root, path, store).await?;
+    ///     let old = history.as_of(earlier, store).await?.unwrap();
+    ///
+    ///     assert_eq!(old.as_file()?.get_content(store).await?, b"draft");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn of_path(
+        root: &Arc<PublicDirectory>,
+        path_segments: &[String],
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        if path_segments.is_empty() {
+            return Self::of(&PublicNode::Dir(Arc::clone(root)), store).await;
+        }
+
+        let Some(node) = root.get_node(path_segments, store).await? else {
+            bail!(FsError::NotFound);
+        };
+
+        Self::of(node, store).await
+    }
+
+    /// Steps the history one revision back and returns the node at that revision.
+    ///
+    /// Returns `None` once all earlier revisions were visited.
+    pub async fn get_previous(&mut self, store: &impl BlockStore) -> Result<Option<PublicNode>> {
+        let Some(QueuedRevision { node, .. }) = self.queue.pop() else {
+            return Ok(None);
+        };
+
+        self.enqueue_previous(&node, store).await?;
+        Ok(Some(node))
+    }
+
+    /// Returns the `n`th earlier revision, where `0` is the revision returned
+    /// by the first call to `get_previous`.
+    pub async fn nth_previous(
+        mut self,
+        n: usize,
+        store: &impl BlockStore,
+    ) -> Result<Option<PublicNode>> {
+        for _ in 0..n {
+            if self.get_previous(store).await?.is_none() {
+                return Ok(None);
+            }
+        }
+
+        self.get_previous(store).await
+    }
+
+    /// Returns the revision that was current at given time, i.e. the newest revision
+    /// (including the node the history was created for) that was modified at or before `time`.
+    ///
+    /// This assumes revisions have modification times no earlier than the revisions they're based on.
+    pub async fn as_of(
+        mut self,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<Option<PublicNode>> {
+        let is_before = |node: &PublicNode| modified_at_or_before(node.get_metadata(), time);
+
+        if is_before(&self.node) {
+            return Ok(Some(self.node));
+        }
+
+        while let Some(node) = self.get_previous(store).await? {
+            if is_before(&node) {
+                return Ok(Some(node));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the revision picked by given selector.
+    ///
+    /// `RevisionSelector::Back(0)` is the node the history was created for.
+    pub async fn select(
+        self,
+        revision: RevisionSelector,
+        store: &impl BlockStore,
+    ) -> Result<Option<PublicNode>> {
+        match revision {
+            RevisionSelector::Back(0) => Ok(Some(self.node)),
+            RevisionSelector::Back(n) => self.nth_previous(n - 1, store).await,
+            RevisionSelector::AsOf(time) => self.as_of(time, store).await,
+        }
+    }
+
+    /// Turns this history into a stream of all earlier revisions.
+    pub fn into_stream<'a>(
+        mut self,
+        store: &'a impl BlockStore,
+    ) -> BoxStream<'a, Result<PublicNode>> {
+        boxed_stream(try_stream! {
+            while let Some(node) = self.get_previous(store).await? {
+                yield node;
+            }
+        })
+    }
+
+    async fn enqueue_previous(&mut self, node: &PublicNode, store: &impl BlockStore) -> Result<()> {
+        for cid in node.get_previous() {
+            if !self.visited.insert(*cid) {
+                continue;
+            }
+
+            let node = PublicNode::load(cid, store).await?;
+            self.queue.push(QueuedRevision {
+                modified: node.get_metadata().get_modified(),
+                cid: *cid,
+                node,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl PartialEq for QueuedRevision {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedRevision {}
+
+impl PartialOrd for QueuedRevision {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedRevision {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.modified, self.cid).cmp(&(other.modified, other.cid))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use futures::TryStreamExt;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn history_walks_through_merge_nodes() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let time = |secs| Utc.timestamp_opt(secs, 0).unwrap();
+        let path = &["file.txt".to_string()];
+
+        let root = &mut PublicDirectory::new_rc(time(0));
+        root.write(path, b"base".to_vec(), time(1), store).await?;
+        root.store(store).await?;
+
+        let fork = &mut Arc::clone(root);
+        root.write(path, b"ours".to_vec(), time(2), store).await?;
+        fork.write(path, b"theirs".to_vec(), time(3), store).await?;
+        root.reconcile(fork, store).await?;
+        root.store(store).await?;
+        root.write(path, b"after".to_vec(), time(4), store).await?;
+
+        let history = PublicNodeHistory::of_path(root, path, store).await?;
+        let mut contents = Vec::new();
+        for node in history.into_stream(store).try_collect::<Vec<_>>().await? {
+            contents.push(String::from_utf8(
+                node.as_file()?.get_content(store).await?,
+            )?);
+        }
+
+        // The merge node comes first, then both merged revisions and their common ancestor
+        assert_eq!(contents.len(), 4);
+        assert_eq!(&contents[1..], &["theirs", "ours", "base"]);
+
+        let history = PublicNodeHistory::of_path(root, path, store).await?;
+        let node = history.as_of(time(2), store).await?.unwrap();
+        assert_eq!(node.as_file()?.get_content(store).await?, b"ours");
+
+        let history = PublicNodeHistory::of_path(root, path, store).await?;
+        let node = history.nth_previous(3, store).await?.unwrap();
+        assert_eq!(node.as_file()?.get_content(store).await?, b"base");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn restoring_creates_a_new_revision() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let path = &["file.txt".to_string()];
+        let root = &mut PublicDirectory::new_rc(Utc::now());
+        root.write(path, b"one".to_vec(), Utc::now(), store).await?;
+        root.store(store).await?;
+        root.write(path, b"two".to_vec(), Utc::now(), store).await?;
+        root.store(store).await?;
+
+        let history = PublicNodeHistory::of_path(root, path, store).await?;
+        let old = history.nth_previous(0, store).await?.unwrap();
+        let current_cid = root
+            .get_node(path, store)
+            .await?
+            .unwrap()
+            .store(store)
+            .await?;
+
+        root.restore(path, &old, Utc::now(), store).await?;
+
+        let restored = root.get_node(path, store).await?.unwrap();
+        assert_eq!(restored.as_file()?.get_content(store).await?, b"one");
+        assert_eq!(restored.get_previous(), &BTreeSet::from([current_cid]));
+
+        Ok(())
+    }
+}
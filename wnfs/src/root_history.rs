@@ -0,0 +1,162 @@
+//! A log of all roots a root tree was stored as.
+//!
+//! With history enabled, every stored root links to a small history block with the time it
+//! was stored at, an optional tag naming it, and the CID of the root stored before it.
+//! The roots thus form an append-only chain that can be listed, checked out and diffed.
+
+use crate::{
+    error::FsError,
+    root_tree::{RootTree, RootTreeSerializable},
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use wnfs_common::{BlockStore, CODEC_DAG_CBOR, Cid};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The in-memory state of a root tree's history.
+#[derive(Debug, Clone, Default)]
+pub struct RootHistory {
+    /// The root that was stored or loaded last, which the next stored root links back to.
+    pub latest: Option<Cid>,
+}
+
+/// A root from the history of a root tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootHistoryEntry {
+    /// The CID of the root.
+    pub root: Cid,
+    /// When the root was stored.
+    pub time: DateTime<Utc>,
+    /// The name the root was tagged with, if any.
+    pub tag: Option<String>,
+}
+
+/// The stored form of a root's history entry.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RootHistorySerializable {
+    /// When the root was stored, in milliseconds since the unix epoch.
+    time: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    /// The root stored before this one.
+    previous: Option<Cid>,
+}
+
+/// A read-only view of a root tree as it was at a root from its history.
+///
+/// Checkouts dereference to a root tree, but only give shared access to it,
+/// so they can be read, walked and searched, but not changed.
+pub struct RootTreeCheckout<'a, B: BlockStore> {
+    tree: RootTree<&'a B>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl RootHistory {
+    /// Stores the history entry for a new root, linking it to the latest root.
+    pub(crate) async fn store_entry(
+        &self,
+        tag: Option<String>,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<Cid> {
+        let serializable = RootHistorySerializable {
+            time: time.timestamp_millis(),
+            tag,
+            previous: self.latest,
+        };
+
+        Ok(store
+            .put_block(serde_ipld_dagcbor::to_vec(&serializable)?, CODEC_DAG_CBOR)
+            .await?)
+    }
+
+    /// Lists all roots in this history, from the latest to the oldest.
+    ///
+    /// Fails with `FsError::InvalidHistoryTime` if a root was stored with a time out of range.
+    pub async fn entries(&self, store: &impl BlockStore) -> Result<Vec<RootHistoryEntry>> {
+        let mut entries = Vec::new();
+        let mut next = self.latest;
+        while let Some(root) = next {
+            let serializable: RootTreeSerializable =
+                serde_ipld_dagcbor::from_slice(&store.get_block(&root).await?)?;
+            // Roots stored before history was enabled end the log
+            let Some(history) = serializable.history else {
+                break;
+            };
+
+            let history: RootHistorySerializable =
+                serde_ipld_dagcbor::from_slice(&store.get_block(&history).await?)?;
+            entries.push(RootHistoryEntry {
+                root,
+                time: DateTime::from_timestamp_millis(history.time)
+                    .ok_or(FsError::InvalidHistoryTime(root, history.time))?,
+                tag: history.tag,
+            });
+            next = history.previous;
+        }
+
+        Ok(entries)
+    }
+}
+
+impl<'a, B: BlockStore> RootTreeCheckout<'a, B> {
+    pub(crate) fn new(tree: RootTree<&'a B>) -> Self {
+        Self { tree }
+    }
+}
+
+impl<'a, B: BlockStore> Deref for RootTreeCheckout<'a, B> {
+    type Target = RootTree<&'a B>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tree
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn entries_with_times_out_of_range_fail() -> TestResult {
+        let store = &MemoryBlockStore::default();
+        let mut root_tree = RootTree::empty(store);
+        root_tree.enable_history();
+        let cid = root_tree.store().await?;
+
+        let mut serializable: RootTreeSerializable =
+            serde_ipld_dagcbor::from_slice(&store.get_block(&cid).await?)?;
+        let history = RootHistorySerializable {
+            time: i64::MAX,
+            tag: None,
+            previous: None,
+        };
+        let history = serde_ipld_dagcbor::to_vec(&history)?;
+        serializable.history = Some(store.put_block(history, CODEC_DAG_CBOR).await?);
+        let root = serde_ipld_dagcbor::to_vec(&serializable)?;
+        let root = store.put_block(root, CODEC_DAG_CBOR).await?;
+
+        let history = RootHistory { latest: Some(root) };
+        let error = history.entries(store).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(FsError::InvalidHistoryTime(cid, time)) if *cid == root && *time == i64::MAX
+        ));
+
+        Ok(())
+    }
+}
@@ -1,28 +1,57 @@
 use crate::{
-    WNFS_VERSION,
-    error::FsError,
+    RevisionSelector, SearchResult, WNFS_VERSION,
+    changes::{ChangeEvent, ChangeNotifier, ChangedPath, PartitionKind},
+    conflict::{Conflict, ConflictPolicy, TieBreakPolicy},
+    diff::{PathChange, PathChangeType},
+    error::{FsError, InvalidPathReason, ShareError},
+    find::{FindQuery, NodeKind},
+    path::WnfsPath,
+    private,
     private::{
-        AccessKey, PrivateDirectory, PrivateNode,
+        AccessKey, ExchangeKey, KEY_BYTE_SIZE, PrivateDirectory, PrivateFile, PrivateKey,
+        PrivateNode, PublicKeyModulus, SnapshotAccessKey, SnapshotKey,
         forest::{hamt::HamtForest, traits::PrivateForest},
+        share::{EXCHANGE_KEY_NAME, recipient, sharer},
     },
-    public::PublicDirectory,
+    public::{self, PublicDirectory, PublicFile, PublicLink, PublicNode, Reconciliation},
+    root_history::{RootHistory, RootHistoryEntry, RootTreeCheckout},
+    transaction::Transaction,
+    walk::{WalkEntry, WalkOptions},
 };
-use anyhow::{Result, bail};
+use anyhow::{Result, bail, ensure};
+use async_stream::try_stream;
 #[cfg(test)]
 use chrono::TimeZone;
 use chrono::{DateTime, Utc};
+use futures::{AsyncRead, AsyncReadExt, StreamExt, TryStreamExt, channel::mpsc::UnboundedReceiver};
+use ipld_core::ipld::Ipld;
 use rand_chacha::ChaCha12Rng;
 use rand_core::{CryptoRngCore, SeedableRng};
 use semver::Version;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use serde_bytes::ByteBuf;
+use std::collections::{BTreeMap, BTreeSet};
 #[cfg(test)]
 use wnfs_common::MemoryBlockStore;
 use wnfs_common::{
-    BlockStore, CODEC_DAG_CBOR, Cid, Metadata, Storable,
-    utils::{Arc, CondSend},
+    BlockStore, CODEC_DAG_CBOR, Cid, MAX_BLOCK_SIZE, Metadata, Storable,
+    utils::{Arc, BoxStream, CondSend, boxed_stream},
 };
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How many revisions may lie between a private root's base revision and its current revision
+/// for the history in between to be followed.
+const HISTORY_DISCREPANCY_BUDGET: usize = 1_000_000;
+
+/// How many share counters are searched for the latest share between a sharer and a recipient.
+const SHARE_COUNTER_SEARCH_LIMIT: u64 = 1_000_000;
+
+/// The domain separation info for deriving the mount table key from a root secret.
+const MOUNT_TABLE_KEY_DSI: &str = "wnfs/1.0/mount table key derivation from root secret";
+
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
@@ -34,6 +63,31 @@ pub struct RootTree<B: BlockStore> {
     pub public_root: Arc<PublicDirectory>,
     pub exchange_root: Arc<PublicDirectory>,
     pub private_map: BTreeMap<Vec<String>, Arc<PrivateDirectory>>,
+    /// The revisions the private roots were created at, which private history is followed
+    /// back to. They're recorded in the mount table, so roots loaded from a mount table without
+    /// them start at the revision they were loaded at.
+    pub(crate) private_bases: BTreeMap<Vec<String>, Arc<PrivateDirectory>>,
+    /// Private files that are mounted on their own, like shares of a single file.
+    /// Their mount point is the path of the file itself.
+    pub(crate) private_files: BTreeMap<Vec<String>, Arc<PrivateFile>>,
+    /// Private files that are mounted read-only from snapshot shares,
+    /// along with the access keys they were mounted with.
+    pub(crate) snapshot_files: BTreeMap<Vec<String>, (SnapshotAccessKey, Arc<PrivateFile>)>,
+    /// The key the table of mounted private roots is encrypted with.
+    /// Without it, the mount points aren't persisted when storing.
+    pub(crate) mount_key: Option<SnapshotKey>,
+    /// The mount key, encrypted for each exchange key that was granted access to it.
+    pub(crate) wrapped_mount_keys: Vec<Vec<u8>>,
+    /// The mount table this root tree was loaded with. Kept as-is when storing without a mount key.
+    pub(crate) mount_table: Option<Cid>,
+    /// The shares made of private paths, by the path they were made for.
+    /// They're persisted in the mount table, so only when there's a mount key.
+    pub(crate) shares: BTreeMap<Vec<String>, BTreeSet<ShareRecord>>,
+    /// The subscribers to changes of this root tree.
+    pub(crate) notifier: ChangeNotifier,
+    /// The log of stored roots, if it's enabled.
+    /// Without it, stored roots don't link to the roots stored before them.
+    pub(crate) history: Option<RootHistory>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -42,6 +96,120 @@ pub struct RootTreeSerializable {
     pub exchange: Cid,
     pub forest: Cid,
     pub version: Version,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mounts: Option<Cid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<Cid>,
+}
+
+/// The stored form of the table of mounted private roots.
+#[derive(Debug, Serialize, Deserialize)]
+struct MountTableSerializable {
+    /// The mount key, encrypted for each exchange key that was granted access to it.
+    wrapped_keys: Vec<ByteBuf>,
+    /// The list of mount points, encrypted with the mount key.
+    #[serde(with = "serde_bytes")]
+    table: Vec<u8>,
+    /// The list of shared paths, encrypted with the mount key. Empty if nothing was shared.
+    #[serde(default, with = "serde_bytes", skip_serializing_if = "Vec::is_empty")]
+    shares: Vec<u8>,
+}
+
+/// A private root mounted at a path.
+#[derive(Debug, Serialize, Deserialize)]
+struct MountPoint {
+    path: Vec<String>,
+    access_key: AccessKey,
+    /// The revision a mounted directory's history goes back to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base: Option<AccessKey>,
+}
+
+/// The shares made of a private path.
+#[derive(Debug, Serialize, Deserialize)]
+struct SharedPath {
+    path: Vec<String>,
+    shares: BTreeSet<ShareRecord>,
+}
+
+/// A share made for one of a recipient's exchange keys, identified by its share label inputs.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) struct ShareRecord {
+    sharer_root_did: String,
+    recipient_exchange_key: ByteBuf,
+    share_count: u64,
+}
+
+/// A file or directory from any of the partitions of a root tree.
+#[derive(Debug, Clone)]
+pub enum RootTreeNode {
+    /// A node from the public or the exchange partition.
+    Public(PublicNode),
+    /// A node from a private partition.
+    Private(PrivateNode),
+}
+
+/// The kind, size and metadata of a file or directory in a root tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeStat {
+    /// Whether this is a file or a directory.
+    pub kind: NodeKind,
+    /// The size of a file's content, or zero for directories.
+    pub size: u64,
+    /// The metadata of the file or directory.
+    pub metadata: Metadata,
+}
+
+/// A file opened for writing with [`RootTree::open_file_mut`].
+///
+/// Subscribers of the root tree are notified of the change once this is dropped,
+/// i.e. after the file was written to.
+pub struct RootTreeFileMut<'a, B: BlockStore> {
+    file: OpenFile<'a, B>,
+    notifier: &'a ChangeNotifier,
+    event: Option<ChangeEvent>,
+}
+
+/// The file behind a [`RootTreeFileMut`].
+///
+/// Writing to private files needs access to the forest,
+/// so it's borrowed along with the file.
+enum OpenFile<'a, B: BlockStore> {
+    /// A file from the public or the exchange partition.
+    Public(&'a mut PublicFile, &'a B),
+    /// A file from a private partition.
+    Private(&'a mut PrivateFile, &'a mut Arc<HamtForest>, &'a B),
+}
+
+/// What a recipient of a share can read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareMode {
+    /// The shared revision and all revisions after it.
+    Temporal,
+    /// Only the shared revision.
+    Snapshot,
+}
+
+/// The exchange key of a device, from the exchange partition of a root tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceExchangeKey {
+    /// The name of the device.
+    pub device: String,
+    /// The modulus of the device's RSA public key.
+    pub modulus: PublicKeyModulus,
+    /// When the key was added.
+    pub created: Option<DateTime<Utc>>,
+}
+
+/// What reconciling two root trees did to each of their partitions.
+#[derive(Debug, Clone)]
+pub struct RootTreeReconciliation {
+    /// How the public partitions were reconciled.
+    pub public: Reconciliation,
+    /// How the exchange partitions were reconciled.
+    pub exchange: Reconciliation,
+    /// How each private root was reconciled, by its mount point.
+    pub private: BTreeMap<Vec<String>, Reconciliation<PrivateNode>>,
 }
 
 /// A directory from a particular WNFS partition
@@ -49,7 +217,10 @@ pub enum Partition {
     Public(Arc<PublicDirectory>),
     Exchange(Arc<PublicDirectory>),
     Private(Vec<String>, Arc<PrivateDirectory>),
-    // TODO(matheus23): Support mounting only singular files
+    /// A single private file, mounted at its own path.
+    PrivateFile(Vec<String>, Arc<PrivateFile>),
+    /// A single private file from a snapshot share, mounted read-only at its own path.
+    Snapshot(Vec<String>, Arc<PrivateFile>),
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -64,6 +235,15 @@ impl<B: BlockStore> RootTree<B> {
             public_root: PublicDirectory::new_rc(time),
             exchange_root: PublicDirectory::new_rc(time),
             private_map: BTreeMap::new(),
+            private_bases: BTreeMap::new(),
+            private_files: BTreeMap::new(),
+            snapshot_files: BTreeMap::new(),
+            mount_key: None,
+            wrapped_mount_keys: Vec::new(),
+            mount_table: None,
+            shares: BTreeMap::new(),
+            notifier: ChangeNotifier::new(),
+            history: None,
         }
     }
 
@@ -82,7 +262,16 @@ impl<B: BlockStore> RootTree<B> {
             forest,
             public_root: PublicDirectory::new_rc(time),
             exchange_root: PublicDirectory::new_rc(time),
+            private_bases: private_map.clone(),
             private_map,
+            private_files: BTreeMap::new(),
+            snapshot_files: BTreeMap::new(),
+            mount_key: None,
+            wrapped_mount_keys: Vec::new(),
+            mount_table: None,
+            shares: BTreeMap::new(),
+            notifier: ChangeNotifier::new(),
+            history: None,
         }
     }
 
@@ -100,14 +289,18 @@ impl<B: BlockStore> RootTree<B> {
         match path.first().map(|p| p.as_str()) {
             Some("private") => {}
             Some("public") | Some("exchange") => bail!(FsError::DirectoryAlreadyExists),
-            Some(_) => bail!(FsError::InvalidPath),
-            None => bail!(FsError::InvalidPath),
+            Some(_) => bail!(FsError::InvalidPath(InvalidPathReason::NotPrivate)),
+            None => bail!(FsError::InvalidPath(InvalidPathReason::Empty)),
         };
 
         if self.private_map.contains_key(path) {
             bail!(FsError::DirectoryAlreadyExists)
         }
 
+        if self.private_files.contains_key(path) || self.snapshot_files.contains_key(path) {
+            bail!(FsError::FileAlreadyExists)
+        }
+
         let root = PrivateDirectory::new_and_store(
             &self.forest.empty_name(),
             time,
@@ -122,7 +315,10 @@ impl<B: BlockStore> RootTree<B> {
             .store(&mut self.forest, &self.store, rng)
             .await?;
 
+        self.private_bases.insert(path.to_vec(), Arc::clone(&root));
         self.private_map.insert(path.to_vec(), root);
+        self.notifier
+            .notify(ChangeEvent::Mkdir(self.changed_path(path)));
 
         Ok(access_key)
     }
@@ -136,11 +332,121 @@ impl<B: BlockStore> RootTree<B> {
             .await?
             .as_dir()?;
 
+        self.private_bases.insert(path.to_vec(), Arc::clone(&dir));
         self.private_map.insert(path.to_vec(), dir);
 
         Ok(())
     }
 
+    /// Mounts a single private file at given path.
+    ///
+    /// The mount point is the file itself, so it can be read and written like any other file,
+    /// but nothing can be created below it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use wnfs::{common::MemoryBlockStore, root_tree::RootTree};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut root_tree = RootTree::empty(MemoryBlockStore::new());
+    ///     root_tree.create_private_root(&["private".into()]).await?;
+    ///
+    ///     let notes = ["private".into(), "notes.md".into()];
+    ///     root_tree.write(&notes, b"# Notes".to_vec()).await?;
+    ///     root_tree.store().await?;
+    ///     let access_key = root_tree.store_private_root(&notes).await?;
+    ///
+    ///     let shared = ["private".into(), "shared.md".into()];
+    ///     let mut recipient = RootTree::empty(root_tree.store.clone());
+    ///     recipient.forest = root_tree.forest.clone();
+    ///     recipient.load_private_file(&shared, &access_key).await?;
+    ///
+    ///     assert_eq!(recipient.read(&shared).await?, b"# Notes");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn load_private_file(
+        &mut self,
+        path: &[String],
+        access_key: &AccessKey,
+    ) -> Result<()> {
+        let file = PrivateNode::load(access_key, &self.forest, &self.store, None)
+            .await?
+            .as_file()?;
+
+        self.private_files.insert(path.to_vec(), file);
+
+        Ok(())
+    }
+
+    /// Mounts the private directory or file that given access key points to at given path.
+    ///
+    /// Snapshot access keys can only be mounted for files, which are mounted read-only.
+    async fn mount_access_key(
+        &mut self,
+        path: Vec<String>,
+        access_key: AccessKey,
+        forest: &impl PrivateForest,
+    ) -> Result<()> {
+        match access_key {
+            AccessKey::Temporal(_) => {
+                let node = PrivateNode::load(&access_key, forest, &self.store, None).await?;
+                self.mount(path, node);
+            }
+            AccessKey::Snapshot(access_key) => {
+                let file = PrivateFile::load_snapshot(&access_key, forest, &self.store).await?;
+                self.snapshot_files
+                    .insert(path, (access_key, Arc::new(file)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gets the private files that are mounted on their own, by their mount points.
+    pub fn get_private_files(&self) -> &BTreeMap<Vec<String>, Arc<PrivateFile>> {
+        &self.private_files
+    }
+
+    /// Gets the private files mounted read-only from snapshot shares, by their mount points,
+    /// along with the access keys they were mounted with.
+    pub fn get_snapshot_files(
+        &self,
+    ) -> &BTreeMap<Vec<String>, (SnapshotAccessKey, Arc<PrivateFile>)> {
+        &self.snapshot_files
+    }
+
+    /// Mounts a private directory or file at given path.
+    fn mount(&mut self, path: Vec<String>, node: PrivateNode) {
+        match node {
+            PrivateNode::Dir(dir) => {
+                self.private_bases.insert(path.clone(), Arc::clone(&dir));
+                self.private_map.insert(path, dir);
+            }
+            PrivateNode::File(file) => {
+                self.private_files.insert(path, file);
+            }
+        }
+    }
+
+    /// Gets all mounted private directories and files by their mount points.
+    fn private_nodes(&self) -> Vec<(Vec<String>, PrivateNode)> {
+        let dirs = self
+            .private_map
+            .iter()
+            .map(|(path, dir)| (path.clone(), dir.as_node()));
+        let files = self
+            .private_files
+            .iter()
+            .map(|(path, file)| (path.clone(), file.as_node()));
+
+        dirs.chain(files).collect()
+    }
+
     pub async fn store_private_root(&mut self, path: &[String]) -> Result<AccessKey> {
         self.store_private_root_with(path, &mut ChaCha12Rng::from_entropy())
             .await
@@ -153,14 +459,14 @@ impl<B: BlockStore> RootTree<B> {
     ) -> Result<AccessKey> {
         let mut forest = Arc::clone(&self.forest);
 
-        let (path, Partition::Private(_, dir)) = self.get_partition(path)? else {
-            bail!("Path is not in the private partition");
+        let node = match self.get_partition(path)? {
+            (path, Partition::Private(_, dir)) => dir
+                .get_node(path, true, &forest, &self.store)
+                .await?
+                .ok_or(FsError::NotFound)?,
+            (_, Partition::PrivateFile(_, file)) => file.as_node(),
+            _ => bail!("Path is not in the private partition"),
         };
-
-        let node = dir
-            .get_node(path, true, &forest, &self.store)
-            .await?
-            .ok_or(FsError::NotFound)?;
         let access_key = node.store(&mut forest, &self.store, rng).await?;
 
         Ok(access_key)
@@ -168,7 +474,7 @@ impl<B: BlockStore> RootTree<B> {
 
     pub fn get_partition<'p>(&self, path: &'p [String]) -> Result<(&'p [String], Partition)> {
         let Some(first) = path.first() else {
-            bail!(FsError::InvalidPath)
+            bail!(FsError::InvalidPath(InvalidPathReason::Empty))
         };
 
         match first.as_str() {
@@ -178,11 +484,25 @@ impl<B: BlockStore> RootTree<B> {
                 Partition::Exchange(Arc::clone(&self.exchange_root)),
             )),
             _ => {
-                let (prefix, root) = self
-                    .lookup_private_root(path)
+                let prefix = self
+                    .find_private_root(path)
                     .ok_or(FsError::PartitionNotFound)?;
-
-                Ok((&path[prefix.len()..], Partition::Private(prefix, root)))
+                let relative_path = &path[prefix.len()..];
+
+                if let Some(file) = self.private_files.get(&prefix) {
+                    ensure!(relative_path.is_empty(), FsError::NotADirectory);
+                    let file = Arc::clone(file);
+                    return Ok((relative_path, Partition::PrivateFile(prefix, file)));
+                }
+
+                if let Some((_, file)) = self.snapshot_files.get(&prefix) {
+                    ensure!(relative_path.is_empty(), FsError::NotADirectory);
+                    let file = Arc::clone(file);
+                    return Ok((relative_path, Partition::Snapshot(prefix, file)));
+                }
+
+                let root = Arc::clone(&self.private_map[&prefix]);
+                Ok((relative_path, Partition::Private(prefix, root)))
             }
         }
     }
@@ -192,16 +512,26 @@ impl<B: BlockStore> RootTree<B> {
             Partition::Public(public_root) => self.public_root = public_root,
             Partition::Exchange(exchange_root) => self.exchange_root = exchange_root,
             Partition::Private(prefix, private_root) => {
+                self.private_bases
+                    .entry(prefix.clone())
+                    .or_insert_with(|| Arc::clone(&private_root));
                 self.private_map.insert(prefix, private_root);
             }
+            Partition::PrivateFile(prefix, file) => {
+                self.private_files.insert(prefix, file);
+            }
+            // Snapshots can't change
+            Partition::Snapshot(..) => {}
         }
     }
 
     fn find_private_root(&self, path: &[String]) -> Option<Vec<String>> {
         for i in 0..=path.len() {
             let prefix = &path[..i];
-            let item = self.private_map.get(prefix);
-            if item.is_some() {
+            if self.private_map.contains_key(prefix)
+                || self.private_files.contains_key(prefix)
+                || self.snapshot_files.contains_key(prefix)
+            {
                 return Some(prefix.to_vec());
             }
         }
@@ -239,9 +569,144 @@ impl<B: BlockStore> RootTree<B> {
             (path, Partition::Private(_, private_root)) => {
                 private_root.ls(path, true, &self.forest, &self.store).await
             }
+            (_, Partition::PrivateFile(..) | Partition::Snapshot(..)) => {
+                bail!(FsError::NotADirectory)
+            }
         }
     }
 
+    /// Recursively walks the directory at given path.
+    ///
+    /// Yielded paths are full paths including the partition prefix, so they can be
+    /// passed back into other `RootTree` functions.
+    pub async fn walk(
+        &self,
+        path: &[String],
+        options: WalkOptions,
+    ) -> Result<BoxStream<'_, Result<WalkEntry<RootTreeNode>>>> {
+        let stream = match self.get_partition(path)? {
+            (relative_path, Partition::Public(root) | Partition::Exchange(root)) => {
+                let dir = if relative_path.is_empty() {
+                    root
+                } else {
+                    root.get_node(relative_path, &self.store)
+                        .await?
+                        .ok_or(FsError::NotFound)?
+                        .as_dir()?
+                };
+
+                boxed_stream(
+                    PublicDirectory::walk_from(path.to_vec(), dir, options, &self.store).map_ok(
+                        |(path, node, metadata)| (path, RootTreeNode::Public(node), metadata),
+                    ),
+                )
+            }
+            (relative_path, Partition::Private(_, root)) => {
+                let dir = match root
+                    .get_leaf_dir(relative_path, true, &self.forest, &self.store)
+                    .await?
+                {
+                    SearchResult::Found(dir) => dir,
+                    SearchResult::NotADir(_, _) => bail!(FsError::NotADirectory),
+                    _ => bail!(FsError::NotFound),
+                };
+
+                boxed_stream(
+                    PrivateDirectory::walk_from(
+                        path.to_vec(),
+                        dir,
+                        options,
+                        &self.forest,
+                        &self.store,
+                    )
+                    .map_ok(|(path, node, metadata)| (path, RootTreeNode::Private(node), metadata)),
+                )
+            }
+            (_, Partition::PrivateFile(..) | Partition::Snapshot(..)) => {
+                bail!(FsError::NotADirectory)
+            }
+        };
+
+        Ok(stream)
+    }
+
+    /// Finds all nodes below given path that match a query.
+    ///
+    /// Glob patterns in the query are matched against paths relative to `path`,
+    /// while yielded paths are full paths including the partition prefix.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use futures::TryStreamExt;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     find::{FindQuery, Glob},
+    ///     root_tree::RootTree,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut root_tree = RootTree::empty(MemoryBlockStore::new());
+    ///     root_tree.create_private_root(&["private".into()]).await?;
+    ///
+    ///     let notes = ["private".into(), "docs".into(), "notes.md".into()];
+    ///     let image = ["private".into(), "docs".into(), "image.png".into()];
+    ///     root_tree.write(&notes, b"# Notes".to_vec()).await?;
+    ///     root_tree.write(&image, vec![0; 100]).await?;
+    ///
+    ///     let query = FindQuery::new().glob(Glob::new("**/*.md")?);
+    ///     let results = root_tree
+    ///         .find(&["private".into()], query)
+    ///         .await?
+    ///         .map_ok(|(path, _, _)| path)
+    ///         .try_collect::<Vec<_>>()
+    ///         .await?;
+    ///
+    ///     assert_eq!(results, [notes]);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn find(
+        &self,
+        path: &[String],
+        query: FindQuery,
+    ) -> Result<BoxStream<'_, Result<WalkEntry<RootTreeNode>>>> {
+        let root_depth = path.len();
+        let walk = self.walk(path, query.walk_options(root_depth)).await?;
+        let query = Arc::new(query);
+
+        Ok(boxed_stream(walk.try_filter_map(
+            move |(path, node, metadata)| {
+                let query = Arc::clone(&query);
+                async move {
+                    if !query.matches(&path[root_depth..], node.kind(), &metadata) {
+                        return Ok(None);
+                    }
+
+                    if query.needs_size() && !query.matches_size(self.node_size(&node).await?) {
+                        return Ok(None);
+                    }
+
+                    Ok(Some((path, node, metadata)))
+                }
+            },
+        )))
+    }
+
+    /// Gets the size of a file's content, or zero for directories.
+    async fn node_size(&self, node: &RootTreeNode) -> Result<u64> {
+        Ok(match node {
+            RootTreeNode::Public(PublicNode::File(file)) => file.size(&self.store).await?,
+            RootTreeNode::Private(PrivateNode::File(file)) => {
+                file.size(&self.forest, &self.store).await?
+            }
+            _ => 0,
+        })
+    }
+
     pub async fn read(&self, path: &[String]) -> Result<Vec<u8>> {
         match self.get_partition(path)? {
             (path, Partition::Public(public_root)) => public_root.read(path, &self.store).await,
@@ -253,9 +718,94 @@ impl<B: BlockStore> RootTree<B> {
                     .read(path, true, &self.forest, &self.store)
                     .await
             }
+            (_, Partition::PrivateFile(_, file) | Partition::Snapshot(_, file)) => {
+                file.get_content(&self.forest, &self.store).await
+            }
+        }
+    }
+
+    /// Reads a number of bytes from the file at given path, starting from a given offset.
+    pub async fn read_at(
+        &self,
+        path: &[String],
+        byte_offset: u64,
+        len_limit: Option<usize>,
+    ) -> Result<Vec<u8>> {
+        match self.get_node(path).await? {
+            RootTreeNode::Public(node) => {
+                node.as_file()?
+                    .read_at(byte_offset, len_limit, &self.store)
+                    .await
+            }
+            RootTreeNode::Private(node) => {
+                node.as_file()?
+                    .read_at(byte_offset, len_limit, &self.forest, &self.store)
+                    .await
+            }
         }
     }
 
+    /// Streams the content of the file at given path in chunks,
+    /// without loading all of it into memory at once.
+    pub async fn read_stream(&self, path: &[String]) -> Result<BoxStream<'_, Result<Vec<u8>>>> {
+        Ok(match self.get_node(path).await? {
+            RootTreeNode::Public(node) => {
+                let file = node.as_file()?;
+                boxed_stream(try_stream! {
+                    let mut reader = Box::pin(file.stream_content(0, &self.store).await?);
+                    loop {
+                        let mut chunk = vec![0; MAX_BLOCK_SIZE];
+                        let len = reader.read(&mut chunk).await?;
+                        if len == 0 {
+                            break;
+                        }
+
+                        chunk.truncate(len);
+                        yield chunk;
+                    }
+                })
+            }
+            RootTreeNode::Private(node) => {
+                let file = node.as_file()?;
+                boxed_stream(try_stream! {
+                    let mut chunks = file.stream_content(0, &self.forest, &self.store);
+                    while let Some(chunk) = chunks.next().await {
+                        yield chunk?;
+                    }
+                })
+            }
+        })
+    }
+
+    /// Gets the exact size of the content of the file at given path.
+    pub async fn size(&self, path: &[String]) -> Result<u64> {
+        let node = self.get_node(path).await?;
+        ensure!(node.kind() == NodeKind::File, FsError::NotAFile);
+        self.node_size(&node).await
+    }
+
+    /// Checks whether there's a file or directory at given path.
+    pub async fn exists(&self, path: &[String]) -> Result<bool> {
+        Ok(self.lookup(path).await?.is_some())
+    }
+
+    /// Gets the kind, size and metadata of the file or directory at given path.
+    pub async fn stat(&self, path: &[String]) -> Result<NodeStat> {
+        let node = self.lookup(path).await?.ok_or(FsError::NotFound)?;
+
+        Ok(NodeStat {
+            kind: node.kind(),
+            size: self.node_size(&node).await?,
+            metadata: node.get_metadata().clone(),
+        })
+    }
+
+    /// Gets the metadata of the file or directory at given path.
+    pub async fn get_metadata(&self, path: &[String]) -> Result<Metadata> {
+        let node = self.lookup(path).await?.ok_or(FsError::NotFound)?;
+        Ok(node.get_metadata().clone())
+    }
+
     pub async fn write(&mut self, path: &[String], content: Vec<u8>) -> Result<()> {
         self.write_with(path, content, Utc::now(), &mut ChaCha12Rng::from_entropy())
             .await
@@ -286,10 +836,242 @@ impl<B: BlockStore> RootTree<B> {
                     .await?;
                 Partition::Private(prefix, private_root)
             }
+            (_, Partition::PrivateFile(prefix, mut file)) => {
+                file.prepare_next_revision()?
+                    .set_content(&content[..], time, forest, &self.store, rng)
+                    .await?;
+                Partition::PrivateFile(prefix, file)
+            }
+            (_, Partition::Snapshot(..)) => bail!(FsError::ReadOnlySnapshot),
         };
 
         self.forest = Arc::clone(forest);
         self.save_partition(partition);
+        self.notifier
+            .notify(ChangeEvent::Write(self.changed_path(path)));
+
+        Ok(())
+    }
+
+    pub async fn write_stream(
+        &mut self,
+        path: &[String],
+        content: impl AsyncRead + CondSend + Unpin,
+    ) -> Result<()> {
+        self.write_stream_with(path, content, Utc::now(), &mut ChaCha12Rng::from_entropy())
+            .await
+    }
+
+    /// Writes a file, streaming in its content instead of holding it in memory.
+    pub async fn write_stream_with(
+        &mut self,
+        path: &[String],
+        content: impl AsyncRead + CondSend + Unpin,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        self.open_file_mut_with(path, time, rng)
+            .await?
+            .set_content(content, time, rng)
+            .await
+    }
+
+    pub async fn open_file_mut(&mut self, path: &[String]) -> Result<RootTreeFileMut<'_, B>> {
+        self.open_file_mut_with(path, Utc::now(), &mut ChaCha12Rng::from_entropy())
+            .await
+    }
+
+    /// Opens the file at given path for writing, creating it if it doesn't exist yet.
+    ///
+    /// Subscribers are notified of the write once the returned file is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use ipld_core::ipld::Ipld;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{common::MemoryBlockStore, root_tree::RootTree};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let mut root_tree = RootTree::empty(MemoryBlockStore::new());
+    ///     root_tree.create_private_root(&["private".into()]).await?;
+    ///
+    ///     let path = ["private".into(), "notes.md".into()];
+    ///     let mut file = root_tree.open_file_mut_with(&path, Utc::now(), rng).await?;
+    ///     file.set_content(&b"# Notes"[..], Utc::now(), rng).await?;
+    ///     file.get_metadata_mut().put("tag", Ipld::String("draft".into()));
+    ///     drop(file);
+    ///
+    ///     assert_eq!(root_tree.read(&path).await?, b"# Notes");
+    ///     assert_eq!(
+    ///         root_tree.get_metadata(&path).await?.get("tag"),
+    ///         Some(&Ipld::String("draft".into()))
+    ///     );
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn open_file_mut_with(
+        &mut self,
+        path: &[String],
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<RootTreeFileMut<'_, B>> {
+        self.open_file_notifying(path, ChangeEvent::Write, time, rng)
+            .await
+    }
+
+    /// Opens a file for writing, notifying subscribers with given event once it's closed again.
+    async fn open_file_notifying(
+        &mut self,
+        path: &[String],
+        event: fn(ChangedPath) -> ChangeEvent,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<RootTreeFileMut<'_, B>> {
+        let Some((first, relative_path)) = path.split_first() else {
+            bail!(FsError::InvalidPath(InvalidPathReason::Empty))
+        };
+        let event = Some(event(self.changed_path(path)));
+
+        let file = match first.as_str() {
+            "public" => {
+                let file = self
+                    .public_root
+                    .open_file_mut(relative_path, time, &self.store)
+                    .await?;
+                OpenFile::Public(file, &self.store)
+            }
+            "exchange" => {
+                let file = self
+                    .exchange_root
+                    .open_file_mut(relative_path, time, &self.store)
+                    .await?;
+                OpenFile::Public(file, &self.store)
+            }
+            _ => {
+                let prefix = self
+                    .find_private_root(path)
+                    .ok_or(FsError::PartitionNotFound)?;
+                ensure!(
+                    !self.snapshot_files.contains_key(&prefix),
+                    FsError::ReadOnlySnapshot
+                );
+                if self.private_files.contains_key(&prefix) {
+                    ensure!(prefix.len() == path.len(), FsError::NotADirectory);
+                    let file = self
+                        .private_files
+                        .get_mut(&prefix)
+                        .ok_or(FsError::PartitionNotFound)?
+                        .prepare_next_revision()?;
+                    file.get_metadata_mut().upsert_mtime(time);
+                    return Ok(RootTreeFileMut {
+                        file: OpenFile::Private(file, &mut self.forest, &self.store),
+                        notifier: &self.notifier,
+                        event,
+                    });
+                }
+
+                let root = self
+                    .private_map
+                    .get_mut(&prefix)
+                    .ok_or(FsError::PartitionNotFound)?;
+                let file = root
+                    .open_file_mut(
+                        &path[prefix.len()..],
+                        true,
+                        time,
+                        &mut self.forest,
+                        &self.store,
+                        rng,
+                    )
+                    .await?;
+                OpenFile::Private(file, &mut self.forest, &self.store)
+            }
+        };
+
+        Ok(RootTreeFileMut {
+            file,
+            notifier: &self.notifier,
+            event,
+        })
+    }
+
+    pub async fn set_metadata(&mut self, path: &[String], key: &str, value: Ipld) -> Result<()> {
+        self.set_metadata_with(
+            path,
+            key,
+            value,
+            Utc::now(),
+            &mut ChaCha12Rng::from_entropy(),
+        )
+        .await
+    }
+
+    /// Sets a metadata entry on the file or directory at given path.
+    pub async fn set_metadata_with(
+        &mut self,
+        path: &[String],
+        key: &str,
+        value: Ipld,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        let node = self.lookup(path).await?.ok_or(FsError::NotFound)?;
+        if node.kind() == NodeKind::File {
+            self.open_file_notifying(path, ChangeEvent::MetadataChanged, time, rng)
+                .await?
+                .get_metadata_mut()
+                .put(key, value);
+            return Ok(());
+        }
+
+        let forest = &Arc::clone(&self.forest);
+        let partition = match self.get_partition(path)? {
+            (path, Partition::Public(mut public_root)) => {
+                let SearchResult::Found(dir) =
+                    public_root.get_leaf_dir_mut(path, &self.store).await?
+                else {
+                    bail!(FsError::NotFound)
+                };
+                dir.get_metadata_mut().upsert_mtime(time);
+                dir.get_metadata_mut().put(key, value);
+                Partition::Public(public_root)
+            }
+            (path, Partition::Exchange(mut exchange_root)) => {
+                let SearchResult::Found(dir) =
+                    exchange_root.get_leaf_dir_mut(path, &self.store).await?
+                else {
+                    bail!(FsError::NotFound)
+                };
+                dir.get_metadata_mut().upsert_mtime(time);
+                dir.get_metadata_mut().put(key, value);
+                Partition::Exchange(exchange_root)
+            }
+            (path, Partition::Private(prefix, mut private_root)) => {
+                let SearchResult::Found(dir) = private_root
+                    .get_leaf_dir_mut(path, true, forest, &self.store)
+                    .await?
+                else {
+                    bail!(FsError::NotFound)
+                };
+                dir.get_metadata_mut().upsert_mtime(time);
+                dir.get_metadata_mut().put(key, value);
+                Partition::Private(prefix, private_root)
+            }
+            (_, Partition::PrivateFile(..) | Partition::Snapshot(..)) => {
+                bail!(FsError::NotADirectory)
+            }
+        };
+
+        self.save_partition(partition);
+        self.notifier
+            .notify(ChangeEvent::MetadataChanged(self.changed_path(path)));
 
         Ok(())
     }
@@ -321,15 +1103,29 @@ impl<B: BlockStore> RootTree<B> {
                     .await?;
                 Partition::Private(prefix, private_root)
             }
+            (_, Partition::PrivateFile(..) | Partition::Snapshot(..)) => {
+                bail!(FsError::FileAlreadyExists)
+            }
         };
 
         self.forest = Arc::clone(forest);
         self.save_partition(partition);
+        self.notifier
+            .notify(ChangeEvent::Mkdir(self.changed_path(path)));
 
         Ok(())
     }
 
     pub async fn rm(&mut self, path: &[String]) -> Result<()> {
+        self.remove(path).await?;
+        self.notifier
+            .notify(ChangeEvent::Rm(self.changed_path(path)));
+
+        Ok(())
+    }
+
+    /// Removes a node without notifying subscribers.
+    async fn remove(&mut self, path: &[String]) -> Result<()> {
         let forest = &mut Arc::clone(&self.forest);
         let partition = match self.get_partition(path)? {
             (path, Partition::Public(mut public_root)) => {
@@ -344,6 +1140,9 @@ impl<B: BlockStore> RootTree<B> {
                 private_root.rm(path, true, forest, &self.store).await?;
                 Partition::Private(prefix, private_root)
             }
+            (_, Partition::PrivateFile(..) | Partition::Snapshot(..)) => {
+                bail!(FsError::InvalidPath(InvalidPathReason::MountedFile))
+            }
         };
 
         self.forest = Arc::clone(forest);
@@ -362,6 +1161,12 @@ impl<B: BlockStore> RootTree<B> {
         .await
     }
 
+    /// Moves a file or directory, possibly into another partition.
+    ///
+    /// Moves into a private partition encrypt the subtree into the forest under new keys.
+    /// Moving private content into the public or exchange partition would reveal it,
+    /// so that fails with [`FsError::PublishingRequired`]. Use [`Self::publish_with`]
+    /// to opt into it. Metadata is carried over in all cases.
     pub async fn basic_mv_with(
         &mut self,
         path_from: &[String],
@@ -384,7 +1189,7 @@ impl<B: BlockStore> RootTree<B> {
                 exchange_root
                     .basic_mv(path_from, path_to, time, &self.store)
                     .await?;
-                Partition::Public(exchange_root)
+                Partition::Exchange(exchange_root)
             }
             (
                 (path_from, Partition::Private(prefix_from, mut private_root)),
@@ -395,123 +1200,2805 @@ impl<B: BlockStore> RootTree<B> {
                     .await?;
                 Partition::Private(prefix_from, private_root)
             }
-            _ => bail!("Moving files or directories across partitions is not yet supported."),
+            _ => {
+                return self
+                    .transfer(path_from, path_to, true, false, time, rng)
+                    .await;
+            }
         };
 
         self.forest = Arc::clone(forest);
         self.save_partition(partition);
+        self.notifier.notify(ChangeEvent::Mv {
+            from: self.changed_path(path_from),
+            to: self.changed_path(path_to),
+        });
 
         Ok(())
     }
 
-    pub async fn store(&mut self) -> Result<Cid> {
-        self.store_with(&mut ChaCha12Rng::from_entropy()).await
+    pub async fn cp(&mut self, path_from: &[String], path_to: &[String]) -> Result<()> {
+        self.cp_with(
+            path_from,
+            path_to,
+            Utc::now(),
+            &mut ChaCha12Rng::from_entropy(),
+        )
+        .await
     }
 
-    pub async fn store_with(&mut self, rng: &mut (impl CryptoRngCore + CondSend)) -> Result<Cid> {
-        for (_, root) in self.private_map.iter() {
-            root.store(&mut self.forest, &self.store, rng).await?;
-        }
-
-        let serializable = RootTreeSerializable {
-            public: self.public_root.store(&self.store).await?,
-            exchange: self.exchange_root.store(&self.store).await?,
-            forest: self.forest.store(&self.store).await?,
-            version: WNFS_VERSION,
+    /// Copies a file or directory, possibly into another partition.
+    ///
+    /// Crossing partitions works like with [`Self::basic_mv_with`]: Copies into a private
+    /// partition are encrypted, and copies of private content into the public or exchange
+    /// partition need [`Self::publish_with`].
+    pub async fn cp_with(
+        &mut self,
+        path_from: &[String],
+        path_to: &[String],
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        let forest = &mut Arc::clone(&self.forest);
+        let partition = match (self.get_partition(path_from)?, self.get_partition(path_to)?) {
+            ((path_from, Partition::Public(mut public_root)), (path_to, Partition::Public(_))) => {
+                public_root
+                    .cp(path_from, path_to, time, &self.store)
+                    .await?;
+                Partition::Public(public_root)
+            }
+            (
+                (path_from, Partition::Exchange(mut exchange_root)),
+                (path_to, Partition::Exchange(_)),
+            ) => {
+                exchange_root
+                    .cp(path_from, path_to, time, &self.store)
+                    .await?;
+                Partition::Exchange(exchange_root)
+            }
+            (
+                (path_from, Partition::Private(prefix_from, mut private_root)),
+                (path_to, Partition::Private(prefix_to, _)),
+            ) if prefix_from == prefix_to => {
+                private_root
+                    .cp(path_from, path_to, true, time, forest, &self.store, rng)
+                    .await?;
+                Partition::Private(prefix_from, private_root)
+            }
+            _ => {
+                return self
+                    .transfer(path_from, path_to, false, false, time, rng)
+                    .await;
+            }
         };
 
-        let cid = self
-            .store
-            .put_block(serde_ipld_dagcbor::to_vec(&serializable)?, CODEC_DAG_CBOR)
-            .await?;
+        self.forest = Arc::clone(forest);
+        self.save_partition(partition);
+        self.notifier.notify(ChangeEvent::Cp {
+            from: self.changed_path(path_from),
+            to: self.changed_path(path_to),
+        });
 
-        Ok(cid)
+        Ok(())
     }
 
-    pub async fn load(cid: &Cid, store: B) -> Result<RootTree<B>> {
-        let deserialized: RootTreeSerializable =
+    pub async fn publish(&mut self, path_from: &[String], path_to: &[String]) -> Result<()> {
+        self.publish_with(
+            path_from,
+            path_to,
+            Utc::now(),
+            &mut ChaCha12Rng::from_entropy(),
+        )
+        .await
+    }
+
+    /// Copies a file or directory like [`Self::cp_with`], but also allows copying
+    /// private content into the public or exchange partition.
+    ///
+    /// The decrypted subtree is stored as public files and directories with the same metadata,
+    /// so anyone with access to the store can read it. The private original is kept,
+    /// remove it with [`Self::rm`] to complete a move.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use wnfs::{common::MemoryBlockStore, error::FsError, root_tree::RootTree};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut root_tree = RootTree::empty(MemoryBlockStore::new());
+    ///     root_tree.create_private_root(&["private".into()]).await?;
+    ///
+    ///     let draft = ["private".into(), "post.md".into()];
+    ///     let post = ["public".into(), "post.md".into()];
+    ///     root_tree.write(&draft, b"# Hello".to_vec()).await?;
+    ///
+    ///     let error = root_tree.cp(&draft, &post).await.unwrap_err();
+    ///     assert!(matches!(error.downcast_ref(), Some(FsError::PublishingRequired)));
+    ///
+    ///     root_tree.publish(&draft, &post).await?;
+    ///     assert_eq!(root_tree.read(&post).await?, b"# Hello");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn publish_with(
+        &mut self,
+        path_from: &[String],
+        path_to: &[String],
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        self.transfer(path_from, path_to, false, true, time, rng)
+            .await
+    }
+
+    /// Copies a node into any partition, converting it between its public and private
+    /// representation as needed, and removes the original if `remove_source` is set.
+    async fn transfer(
+        &mut self,
+        path_from: &[String],
+        path_to: &[String],
+        remove_source: bool,
+        allow_publishing: bool,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        if remove_source {
+            // Mounted files can't be removed from their own mount point
+            let (_, partition) = self.get_partition(path_from)?;
+            ensure!(
+                !matches!(
+                    partition,
+                    Partition::PrivateFile(..) | Partition::Snapshot(..)
+                ),
+                FsError::InvalidPath(InvalidPathReason::MountedFile)
+            );
+        }
+
+        let node = self.get_node(path_from).await?;
+        let forest = &mut Arc::clone(&self.forest);
+        let partition = match self.get_partition(path_to)? {
+            (path_to, Partition::Public(mut root)) => {
+                let node = self.publishable(node, allow_publishing, time).await?;
+                root.attach(node, path_to, time, &self.store).await?;
+                Partition::Public(root)
+            }
+            (path_to, Partition::Exchange(mut root)) => {
+                let node = self.publishable(node, allow_publishing, time).await?;
+                root.attach(node, path_to, time, &self.store).await?;
+                Partition::Exchange(root)
+            }
+            (path_to, Partition::Private(prefix, mut root)) => {
+                let node = match node {
+                    RootTreeNode::Private(node) => node,
+                    RootTreeNode::Public(node) => {
+                        PrivateNode::from_public(
+                            &node,
+                            root.header.get_name(),
+                            time,
+                            forest,
+                            &self.store,
+                            rng,
+                        )
+                        .await?
+                    }
+                };
+                root.attach(node, path_to, true, time, forest, &self.store, rng)
+                    .await?;
+                Partition::Private(prefix, root)
+            }
+            (_, Partition::PrivateFile(..) | Partition::Snapshot(..)) => {
+                bail!(FsError::FileAlreadyExists)
+            }
+        };
+
+        self.forest = Arc::clone(forest);
+        self.save_partition(partition);
+
+        if remove_source {
+            self.remove(path_from).await?;
+        }
+
+        let (from, to) = (self.changed_path(path_from), self.changed_path(path_to));
+        self.notifier.notify(match remove_source {
+            true => ChangeEvent::Mv { from, to },
+            false => ChangeEvent::Cp { from, to },
+        });
+
+        Ok(())
+    }
+
+    /// Turns a node into a public node, decrypting it if it's private and publishing is allowed.
+    async fn publishable(
+        &self,
+        node: RootTreeNode,
+        allow_publishing: bool,
+        time: DateTime<Utc>,
+    ) -> Result<PublicNode> {
+        match node {
+            RootTreeNode::Public(node) => Ok(node),
+            RootTreeNode::Private(node) => {
+                ensure!(allow_publishing, FsError::PublishingRequired);
+                node.to_public(time, &self.forest, &self.store).await
+            }
+        }
+    }
+
+    /// Gets the node at given path, which must be below a partition root or a mounted file.
+    async fn get_node(&self, path: &[String]) -> Result<RootTreeNode> {
+        let (relative_path, partition) = self.get_partition(path)?;
+        ensure!(
+            !relative_path.is_empty()
+                || matches!(
+                    partition,
+                    Partition::PrivateFile(..) | Partition::Snapshot(..)
+                ),
+            FsError::InvalidPath(InvalidPathReason::PartitionRoot)
+        );
+
+        Ok(self.lookup(path).await?.ok_or(FsError::NotFound)?)
+    }
+
+    /// Looks up the node at given path, including the partition roots themselves.
+    async fn lookup(&self, path: &[String]) -> Result<Option<RootTreeNode>> {
+        Ok(match self.get_partition(path)? {
+            ([], Partition::Public(root) | Partition::Exchange(root)) => {
+                Some(RootTreeNode::Public(PublicNode::Dir(root)))
+            }
+            ([], Partition::Private(_, root)) => Some(RootTreeNode::Private(root.as_node())),
+            (_, Partition::PrivateFile(_, file) | Partition::Snapshot(_, file)) => {
+                Some(RootTreeNode::Private(file.as_node()))
+            }
+            (path, Partition::Public(root) | Partition::Exchange(root)) => root
+                .get_node(path, &self.store)
+                .await?
+                .cloned()
+                .map(RootTreeNode::Public),
+            (path, Partition::Private(_, root)) => root
+                .get_node(path, true, &self.forest, &self.store)
+                .await?
+                .map(RootTreeNode::Private),
+        })
+    }
+
+    /// Restores the file at given path to the revision picked by `revision`.
+    ///
+    /// The old content and metadata are written as a new revision, so restoring can be
+    /// undone like any other change. In private partitions, only files can be restored.
+    /// Their history goes back to when the private root was created, if that was recorded
+    /// in the mount table it was loaded from, or else to when it was loaded.
+    pub async fn restore_revision(
+        &mut self,
+        path: &[String],
+        revision: RevisionSelector,
+    ) -> Result<()> {
+        self.restore_revision_with(path, revision, Utc::now()).await
+    }
+
+    pub async fn restore_revision_with(
+        &mut self,
+        path: &[String],
+        revision: RevisionSelector,
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        let partition = match self.get_partition(path)? {
+            (path, Partition::Public(mut public_root)) => {
+                public_root
+                    .restore_revision(path, revision, time, &self.store)
+                    .await?;
+                Partition::Public(public_root)
+            }
+            (path, Partition::Exchange(mut exchange_root)) => {
+                exchange_root
+                    .restore_revision(path, revision, time, &self.store)
+                    .await?;
+                Partition::Exchange(exchange_root)
+            }
+            (path, Partition::Private(prefix, mut private_root)) => {
+                let past = self
+                    .private_bases
+                    .get(&prefix)
+                    .cloned()
+                    .unwrap_or_else(|| Arc::clone(&private_root));
+
+                private_root
+                    .restore_revision(
+                        path,
+                        revision,
+                        &past,
+                        HISTORY_DISCREPANCY_BUDGET,
+                        time,
+                        &self.forest,
+                        &self.store,
+                    )
+                    .await?;
+                Partition::Private(prefix, private_root)
+            }
+            (_, Partition::PrivateFile(..)) => {
+                bail!("Restoring revisions of mounted private files isn't supported")
+            }
+            (_, Partition::Snapshot(..)) => bail!(FsError::ReadOnlySnapshot),
+        };
+
+        self.save_partition(partition);
+        self.notifier
+            .notify(ChangeEvent::Write(self.changed_path(path)));
+
+        Ok(())
+    }
+
+    pub async fn add_exchange_key(
+        &mut self,
+        device: &str,
+        modulus: PublicKeyModulus,
+    ) -> Result<()> {
+        self.add_exchange_key_with(device, modulus, Utc::now())
+            .await
+    }
+
+    /// Publishes the exchange key of a device in the exchange partition, so others can share
+    /// private data with it.
+    ///
+    /// Keys are stored at `exchange/<device>/v1.exchange_key`, which is where
+    /// [`fetch_exchange_keys`][crate::private::share::sharer::fetch_exchange_keys] looks for them.
+    /// Each device has a single key, so rotating it means removing the device's key and adding
+    /// the new one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use wnfs::{common::MemoryBlockStore, root_tree::RootTree};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut root_tree = RootTree::empty(MemoryBlockStore::new());
+    ///     root_tree.add_exchange_key("laptop", vec![1; 256]).await?;
+    ///     root_tree.add_exchange_key("phone", vec![2; 256]).await?;
+    ///     root_tree.remove_exchange_key("laptop").await?;
+    ///
+    ///     let keys = root_tree.exchange_keys().await?;
+    ///     assert_eq!(keys.len(), 1);
+    ///     assert_eq!(keys[0].device, "phone");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn add_exchange_key_with(
+        &mut self,
+        device: &str,
+        modulus: PublicKeyModulus,
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        let device = WnfsPath::normalize_name(device)?;
+        let exists = self
+            .exchange_root
+            .get_node(std::slice::from_ref(&device), &self.store)
+            .await?
+            .is_some();
+        ensure!(!exists, FsError::DirectoryAlreadyExists);
+
+        let path = ["exchange".into(), device, EXCHANGE_KEY_NAME.into()];
+        self.exchange_root
+            .write(&path[1..], modulus, time, &self.store)
+            .await?;
+        self.notifier
+            .notify(ChangeEvent::Write(self.changed_path(&path)));
+
+        Ok(())
+    }
+
+    /// Lists the exchange keys of all devices in the exchange partition.
+    pub async fn exchange_keys(&self) -> Result<Vec<DeviceExchangeKey>> {
+        let mut keys = Vec::new();
+        for (device, _) in self.exchange_root.ls(&[], &self.store).await? {
+            let path = [device.clone(), EXCHANGE_KEY_NAME.into()];
+            let Some(PublicNode::File(file)) =
+                self.exchange_root.get_node(&path, &self.store).await?
+            else {
+                continue;
+            };
+
+            keys.push(DeviceExchangeKey {
+                device,
+                modulus: file.get_content(&self.store).await?,
+                created: file.get_metadata().get_created(),
+            });
+        }
+
+        Ok(keys)
+    }
+
+    /// Removes a device and its exchange key from the exchange partition.
+    ///
+    /// Shares made for the device before can still be received with its private key.
+    pub async fn remove_exchange_key(&mut self, device: &str) -> Result<()> {
+        let device = WnfsPath::normalize_name(device)?;
+        let key_path = [device.clone(), EXCHANGE_KEY_NAME.into()];
+        let exists = self
+            .exchange_root
+            .get_node(&key_path, &self.store)
+            .await?
+            .is_some();
+        ensure!(exists, FsError::NotFound);
+
+        self.rm(&["exchange".into(), device]).await
+    }
+
+    pub async fn share_path<K: ExchangeKey>(
+        &mut self,
+        path: &[String],
+        recipient_exchange_root: PublicLink,
+        mode: ShareMode,
+        sharer_root_did: &str,
+    ) -> Result<u64> {
+        self.share_path_with::<K>(
+            path,
+            recipient_exchange_root,
+            mode,
+            sharer_root_did,
+            &mut ChaCha12Rng::from_entropy(),
+        )
+        .await
+    }
+
+    /// Shares the private file or directory at given path with all devices in a recipient's
+    /// exchange partition.
+    ///
+    /// The share counter is picked automatically, as the next one that's free for all of the
+    /// recipient's devices. Returns the share counter that was used.
+    ///
+    /// The share is recorded along with the path, so that [`Self::revoke_path`] can remove it.
+    ///
+    /// Snapshot shares can only be made of files, since the keys to a directory's entries
+    /// can't be derived from a snapshot key.
+    pub async fn share_path_with<K: ExchangeKey>(
+        &mut self,
+        path: &[String],
+        recipient_exchange_root: PublicLink,
+        mode: ShareMode,
+        sharer_root_did: &str,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<u64> {
+        ensure!(
+            !matches!(self.get_partition(path)?, (_, Partition::Snapshot(..))),
+            FsError::ReadOnlySnapshot
+        );
+        let RootTreeNode::Private(node) = self.get_node(path).await? else {
+            bail!("Path is not in the private partition");
+        };
+        ensure!(
+            mode == ShareMode::Temporal || node.is_file(),
+            FsError::NotAFile
+        );
+
+        let forest = &mut Arc::clone(&self.forest);
+        let access_key = match node.store(forest, &self.store, rng).await? {
+            access_key if mode == ShareMode::Temporal => access_key,
+            access_key => AccessKey::Snapshot(SnapshotAccessKey {
+                label: *access_key.get_label(),
+                content_cid: *access_key.get_content_cid(),
+                snapshot_key: access_key.get_snapshot_key(),
+            }),
+        };
+
+        let recipient_keys =
+            sharer::fetch_exchange_keys(recipient_exchange_root.clone(), &self.store)
+                .await
+                .try_collect::<Vec<_>>()
+                .await?;
+        ensure!(!recipient_keys.is_empty(), ShareError::NoSharerOrRecipients);
+
+        let mut share_count = 0;
+        for recipient_key in recipient_keys.iter() {
+            let latest = recipient::find_latest_share_counter(
+                0,
+                SHARE_COUNTER_SEARCH_LIMIT,
+                recipient_key,
+                sharer_root_did,
+                forest,
+                &self.store,
+            )
+            .await?;
+            if let Some(latest) = latest {
+                share_count = share_count.max(latest + 1);
+            }
+        }
+
+        sharer::share::<K>(
+            &access_key,
+            share_count,
+            sharer_root_did,
+            recipient_exchange_root,
+            forest,
+            &self.store,
+        )
+        .await?;
+        self.forest = Arc::clone(forest);
+        self.shares
+            .entry(path.to_vec())
+            .or_default()
+            .extend(recipient_keys.into_iter().map(|key| ShareRecord {
+                sharer_root_did: sharer_root_did.into(),
+                recipient_exchange_key: ByteBuf::from(key),
+                share_count,
+            }));
+
+        Ok(share_count)
+    }
+
+    /// Finds the latest share from a sharer for given exchange key and mounts the shared
+    /// private file or directory at given path.
+    ///
+    /// The sharer's forest is merged into this root tree's forest, so that later revisions
+    /// of temporal shares can be followed. This requires both forests to use the same
+    /// accumulator setup.
+    ///
+    /// Snapshot shares of files are mounted read-only: They can be read and copied elsewhere,
+    /// but changing them fails with [`FsError::ReadOnlySnapshot`].
+    ///
+    /// Returns the share counter of the accepted share.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use std::{collections::BTreeMap, sync::Arc};
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     private::{
+    ///         ExchangeKey, PUBLIC_KEY_EXPONENT, PrivateKey,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     public::PublicLink,
+    ///     root_tree::{RootTree, ShareMode},
+    /// };
+    /// # use rsa::{BigUint, Oaep, RsaPrivateKey, RsaPublicKey, traits::PublicKeyParts};
+    /// # use sha2::Sha256;
+    /// #
+    /// # struct PublicExchangeKey(RsaPublicKey);
+    /// # struct PrivateExchangeKey(RsaPrivateKey);
+    /// #
+    /// # impl ExchangeKey for PublicExchangeKey {
+    /// #     async fn from_modulus(modulus: &[u8]) -> Result<Self> {
+    /// #         let (n, e) = (BigUint::from_bytes_be(modulus), BigUint::from(PUBLIC_KEY_EXPONENT));
+    /// #         Ok(Self(RsaPublicKey::new(n, e)?))
+    /// #     }
+    /// #
+    /// #     async fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+    /// #         Ok(self.0.encrypt(&mut rand::thread_rng(), Oaep::new::<Sha256>(), data)?)
+    /// #     }
+    /// # }
+    /// #
+    /// # impl PrivateKey for PrivateExchangeKey {
+    /// #     async fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+    /// #         Ok(self.0.decrypt(Oaep::new::<Sha256>(), ciphertext)?)
+    /// #     }
+    /// # }
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let mut sharer = RootTree::empty(store);
+    ///     sharer.create_private_root(&["private".into()]).await?;
+    ///     let notes = ["private".into(), "notes.md".into()];
+    ///     sharer.write(&notes, b"# Notes".to_vec()).await?;
+    ///
+    ///     // Both forests need to use the same accumulator setup
+    ///     let forest = HamtForest::new(sharer.forest.get_accumulator_setup().clone());
+    ///     let mut recipient = RootTree::new(Arc::new(forest), store, Utc::now(), BTreeMap::new()).await;
+    ///     let key = PrivateExchangeKey(RsaPrivateKey::new(&mut rand::thread_rng(), 2048)?);
+    ///     let modulus = key.0.n().to_bytes_be();
+    ///     recipient.add_exchange_key("laptop", modulus.clone()).await?;
+    ///     let exchange_root = PublicLink::with_rc_dir(Arc::clone(&recipient.exchange_root));
+    ///
+    ///     sharer
+    ///         .share_path::<PublicExchangeKey>(&notes, exchange_root, ShareMode::Snapshot, "did:key:sharer")
+    ///         .await?;
+    ///
+    ///     let shared = ["private".into(), "notes.md".into()];
+    ///     recipient
+    ///         .accept_share(&shared, "did:key:sharer", &sharer.forest, &key, &modulus)
+    ///         .await?;
+    ///
+    ///     assert_eq!(recipient.read(&shared).await?, b"# Notes");
+    ///     assert!(recipient.write(&shared, b"# Edited".to_vec()).await.is_err());
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn accept_share(
+        &mut self,
+        mount_path: &[String],
+        sharer_root_did: &str,
+        sharer_forest: &Arc<HamtForest>,
+        recipient_key: &impl PrivateKey,
+        recipient_exchange_key: &[u8],
+    ) -> Result<u64> {
+        ensure!(
+            mount_path.first().is_some_and(|first| first == "private"),
+            FsError::InvalidPath(InvalidPathReason::NotPrivate)
+        );
+        ensure!(
+            self.find_private_root(mount_path).is_none(),
+            FsError::DirectoryAlreadyExists
+        );
+
+        let share_count = recipient::find_latest_share_counter(
+            0,
+            SHARE_COUNTER_SEARCH_LIMIT,
+            recipient_exchange_key,
+            sharer_root_did,
+            sharer_forest,
+            &self.store,
+        )
+        .await?
+        .ok_or(ShareError::AccessKeyNotFound)?;
+        let share_label = sharer::create_share_name(
+            share_count,
+            sharer_root_did,
+            recipient_exchange_key,
+            sharer_forest,
+        );
+
+        let access_key =
+            recipient::receive_access_key(&share_label, recipient_key, sharer_forest, &self.store)
+                .await?;
+        self.mount_access_key(mount_path.to_vec(), access_key, sharer_forest)
+            .await?;
+        self.forest = Arc::new(self.forest.merge(sharer_forest, &self.store).await?);
+        self.notifier
+            .notify(ChangeEvent::Mkdir(self.changed_path(mount_path)));
+
+        Ok(share_count)
+    }
+
+    pub async fn revoke_path<K: ExchangeKey>(
+        &mut self,
+        path: &[String],
+        revoked_exchange_roots: &[PublicLink],
+        remaining_exchange_roots: &[PublicLink],
+        sharer_root_did: &str,
+    ) -> Result<()> {
+        self.revoke_path_with::<K>(
+            path,
+            revoked_exchange_roots,
+            remaining_exchange_roots,
+            sharer_root_did,
+            Utc::now(),
+            &mut ChaCha12Rng::from_entropy(),
+        )
+        .await
+    }
+
+    /// Revokes access to the private file or directory at given path from some recipients.
+    ///
+    /// The subtree is re-keyed by giving it fresh inumbers and ratchets, so that none of its
+    /// future revisions can be read with access keys shared before. The new access key is then
+    /// shared with the remaining recipients.
+    ///
+    /// The shares of this path recorded for the revoked recipients are removed from the forest.
+    /// Their share labels are kept without an access key, so that the recipients' later shares
+    /// can still be found by their share counters. Shares of other paths are left untouched.
+    /// Revoked recipients who already received the access key can still read the revisions
+    /// they had access to.
+    ///
+    /// Subscribers are notified with [`ChangeEvent::Revoked`].
+    #[allow(clippy::too_many_arguments)]
+    pub async fn revoke_path_with<K: ExchangeKey>(
+        &mut self,
+        path: &[String],
+        revoked_exchange_roots: &[PublicLink],
+        remaining_exchange_roots: &[PublicLink],
+        sharer_root_did: &str,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        let forest = &mut Arc::clone(&self.forest);
+        let (prefix, root) = match self.get_partition(path)? {
+            ([], Partition::Private(..)) => {
+                bail!(FsError::InvalidPath(InvalidPathReason::PartitionRoot))
+            }
+            (path, Partition::Private(prefix, mut root)) => {
+                root.rotate_keys(path, true, time, forest, &self.store, rng)
+                    .await?;
+                (prefix, root)
+            }
+            (_, Partition::PrivateFile(..)) => {
+                bail!(FsError::InvalidPath(InvalidPathReason::MountedFile))
+            }
+            _ => bail!(FsError::InvalidPath(InvalidPathReason::NotPrivate)),
+        };
+        let mut shares = self.shares.get(path).cloned().unwrap_or_default();
+        for exchange_root in revoked_exchange_roots {
+            let exchange_keys = sharer::fetch_exchange_keys(exchange_root.clone(), &self.store)
+                .await
+                .try_collect::<Vec<_>>()
+                .await?;
+            let (revoked, kept) = shares.into_iter().partition::<BTreeSet<_>, _>(|share| {
+                exchange_keys.contains(&share.recipient_exchange_key.to_vec())
+            });
+            for share in revoked {
+                let share_label = sharer::create_share_name(
+                    share.share_count,
+                    &share.sharer_root_did,
+                    &share.recipient_exchange_key,
+                    forest,
+                );
+                forest.remove_encrypted(&share_label, &self.store).await?;
+                forest.put_encrypted(&share_label, [], &self.store).await?;
+            }
+            shares = kept;
+        }
+
+        self.forest = Arc::clone(forest);
+        self.save_partition(Partition::Private(prefix, root));
+        if shares.is_empty() {
+            self.shares.remove(path);
+        } else {
+            self.shares.insert(path.to_vec(), shares);
+        }
+
+        for exchange_root in remaining_exchange_roots {
+            self.share_path_with::<K>(
+                path,
+                exchange_root.clone(),
+                ShareMode::Temporal,
+                sharer_root_did,
+                rng,
+            )
+            .await?;
+        }
+
+        self.notifier
+            .notify(ChangeEvent::Revoked(self.changed_path(path)));
+
+        Ok(())
+    }
+
+    pub async fn reconcile(&mut self, other: &Self) -> Result<RootTreeReconciliation> {
+        self.reconcile_with(other, &TieBreakPolicy, &mut ChaCha12Rng::from_entropy())
+            .await
+    }
+
+    /// Merges the changes of another version of this root tree into this one,
+    /// resolving conflicts with given policy.
+    ///
+    /// The public and exchange partitions are reconciled directly. The forests are merged,
+    /// after which every private root is fast-forwarded to its latest revision, merging
+    /// concurrent revisions. Private roots only mounted in `other` get mounted here as well.
+    ///
+    /// Both root trees must be able to read each other's blocks from their block stores.
+    /// If reconciling fails, this root tree is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use wnfs::{common::MemoryBlockStore, public::Reconciliation, root_tree::RootTree};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut ours = RootTree::empty(MemoryBlockStore::new());
+    ///     ours.create_private_root(&["private".into()]).await?;
+    ///     ours.store().await?;
+    ///
+    ///     let mut theirs = ours.clone();
+    ///     ours.write(&["private".into(), "ours.md".into()], b"ours".to_vec()).await?;
+    ///     theirs.write(&["private".into(), "theirs.md".into()], b"theirs".to_vec()).await?;
+    ///     theirs.store().await?;
+    ///
+    ///     let report = ours.reconcile(&theirs).await?;
+    ///
+    ///     assert!(matches!(report.private[&vec!["private".to_string()]], Reconciliation::Merged { .. }));
+    ///     assert_eq!(ours.ls(&["private".into()]).await?.len(), 2);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn reconcile_with(
+        &mut self,
+        other: &Self,
+        policy: &(impl ConflictPolicy<PublicNode> + ConflictPolicy<PrivateNode>),
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<RootTreeReconciliation> {
+        // Private revisions can only be found once they're in the forest
+        let mut other_forest = Arc::clone(&other.forest);
+        for (_, node) in other.private_nodes() {
+            node.store(&mut other_forest, &self.store, rng).await?;
+        }
+        let mut forest = Arc::clone(&self.forest);
+        for (_, node) in self.private_nodes() {
+            node.store(&mut forest, &self.store, rng).await?;
+        }
+
+        let mut public_root = Arc::clone(&self.public_root);
+        let public = public_root
+            .reconcile_with(&other.public_root, policy, &self.store)
+            .await?;
+        let mut exchange_root = Arc::clone(&self.exchange_root);
+        let exchange = exchange_root
+            .reconcile_with(&other.exchange_root, policy, &self.store)
+            .await?;
+
+        let forest = Arc::new(forest.merge(&other_forest, &self.store).await?);
+
+        let mut private_map = self.private_map.clone();
+        let mut private_bases = self.private_bases.clone();
+        let mut private_files = self.private_files.clone();
+        let mut snapshot_files = self.snapshot_files.clone();
+        for (path, node) in other.private_nodes() {
+            if private_map.contains_key(&path)
+                || private_files.contains_key(&path)
+                || snapshot_files.contains_key(&path)
+            {
+                continue;
+            }
+            match node {
+                PrivateNode::Dir(dir) => {
+                    private_bases.insert(path.clone(), Arc::clone(&dir));
+                    private_map.insert(path, dir);
+                }
+                PrivateNode::File(file) => {
+                    private_files.insert(path, file);
+                }
+            }
+        }
+        for (path, snapshot) in other.snapshot_files.iter() {
+            if !private_map.contains_key(path) && !private_files.contains_key(path) {
+                snapshot_files
+                    .entry(path.clone())
+                    .or_insert_with(|| snapshot.clone());
+            }
+        }
+        let mut shares = self.shares.clone();
+        for (path, other_shares) in other.shares.iter() {
+            shares
+                .entry(path.clone())
+                .or_default()
+                .extend(other_shares.iter().cloned());
+        }
+
+        let mut private = BTreeMap::new();
+        for (path, dir) in private_map.iter_mut() {
+            let (latest, reconciliation) = dir
+                .as_node()
+                .search_latest_reconciliation(policy, &forest, &self.store)
+                .await?;
+            *dir = latest.as_dir()?;
+            private.insert(path.clone(), reconciliation);
+        }
+        for (path, file) in private_files.iter_mut() {
+            let (latest, reconciliation) = file
+                .as_node()
+                .search_latest_reconciliation(policy, &forest, &self.store)
+                .await?;
+            *file = latest.as_file()?;
+            private.insert(path.clone(), reconciliation);
+        }
+
+        // Only applied once everything was reconciled, so that failures leave this root tree as-is
+        self.forest = forest;
+        self.public_root = public_root;
+        self.exchange_root = exchange_root;
+        self.private_map = private_map;
+        self.private_bases = private_bases;
+        self.private_files = private_files;
+        self.snapshot_files = snapshot_files;
+        self.shares = shares;
+
+        let reconciliation = RootTreeReconciliation {
+            public,
+            exchange,
+            private,
+        };
+        self.notifier
+            .notify(ChangeEvent::Reconciled(reconciliation.clone()));
+
+        Ok(reconciliation)
+    }
+
+    /// Subscribes to all changes made to this root tree from now on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use futures::StreamExt;
+    /// use wnfs::{changes::ChangeEvent, common::MemoryBlockStore, root_tree::RootTree};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut root_tree = RootTree::empty(MemoryBlockStore::new());
+    ///     let mut changes = root_tree.subscribe();
+    ///
+    ///     root_tree.mkdir(&["public".into(), "photos".into()]).await?;
+    ///     let cid = root_tree.store().await?;
+    ///
+    ///     assert!(matches!(changes.next().await, Some(ChangeEvent::Mkdir(_))));
+    ///     assert!(matches!(changes.next().await, Some(ChangeEvent::Stored(stored)) if stored == cid));
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn subscribe(&self) -> UnboundedReceiver<ChangeEvent> {
+        self.notifier.subscribe()
+    }
+
+    /// Pairs given path with the partition it's in.
+    fn changed_path(&self, path: &[String]) -> ChangedPath {
+        let partition = match path.first().map(|p| p.as_str()) {
+            Some("public") => PartitionKind::Public,
+            Some("exchange") => PartitionKind::Exchange,
+            _ => PartitionKind::Private(self.find_private_root(path).unwrap_or_default()),
+        };
+
+        ChangedPath {
+            partition,
+            path: path.to_vec(),
+        }
+    }
+
+    /// Starts a transaction, staging changes until they're committed all at once.
+    ///
+    /// See [`Transaction`] for more information.
+    pub fn transaction(&mut self) -> Transaction<'_, B> {
+        Transaction::new(self)
+    }
+
+    pub async fn store(&mut self) -> Result<Cid> {
+        self.store_with(Utc::now(), &mut ChaCha12Rng::from_entropy())
+            .await
+    }
+
+    /// Stores the root tree, recording given time in the root history if it's enabled.
+    pub async fn store_with(
+        &mut self,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<Cid> {
+        self.store_root(None, time, rng).await
+    }
+
+    /// Stores the root tree, recording it in the root history with given tag and time.
+    async fn store_root(
+        &mut self,
+        tag: Option<String>,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<Cid> {
+        ensure!(
+            tag.is_none() || self.history.is_some(),
+            FsError::HistoryNotEnabled
+        );
+
+        let mut mount_points = Vec::with_capacity(self.private_map.len());
+        for (path, node) in self.private_nodes() {
+            let access_key = node.store(&mut self.forest, &self.store, rng).await?;
+            let stored_base = self
+                .private_bases
+                .get(&path)
+                .filter(|base| base.content.persisted_as.get().is_some())
+                .map(|base| base.as_node());
+            let base = match (stored_base, &node) {
+                (Some(base), _) => Some(base.store(&mut self.forest, &self.store, rng).await?),
+                // Revisions from before the root was first stored can't be followed anyway
+                (None, PrivateNode::Dir(dir)) => {
+                    self.private_bases.insert(path.clone(), Arc::clone(dir));
+                    Some(access_key.clone())
+                }
+                (None, PrivateNode::File(_)) => None,
+            };
+            mount_points.push(MountPoint {
+                path,
+                access_key,
+                base,
+            });
+        }
+        for (path, (access_key, _)) in self.snapshot_files.iter() {
+            mount_points.push(MountPoint {
+                path: path.clone(),
+                access_key: AccessKey::Snapshot(access_key.clone()),
+                base: None,
+            });
+        }
+
+        if let Some(mount_key) = &self.mount_key {
+            let mut table = MountTableSerializable {
+                wrapped_keys: self
+                    .wrapped_mount_keys
+                    .iter()
+                    .cloned()
+                    .map(ByteBuf::from)
+                    .collect(),
+                table: mount_key.encrypt(&serde_ipld_dagcbor::to_vec(&mount_points)?, rng)?,
+                shares: Vec::new(),
+            };
+            if !self.shares.is_empty() {
+                let shared_paths = self
+                    .shares
+                    .iter()
+                    .map(|(path, shares)| SharedPath {
+                        path: path.clone(),
+                        shares: shares.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                table.shares =
+                    mount_key.encrypt(&serde_ipld_dagcbor::to_vec(&shared_paths)?, rng)?;
+            }
+            let cid = self
+                .store
+                .put_block(serde_ipld_dagcbor::to_vec(&table)?, CODEC_DAG_CBOR)
+                .await?;
+            self.mount_table = Some(cid);
+        }
+
+        let serializable = RootTreeSerializable {
+            public: self.public_root.store(&self.store).await?,
+            exchange: self.exchange_root.store(&self.store).await?,
+            forest: self.forest.store(&self.store).await?,
+            version: WNFS_VERSION,
+            mounts: self.mount_table,
+            history: match &self.history {
+                Some(history) => Some(history.store_entry(tag, time, &self.store).await?),
+                None => None,
+            },
+        };
+
+        let cid = self
+            .store
+            .put_block(serde_ipld_dagcbor::to_vec(&serializable)?, CODEC_DAG_CBOR)
+            .await?;
+        if let Some(history) = &mut self.history {
+            history.latest = Some(cid);
+        }
+        self.notifier.notify(ChangeEvent::Stored(cid));
+
+        Ok(cid)
+    }
+
+    /// Starts recording every stored root in the root history.
+    ///
+    /// Roots stored from now on link back to the root stored before them,
+    /// so past roots can be listed with [`Self::history`], checked out and diffed.
+    pub fn enable_history(&mut self) {
+        self.history.get_or_insert_with(RootHistory::default);
+    }
+
+    /// Gets the in-memory state of the root history, if it's enabled.
+    pub fn get_root_history(&self) -> Option<&RootHistory> {
+        self.history.as_ref()
+    }
+
+    /// Lists all roots in the root history, from the latest to the oldest.
+    pub async fn history(&self) -> Result<Vec<RootHistoryEntry>> {
+        let history = self.history.as_ref().ok_or(FsError::HistoryNotEnabled)?;
+        history.entries(&self.store).await
+    }
+
+    pub async fn tag(&mut self, name: impl Into<String>) -> Result<Cid> {
+        self.tag_with(name, Utc::now(), &mut ChaCha12Rng::from_entropy())
+            .await
+    }
+
+    /// Stores the root tree like [`Self::store_with`], naming the new root in the root history.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use wnfs::{common::MemoryBlockStore, root_tree::RootTree};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut root_tree = RootTree::empty(MemoryBlockStore::new());
+    ///     root_tree.enable_history();
+    ///
+    ///     let path = ["public".into(), "config.toml".into()];
+    ///     root_tree.write(&path, b"version = 1".to_vec()).await?;
+    ///     let before = root_tree.tag("before-migration").await?;
+    ///     root_tree.write(&path, b"version = 2".to_vec()).await?;
+    ///     root_tree.store().await?;
+    ///
+    ///     assert_eq!(root_tree.history().await?.len(), 2);
+    ///     assert_eq!(root_tree.find_tag("before-migration").await?, Some(before));
+    ///
+    ///     let checkout = root_tree.checkout(&before).await?;
+    ///     assert_eq!(checkout.read(&path).await?, b"version = 1");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn tag_with(
+        &mut self,
+        name: impl Into<String>,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<Cid> {
+        self.store_root(Some(name.into()), time, rng).await
+    }
+
+    /// Finds the latest root in the root history that was tagged with given name.
+    pub async fn find_tag(&self, name: &str) -> Result<Option<Cid>> {
+        Ok(self
+            .history()
+            .await?
+            .into_iter()
+            .find(|entry| entry.tag.as_deref() == Some(name))
+            .map(|entry| entry.root))
+    }
+
+    /// Loads a past root of this root tree for reading.
+    ///
+    /// Private roots are only mounted if this root tree has a mount key.
+    pub async fn checkout(&self, root: &Cid) -> Result<RootTreeCheckout<'_, B>> {
+        let tree = match &self.mount_key {
+            Some(mount_key) => RootTree::load_with_keys(root, &self.store, mount_key).await?,
+            None => RootTree::load(root, &self.store).await?,
+        };
+
+        Ok(RootTreeCheckout::new(tree))
+    }
+
+    /// Computes the changes between two roots of this root tree, like two snapshots from the
+    /// root history. Changed paths are full paths, including the partition prefix.
+    ///
+    /// Private roots are compared by their mount points and only if this root tree has a
+    /// mount key. Private roots that were mounted or unmounted in between are reported as
+    /// added or removed as a whole.
+    pub async fn diff_roots(&self, old: &Cid, new: &Cid) -> Result<Vec<PathChange>> {
+        let old = self.checkout(old).await?;
+        let new = self.checkout(new).await?;
+        let prefixed = |prefix: &[String], change: PathChange| PathChange {
+            path: [prefix, &change.path].concat(),
+            ..change
+        };
+
+        let mut changes = Vec::new();
+        for (prefix, old_root, new_root) in [
+            ("public", &old.public_root, &new.public_root),
+            ("exchange", &old.exchange_root, &new.exchange_root),
+        ] {
+            let prefix = [prefix.to_string()];
+            let diff = public::diff(old_root, new_root, &self.store)
+                .try_collect::<Vec<_>>()
+                .await?;
+            changes.extend(diff.into_iter().map(|change| prefixed(&prefix, change)));
+        }
+
+        let old_nodes = old.private_nodes().into_iter().collect::<BTreeMap<_, _>>();
+        let new_nodes = new.private_nodes().into_iter().collect::<BTreeMap<_, _>>();
+        let mount_points = old_nodes
+            .keys()
+            .chain(new_nodes.keys())
+            .collect::<BTreeSet<_>>();
+        for path in mount_points {
+            let (r#type, node) = match (old_nodes.get(path), new_nodes.get(path)) {
+                (Some(PrivateNode::Dir(old_dir)), Some(PrivateNode::Dir(new_dir))) => {
+                    let diff = private::diff(old_dir, new_dir, &new.forest, &self.store)
+                        .try_collect::<Vec<_>>()
+                        .await?;
+                    changes.extend(diff.into_iter().map(|change| prefixed(path, change)));
+                    continue;
+                }
+                (Some(PrivateNode::File(old_file)), Some(PrivateNode::File(new_file))) => {
+                    let old_content = old_file.get_content(&new.forest, &self.store).await?;
+                    let new_content = new_file.get_content(&new.forest, &self.store).await?;
+                    if old_content != new_content {
+                        (PathChangeType::ContentModified, new_file.as_node())
+                    } else if old_file.get_metadata() != new_file.get_metadata() {
+                        (PathChangeType::MetadataModified, new_file.as_node())
+                    } else {
+                        continue;
+                    }
+                }
+                (Some(old_node), Some(new_node)) => {
+                    changes.push(PathChange {
+                        r#type: PathChangeType::Removed,
+                        path: path.clone(),
+                        kind: RootTreeNode::Private(old_node.clone()).kind(),
+                    });
+                    (PathChangeType::Added, new_node.clone())
+                }
+                (Some(old_node), None) => (PathChangeType::Removed, old_node.clone()),
+                (None, Some(new_node)) => (PathChangeType::Added, new_node.clone()),
+                (None, None) => continue,
+            };
+
+            changes.push(PathChange {
+                r#type,
+                path: path.clone(),
+                kind: RootTreeNode::Private(node).kind(),
+            });
+        }
+
+        Ok(changes)
+    }
+
+    pub async fn load(cid: &Cid, store: B) -> Result<RootTree<B>> {
+        let deserialized: RootTreeSerializable =
             serde_ipld_dagcbor::from_slice(&store.get_block(cid).await?)?;
         let forest = Arc::new(HamtForest::load(&deserialized.forest, &store).await?);
         let public_root = Arc::new(PublicDirectory::load(&deserialized.public, &store).await?);
         let exchange_root = Arc::new(PublicDirectory::load(&deserialized.exchange, &store).await?);
 
-        Ok(Self {
-            store,
-            forest,
-            public_root,
-            exchange_root,
-            private_map: BTreeMap::new(),
-        })
+        Ok(Self {
+            store,
+            forest,
+            public_root,
+            exchange_root,
+            private_map: BTreeMap::new(),
+            private_bases: BTreeMap::new(),
+            private_files: BTreeMap::new(),
+            snapshot_files: BTreeMap::new(),
+            mount_key: None,
+            wrapped_mount_keys: Vec::new(),
+            mount_table: deserialized.mounts,
+            shares: BTreeMap::new(),
+            notifier: ChangeNotifier::new(),
+            history: deserialized
+                .history
+                .map(|_| RootHistory { latest: Some(*cid) }),
+        })
+    }
+
+    /// Loads a root tree and mounts all private roots from its mount table,
+    /// decrypting the table with given mount key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use wnfs::{common::MemoryBlockStore, root_tree::RootTree};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let mount_key = RootTree::<MemoryBlockStore>::derive_mount_key(b"root secret");
+    ///     let mut root_tree = RootTree::empty(MemoryBlockStore::new());
+    ///     root_tree.set_mount_key(mount_key.clone())?;
+    ///     root_tree.create_private_root(&["private".into()]).await?;
+    ///
+    ///     let path = ["private".into(), "notes.md".into()];
+    ///     root_tree.write(&path, b"# Notes".to_vec()).await?;
+    ///     let cid = root_tree.store().await?;
+    ///
+    ///     let loaded = RootTree::load_with_keys(&cid, root_tree.store, &mount_key).await?;
+    ///
+    ///     assert_eq!(loaded.read(&path).await?, b"# Notes");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn load_with_keys(
+        cid: &Cid,
+        store: B,
+        mount_key: &SnapshotKey,
+    ) -> Result<RootTree<B>> {
+        let mut root_tree = Self::load(cid, store).await?;
+        root_tree.mount_key = Some(mount_key.clone());
+
+        let Some(mount_table) = root_tree.mount_table else {
+            return Ok(root_tree);
+        };
+
+        let (table, mount_points) =
+            read_mount_table(&mount_table, mount_key, &root_tree.store).await?;
+
+        if !table.shares.is_empty() {
+            let shared_paths: Vec<SharedPath> =
+                serde_ipld_dagcbor::from_slice(&mount_key.decrypt(&table.shares)?)?;
+            root_tree.shares = shared_paths
+                .into_iter()
+                .map(|SharedPath { path, shares }| (path, shares))
+                .collect();
+        }
+        root_tree.wrapped_mount_keys = table
+            .wrapped_keys
+            .into_iter()
+            .map(ByteBuf::into_vec)
+            .collect();
+        let forest = Arc::clone(&root_tree.forest);
+        for MountPoint {
+            path,
+            access_key,
+            base,
+        } in mount_points
+        {
+            root_tree
+                .mount_access_key(path.clone(), access_key, &forest)
+                .await?;
+            if let Some(base) = base {
+                let base = PrivateNode::load(&base, &forest, &root_tree.store, None)
+                    .await?
+                    .as_dir()?;
+                root_tree.private_bases.insert(path, base);
+            }
+        }
+
+        Ok(root_tree)
+    }
+
+    /// Like [`Self::load_with_keys`], but recovers the mount key with the private key
+    /// of an exchange key that was granted access through [`Self::grant_mount_access`].
+    pub async fn load_with_exchange_key(
+        cid: &Cid,
+        store: B,
+        private_key: &impl PrivateKey,
+    ) -> Result<RootTree<B>> {
+        let root_tree = Self::load(cid, store).await?;
+        let mount_table = root_tree
+            .mount_table
+            .ok_or(FsError::MountTableKeyNotFound)?;
+        let table: MountTableSerializable =
+            serde_ipld_dagcbor::from_slice(&root_tree.store.get_block(&mount_table).await?)?;
+
+        for wrapped_key in table.wrapped_keys {
+            let Ok(key_bytes) = private_key.decrypt(&wrapped_key).await else {
+                continue;
+            };
+            let Ok(key_bytes) = <[u8; KEY_BYTE_SIZE]>::try_from(key_bytes) else {
+                continue;
+            };
+
+            return Self::load_with_keys(cid, root_tree.store, &SnapshotKey(key_bytes)).await;
+        }
+
+        bail!(FsError::MountTableKeyNotFound)
+    }
+
+    /// Derives a mount key from a root secret, so the mount table can be
+    /// decrypted again by anyone who knows the secret.
+    pub fn derive_mount_key(secret: &[u8]) -> SnapshotKey {
+        SnapshotKey(blake3::derive_key(MOUNT_TABLE_KEY_DSI, secret))
+    }
+
+    /// Gets the key the mount table is encrypted with, if there is one.
+    pub fn get_mount_key(&self) -> Option<&SnapshotKey> {
+        self.mount_key.as_ref()
+    }
+
+    /// Gets the CID of the mount table this root tree was last loaded or stored with.
+    pub fn get_mount_table(&self) -> Option<Cid> {
+        self.mount_table
+    }
+
+    /// Sets the key to encrypt the mount table with when storing, e.g. one from
+    /// [`Self::derive_mount_key`].
+    ///
+    /// Exchange keys that were granted access to a different mount key before lose it.
+    /// Fails with `FsError::MountTableKeyNotFound` if this root tree was loaded with a
+    /// mount table, but without its key, since storing would replace the existing table.
+    pub fn set_mount_key(&mut self, mount_key: SnapshotKey) -> Result<()> {
+        ensure!(
+            self.mount_key.is_some() || self.mount_table.is_none(),
+            FsError::MountTableKeyNotFound
+        );
+
+        if self.mount_key.as_ref() != Some(&mount_key) {
+            self.wrapped_mount_keys.clear();
+        }
+        self.mount_key = Some(mount_key);
+
+        Ok(())
+    }
+
+    /// Lets the holder of the private key for given exchange key decrypt the mount table.
+    ///
+    /// Creates a random mount key, if there's none yet. Fails with
+    /// `FsError::MountTableKeyNotFound` if this root tree was loaded with a mount table,
+    /// but without its key, since a new key couldn't decrypt the existing table.
+    pub async fn grant_mount_access(
+        &mut self,
+        exchange_key: &impl ExchangeKey,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        ensure!(
+            self.mount_key.is_some() || self.mount_table.is_none(),
+            FsError::MountTableKeyNotFound
+        );
+
+        let mount_key = self.mount_key.get_or_insert_with(|| SnapshotKey::new(rng));
+        let wrapped_key = exchange_key.encrypt(mount_key.as_bytes()).await?;
+        self.wrapped_mount_keys.push(wrapped_key);
+
+        Ok(())
+    }
+}
+
+impl RootTreeNode {
+    /// Whether this is a file or a directory.
+    pub fn kind(&self) -> NodeKind {
+        let is_dir = match self {
+            Self::Public(node) => node.is_dir(),
+            Self::Private(node) => node.is_dir(),
+        };
+
+        if is_dir {
+            NodeKind::Directory
+        } else {
+            NodeKind::File
+        }
+    }
+
+    /// Gets the metadata of the underlying file or directory.
+    pub fn get_metadata(&self) -> &Metadata {
+        match self {
+            Self::Public(node) => node.get_metadata(),
+            Self::Private(node) => node.get_metadata(),
+        }
+    }
+}
+
+impl RootTreeReconciliation {
+    /// Gets the full paths of all conflicts that were resolved, including the partition prefix.
+    pub fn conflict_paths(&self) -> Vec<Vec<String>> {
+        let public = [("public", &self.public), ("exchange", &self.exchange)]
+            .into_iter()
+            .flat_map(|(prefix, reconciliation)| {
+                conflicts(reconciliation)
+                    .iter()
+                    .map(move |conflict| [&[prefix.to_string()], &conflict.path[..]].concat())
+            });
+        let private = self.private.iter().flat_map(|(prefix, reconciliation)| {
+            conflicts(reconciliation)
+                .iter()
+                .map(move |conflict| [&prefix[..], &conflict.path[..]].concat())
+        });
+
+        public.chain(private).collect()
+    }
+}
+
+impl<B: BlockStore> RootTreeFileMut<'_, B> {
+    /// Gets the metadata of the file.
+    pub fn get_metadata(&self) -> &Metadata {
+        match &self.file {
+            OpenFile::Public(file, _) => file.get_metadata(),
+            OpenFile::Private(file, _, _) => file.get_metadata(),
+        }
+    }
+
+    /// Returns a mutable reference to the file's metadata.
+    pub fn get_metadata_mut(&mut self) -> &mut Metadata {
+        match &mut self.file {
+            OpenFile::Public(file, _) => file.get_metadata_mut(),
+            OpenFile::Private(file, _, _) => file.get_metadata_mut(),
+        }
+    }
+
+    /// Replaces the content of the file, streaming it in from given reader.
+    pub async fn set_content(
+        &mut self,
+        content: impl AsyncRead + CondSend + Unpin,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        match &mut self.file {
+            OpenFile::Public(file, store) => {
+                file.set_content_streaming(content, time, *store).await
+            }
+            OpenFile::Private(file, forest, store) => {
+                file.set_content(content, time, &mut **forest, *store, rng)
+                    .await
+            }
+        }
+    }
+}
+
+impl<B: BlockStore> Drop for RootTreeFileMut<'_, B> {
+    fn drop(&mut self) {
+        if let Some(event) = self.event.take() {
+            self.notifier.notify(event);
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Loads a mount table and decrypts its mount points with given mount key.
+async fn read_mount_table(
+    cid: &Cid,
+    mount_key: &SnapshotKey,
+    store: &impl BlockStore,
+) -> Result<(MountTableSerializable, Vec<MountPoint>)> {
+    let table: MountTableSerializable =
+        serde_ipld_dagcbor::from_slice(&store.get_block(cid).await?)?;
+    let mount_points = serde_ipld_dagcbor::from_slice(&mount_key.decrypt(&table.table)?)?;
+    Ok((table, mount_points))
+}
+
+/// Gets the conflicts that came up during a reconciliation.
+fn conflicts<N>(reconciliation: &Reconciliation<N>) -> &[Conflict<N>] {
+    match reconciliation {
+        Reconciliation::Merged { conflicts } => conflicts,
+        _ => &[],
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        find::Glob,
+        private::{RsaPrivateKey, RsaPublicKey},
+    };
+    use testresult::TestResult;
+
+    #[async_std::test]
+    async fn test_roots_read_write() {
+        let store = MemoryBlockStore::default();
+        let mut root_tree = RootTree::empty(store);
+        root_tree
+            .create_private_root(&["private".into()])
+            .await
+            .unwrap();
+
+        // Public root
+
+        root_tree
+            .write(
+                &["public".into(), "test".into(), "file".into()],
+                b"hello world".to_vec(),
+            )
+            .await
+            .unwrap();
+
+        let content = root_tree
+            .read(&["public".into(), "test".into(), "file".into()])
+            .await
+            .unwrap();
+
+        assert_eq!(content, b"hello world".to_vec());
+
+        // Exchange root
+
+        root_tree
+            .write(
+                &["exchange".into(), "test".into(), "file".into()],
+                b"hello world".to_vec(),
+            )
+            .await
+            .unwrap();
+
+        let content = root_tree
+            .read(&["exchange".into(), "test".into(), "file".into()])
+            .await
+            .unwrap();
+
+        assert_eq!(content, b"hello world".to_vec());
+
+        // Private root
+
+        root_tree
+            .write(
+                &["private".into(), "test".into(), "file".into()],
+                b"hello world".to_vec(),
+            )
+            .await
+            .unwrap();
+
+        let content = root_tree
+            .read(&["private".into(), "test".into(), "file".into()])
+            .await
+            .unwrap();
+
+        assert_eq!(content, b"hello world".to_vec());
+    }
+
+    #[async_std::test]
+    async fn test_walk_yields_full_paths() -> TestResult {
+        let store = MemoryBlockStore::default();
+        let mut root_tree = RootTree::empty(store);
+        root_tree.create_private_root(&["private".into()]).await?;
+
+        for path in [
+            ["public", "dir", "file"],
+            ["private", "dir", "file"],
+            ["exchange", "dir", "file"],
+        ] {
+            let path = path.map(String::from);
+            root_tree.write(&path, b"hello world".to_vec()).await?;
+        }
+
+        for partition in ["public", "private", "exchange"] {
+            let entries = root_tree
+                .walk(&[partition.into()], WalkOptions::new())
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?;
+
+            let paths = entries
+                .iter()
+                .map(|(path, _, _)| path.join("/"))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                paths,
+                [format!("{partition}/dir"), format!("{partition}/dir/file")]
+            );
+
+            let is_private = matches!(entries[0].1, RootTreeNode::Private(_));
+            assert_eq!(is_private, partition == "private");
+        }
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_find_filters_nodes() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let old = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+        let new = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut root_tree = RootTree::empty_with(MemoryBlockStore::default(), rng, old);
+        root_tree
+            .create_private_root_with(&["private".into()], old, rng)
+            .await?;
+
+        for partition in ["public", "private"] {
+            for (path, size, time) in [
+                ("docs/a.md", 10, old),
+                ("docs/nested/b.md", 1000, new),
+                ("docs/c.txt", 10, new),
+                ("d.md", 10, new),
+            ] {
+                let path = format!("{partition}/{path}")
+                    .split('/')
+                    .map(String::from)
+                    .collect::<Vec<_>>();
+                root_tree
+                    .write_with(&path, vec![0; size], time, rng)
+                    .await?;
+            }
+
+            let find = |query: FindQuery| {
+                let root_tree = &root_tree;
+                async move {
+                    root_tree
+                        .find(&[partition.into()], query)
+                        .await?
+                        .map_ok(|(path, _, _)| path[1..].join("/"))
+                        .try_collect::<Vec<_>>()
+                        .await
+                }
+            };
+
+            let md = FindQuery::new().glob(Glob::new("docs/**/*.md")?);
+            assert_eq!(find(md.clone()).await?, ["docs/a.md", "docs/nested/b.md"]);
+            assert_eq!(find(md.clone().max_size(100)).await?, ["docs/a.md"]);
+            assert_eq!(find(md.modified_after(new)).await?, ["docs/nested/b.md"]);
+            assert_eq!(
+                find(FindQuery::new().kind(NodeKind::Directory)).await?,
+                ["docs", "docs/nested"]
+            );
+            assert_eq!(
+                find(FindQuery::new().glob(Glob::new("*")?).kind(NodeKind::File)).await?,
+                ["d.md"]
+            );
+        }
+
+        let path = ["public".into(), "docs".into(), "c.txt".into()];
+        let (relative_path, Partition::Public(mut public_root)) = root_tree.get_partition(&path)?
+        else {
+            unreachable!()
+        };
+        public_root
+            .open_file_mut(relative_path, new, &root_tree.store)
+            .await?
+            .get_metadata_mut()
+            .put("tag", Ipld::String("important".into()));
+        root_tree.save_partition(Partition::Public(public_root));
+
+        let tagged = root_tree
+            .find(
+                &[],
+                FindQuery::new().metadata_eq("tag", Ipld::String("important".into())),
+            )
+            .await;
+        assert!(tagged.is_err(), "Searching requires a partition");
+
+        let tagged = root_tree
+            .find(
+                &["public".into()],
+                FindQuery::new().metadata_eq("tag", Ipld::String("important".into())),
+            )
+            .await?
+            .map_ok(|(path, _, _)| path)
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(tagged, [path]);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_restore_revision_in_all_partitions() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = |secs| Utc.timestamp_opt(secs, 0).unwrap();
+        let mut root_tree = RootTree::empty_with(MemoryBlockStore::default(), rng, time(0));
+        root_tree
+            .create_private_root_with(&["private".into()], time(0), rng)
+            .await?;
+
+        for partition in ["public", "private"] {
+            let path = &[partition.to_string(), "notes.md".to_string()];
+            for (secs, content) in [(1, "one"), (2, "two"), (3, "three")] {
+                root_tree
+                    .write_with(path, content.as_bytes().to_vec(), time(secs), rng)
+                    .await?;
+                root_tree.store_with(time(secs), rng).await?;
+            }
+
+            root_tree
+                .restore_revision_with(path, RevisionSelector::Back(1), time(4))
+                .await?;
+            root_tree.store_with(time(4), rng).await?;
+            assert_eq!(root_tree.read(path).await?, b"two");
+
+            // Restoring is a revision of its own, so it can be undone
+            root_tree
+                .restore_revision_with(path, RevisionSelector::Back(1), time(5))
+                .await?;
+            root_tree.store_with(time(5), rng).await?;
+            assert_eq!(root_tree.read(path).await?, b"three");
+
+            root_tree
+                .restore_revision_with(path, RevisionSelector::AsOf(time(1)), time(6))
+                .await?;
+            root_tree.store_with(time(6), rng).await?;
+            assert_eq!(root_tree.read(path).await?, b"one");
+
+            let result = root_tree
+                .restore_revision_with(path, RevisionSelector::Back(100), time(7))
+                .await;
+            assert!(matches!(
+                result.unwrap_err().downcast_ref(),
+                Some(FsError::RevisionNotFound)
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_restore_revision_follows_private_history_past_loaded_revision() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = |secs| Utc.timestamp_opt(secs, 0).unwrap();
+        let mount_key = RootTree::<MemoryBlockStore>::derive_mount_key(b"root secret");
+        let mut root_tree = RootTree::empty_with(MemoryBlockStore::default(), rng, time(0));
+        root_tree.mount_key = Some(mount_key.clone());
+        root_tree.enable_history();
+        root_tree
+            .create_private_root_with(&["private".into()], time(0), rng)
+            .await?;
+
+        let path = &["private".to_string(), "notes.md".to_string()];
+        let mut cid = root_tree.store_with(time(0), rng).await?;
+        for (secs, content) in [(1, "one"), (2, "two"), (3, "three")] {
+            root_tree
+                .write_with(path, content.as_bytes().to_vec(), time(secs), rng)
+                .await?;
+            cid = root_tree.store_with(time(secs), rng).await?;
+        }
+
+        let mut loaded = RootTree::load_with_keys(&cid, root_tree.store, &mount_key).await?;
+        loaded
+            .restore_revision_with(path, RevisionSelector::Back(2), time(4))
+            .await?;
+        assert_eq!(loaded.read(path).await?, b"one");
+
+        loaded
+            .restore_revision_with(path, RevisionSelector::AsOf(time(2)), time(5))
+            .await?;
+        assert_eq!(loaded.read(path).await?, b"two");
+
+        Ok(())
     }
-}
 
-//--------------------------------------------------------------------------------------------------
-// Tests
-//--------------------------------------------------------------------------------------------------
+    #[async_std::test]
+    async fn test_mv_and_cp_across_partitions() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let mut root_tree = RootTree::empty_with(MemoryBlockStore::default(), rng, time);
+        for root in ["home", "archive"] {
+            root_tree
+                .create_private_root_with(&["private".into(), root.into()], time, rng)
+                .await?;
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        root_tree
+            .write_with(&path(&["public", "docs", "a.md"]), b"a".to_vec(), time, rng)
+            .await?;
+        root_tree
+            .public_root
+            .open_file_mut(&path(&["docs", "a.md"]), time, &root_tree.store)
+            .await?
+            .get_metadata_mut()
+            .put("tag", Ipld::String("draft".into()));
+
+        // Public to private encrypts the subtree and removes the original
+        root_tree
+            .basic_mv_with(
+                &path(&["public", "docs"]),
+                &path(&["private", "home", "docs"]),
+                time,
+                rng,
+            )
+            .await?;
+        root_tree.store_with(time, rng).await?;
+        assert!(root_tree.ls(&path(&["public"])).await?.is_empty());
+        assert_eq!(
+            root_tree
+                .read(&path(&["private", "home", "docs", "a.md"]))
+                .await?,
+            b"a"
+        );
+        let entries = root_tree.ls(&path(&["private", "home", "docs"])).await?;
+        assert_eq!(entries[0].1.get("tag"), Some(&Ipld::String("draft".into())));
+
+        // Private content can move between private roots, but isn't published by accident
+        root_tree
+            .cp_with(
+                &path(&["private", "home", "docs"]),
+                &path(&["private", "archive", "docs"]),
+                time,
+                rng,
+            )
+            .await?;
+        assert_eq!(
+            root_tree
+                .read(&path(&["private", "archive", "docs", "a.md"]))
+                .await?,
+            b"a"
+        );
+        let result = root_tree
+            .basic_mv_with(
+                &path(&["private", "home", "docs"]),
+                &path(&["public", "docs"]),
+                time,
+                rng,
+            )
+            .await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(FsError::PublishingRequired)
+        ));
+
+        root_tree
+            .publish_with(
+                &path(&["private", "home", "docs"]),
+                &path(&["public", "docs"]),
+                time,
+                rng,
+            )
+            .await?;
+        assert_eq!(
+            root_tree.read(&path(&["public", "docs", "a.md"])).await?,
+            b"a"
+        );
+        assert_eq!(
+            root_tree
+                .read(&path(&["private", "home", "docs", "a.md"]))
+                .await?,
+            b"a"
+        );
+        let entries = root_tree.ls(&path(&["public", "docs"])).await?;
+        assert_eq!(entries[0].1.get("tag"), Some(&Ipld::String("draft".into())));
+
+        // Moves within the exchange partition stay there
+        root_tree
+            .write_with(&path(&["exchange", "key"]), b"key".to_vec(), time, rng)
+            .await?;
+        root_tree
+            .basic_mv_with(
+                &path(&["exchange", "key"]),
+                &path(&["exchange", "moved"]),
+                time,
+                rng,
+            )
+            .await?;
+        assert_eq!(root_tree.read(&path(&["exchange", "moved"])).await?, b"key");
+        assert!(root_tree.read(&path(&["public", "moved"])).await.is_err());
+
+        Ok(())
+    }
 
     #[async_std::test]
-    async fn test_roots_read_write() {
-        let store = MemoryBlockStore::default();
-        let mut root_tree = RootTree::empty(store);
+    async fn test_file_api_in_all_partitions() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let mut root_tree = RootTree::empty_with(MemoryBlockStore::default(), rng, time);
         root_tree
-            .create_private_root(&["private".into()])
-            .await
-            .unwrap();
+            .create_private_root_with(&["private".into()], time, rng)
+            .await?;
 
-        // Public root
+        let content = b"Hello, World!\n".repeat(30_000);
+        for partition in ["public", "exchange", "private"] {
+            let dir = vec![partition.to_string(), "docs".into()];
+            let file = vec![partition.to_string(), "docs".into(), "hello.txt".into()];
+
+            assert!(!root_tree.exists(&file).await?);
+            root_tree
+                .write_stream_with(&file, &content[..], time, rng)
+                .await?;
+            root_tree.store_with(time, rng).await?;
+            assert!(root_tree.exists(&file).await?);
+            assert!(root_tree.exists(&[partition.into()]).await?);
+
+            let streamed = root_tree.read_stream(&file).await?.try_concat().await?;
+            assert_eq!(streamed, content);
+            assert_eq!(
+                root_tree.read_at(&file, 14, Some(28)).await?,
+                b"Hello, World!\nHello, World!\n"
+            );
+            assert_eq!(root_tree.size(&file).await?, content.len() as u64);
+            assert!(root_tree.size(&dir).await.is_err());
+
+            let tag = Ipld::String(partition.into());
+            root_tree
+                .set_metadata_with(&file, "tag", tag.clone(), time, rng)
+                .await?;
+            root_tree
+                .set_metadata_with(&dir, "tag", tag.clone(), time, rng)
+                .await?;
+            root_tree.store_with(time, rng).await?;
+
+            let stat = root_tree.stat(&file).await?;
+            assert_eq!(stat.kind, NodeKind::File);
+            assert_eq!(stat.size, content.len() as u64);
+            assert_eq!(stat.metadata.get("tag"), Some(&tag));
+            let stat = root_tree.stat(&dir).await?;
+            assert_eq!(stat.kind, NodeKind::Directory);
+            assert_eq!(root_tree.get_metadata(&dir).await?.get("tag"), Some(&tag));
+
+            let mut opened = root_tree.open_file_mut_with(&file, time, rng).await?;
+            assert_eq!(opened.get_metadata().get("tag"), Some(&tag));
+            opened.set_content(&b"replaced"[..], time, rng).await?;
+            drop(opened);
+            assert_eq!(root_tree.read(&file).await?, b"replaced");
+            assert_eq!(root_tree.get_metadata(&file).await?.get("tag"), Some(&tag));
+        }
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_mount_table_restores_private_roots() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let mount_key = RootTree::<MemoryBlockStore>::derive_mount_key(b"root secret");
+        let mut root_tree = RootTree::empty_with(MemoryBlockStore::default(), rng, time);
+        root_tree.mount_key = Some(mount_key.clone());
+
+        let roots = [
+            vec!["private".to_string(), "home".into()],
+            vec!["private".to_string(), "work".into()],
+        ];
+        for root in &roots {
+            root_tree.create_private_root_with(root, time, rng).await?;
+            let file = [&root[..], &["file".into()]].concat();
+            root_tree
+                .write_with(&file, root.concat().into_bytes(), time, rng)
+                .await?;
+        }
 
+        let device = RsaPrivateKey::new()?;
         root_tree
-            .write(
-                &["public".into(), "test".into(), "file".into()],
-                b"hello world".to_vec(),
+            .grant_mount_access(&device.get_public_key(), rng)
+            .await?;
+        let cid = root_tree.store_with(time, rng).await?;
+        let store = root_tree.store;
+
+        let loaded = RootTree::load_with_keys(&cid, store.clone(), &mount_key).await?;
+        for root in &roots {
+            let file = [&root[..], &["file".into()]].concat();
+            assert_eq!(loaded.read(&file).await?, root.concat().into_bytes());
+        }
+
+        let wrong_key = RootTree::<MemoryBlockStore>::derive_mount_key(b"wrong secret");
+        assert!(
+            RootTree::load_with_keys(&cid, store.clone(), &wrong_key)
+                .await
+                .is_err()
+        );
+
+        // Storing without the mount key keeps the mount table around
+        let mut without_keys = RootTree::load(&cid, store.clone()).await?;
+        assert!(without_keys.private_map.is_empty());
+        without_keys
+            .write_with(
+                &["public".into(), "file".into()],
+                b"public".to_vec(),
+                time,
+                rng,
             )
-            .await
-            .unwrap();
+            .await?;
+        let cid = without_keys.store_with(time, rng).await?;
+
+        // Granting access needs the key of the existing mount table
+        let other_device = RsaPrivateKey::new()?;
+        let result = without_keys
+            .grant_mount_access(&other_device.get_public_key(), rng)
+            .await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(FsError::MountTableKeyNotFound)
+        ));
+        assert_eq!(without_keys.mount_key, None);
+
+        let loaded = RootTree::load_with_exchange_key(&cid, store.clone(), &device).await?;
+        assert_eq!(
+            loaded.private_map.keys().cloned().collect::<Vec<_>>(),
+            roots
+        );
+        assert_eq!(loaded.mount_key, Some(mount_key));
+
+        let result = RootTree::load_with_exchange_key(&cid, store, &other_device).await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(FsError::MountTableKeyNotFound)
+        ));
 
-        let content = root_tree
-            .read(&["public".into(), "test".into(), "file".into()])
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_private_file_mounts() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let notes = path(&["private", "home", "notes.md"]);
+
+        let mut sharer = RootTree::empty_with(MemoryBlockStore::default(), rng, time);
+        sharer
+            .create_private_root_with(&path(&["private", "home"]), time, rng)
+            .await?;
+        sharer
+            .write_with(&notes, b"# Notes".to_vec(), time, rng)
+            .await?;
+        sharer.store_with(time, rng).await?;
+        let access_key = sharer.store_private_root_with(&notes, rng).await?;
+
+        let mount_key = RootTree::<MemoryBlockStore>::derive_mount_key(b"root secret");
+        let shared = path(&["private", "shared.md"]);
+        let mut root_tree = RootTree::empty_with(sharer.store.clone(), rng, time);
+        root_tree.forest = Arc::clone(&sharer.forest);
+        root_tree.mount_key = Some(mount_key.clone());
+        root_tree.load_private_file(&shared, &access_key).await?;
+        assert_eq!(root_tree.read(&shared).await?, b"# Notes");
+
+        root_tree
+            .write_with(&shared, b"# Edited".to_vec(), time, rng)
+            .await?;
+        assert_eq!(root_tree.read(&shared).await?, b"# Edited");
+        assert_eq!(root_tree.stat(&shared).await?.kind, NodeKind::File);
+        let below = path(&["private", "shared.md", "file"]);
+        let error = root_tree
+            .write_with(&below, b"".to_vec(), time, rng)
             .await
-            .unwrap();
+            .unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(FsError::NotADirectory)));
+        assert!(root_tree.ls(&shared).await.is_err());
+        assert!(root_tree.rm(&shared).await.is_err());
 
-        assert_eq!(content, b"hello world".to_vec());
+        let copy = path(&["private", "home", "copy.md"]);
+        root_tree
+            .create_private_root_with(&path(&["private", "home"]), time, rng)
+            .await?;
+        root_tree.cp_with(&shared, &copy, time, rng).await?;
+        assert_eq!(root_tree.read(&copy).await?, b"# Edited");
 
-        // Exchange root
+        let cid = root_tree.store_with(time, rng).await?;
+        let loaded = RootTree::load_with_keys(&cid, root_tree.store, &mount_key).await?;
+        assert_eq!(loaded.private_files.keys().collect::<Vec<_>>(), [&shared]);
+        assert_eq!(loaded.read(&shared).await?, b"# Edited");
+        assert_eq!(loaded.read(&copy).await?, b"# Edited");
+
+        Ok(())
+    }
 
+    #[async_std::test]
+    async fn test_root_history_lists_checks_out_and_diffs_roots() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = |secs| Utc.timestamp_opt(secs, 0).unwrap();
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let config = path(&["public", "config.toml"]);
+        let notes = path(&["private", "notes.md"]);
+
+        let mut root_tree = RootTree::empty_with(MemoryBlockStore::default(), rng, time(0));
+        let error = root_tree.tag_with("v0", time(0), rng).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(FsError::HistoryNotEnabled)
+        ));
+
+        root_tree.enable_history();
+        root_tree.mount_key = Some(RootTree::<MemoryBlockStore>::derive_mount_key(b"secret"));
         root_tree
-            .write(
-                &["exchange".into(), "test".into(), "file".into()],
-                b"hello world".to_vec(),
+            .create_private_root_with(&path(&["private"]), time(0), rng)
+            .await?;
+        root_tree
+            .write_with(&config, b"version = 1".to_vec(), time(1), rng)
+            .await?;
+        root_tree
+            .write_with(&notes, b"# Notes".to_vec(), time(1), rng)
+            .await?;
+        let before = root_tree.tag_with("before-migration", time(1), rng).await?;
+
+        root_tree
+            .write_with(&config, b"version = 2".to_vec(), time(2), rng)
+            .await?;
+        root_tree.rm(&notes).await?;
+        let after = root_tree.tag_with("after-migration", time(2), rng).await?;
+
+        // History continues from loaded roots
+        let mut root_tree =
+            RootTree::load_with_keys(&after, root_tree.store, &root_tree.mount_key.unwrap())
+                .await?;
+        root_tree
+            .mkdir_with(&path(&["public", "photos"]), time(3), rng)
+            .await?;
+        // Times are kept with millisecond precision
+        let millis = Utc.timestamp_millis_opt(3_250).unwrap();
+        let latest = root_tree.tag_with("latest", millis, rng).await?;
+        let untagged = root_tree.store_with(time(4), rng).await?;
+
+        let history = root_tree.history().await?;
+        assert_eq!(
+            history
+                .iter()
+                .map(|entry| (entry.root, entry.time, entry.tag.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                (untagged, time(4), None),
+                (latest, millis, Some("latest")),
+                (after, time(2), Some("after-migration")),
+                (before, time(1), Some("before-migration")),
+            ]
+        );
+        assert_eq!(root_tree.find_tag("before-migration").await?, Some(before));
+        assert_eq!(root_tree.find_tag("missing").await?, None);
+
+        let checkout = root_tree.checkout(&before).await?;
+        assert_eq!(checkout.read(&config).await?, b"version = 1");
+        assert_eq!(checkout.read(&notes).await?, b"# Notes");
+
+        let mut changes = root_tree.diff_roots(&before, &latest).await?;
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(
+            changes
+                .into_iter()
+                .map(|change| (change.r#type, change.path))
+                .collect::<Vec<_>>(),
+            [
+                (PathChangeType::Removed, notes),
+                (PathChangeType::ContentModified, config),
+                (PathChangeType::Added, path(&["public", "photos"])),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_exchange_keys_can_be_added_listed_and_removed() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = |secs| Utc.timestamp_opt(secs, 0).unwrap();
+        let laptop = RsaPrivateKey::new()?;
+        let phone = RsaPrivateKey::new()?;
+        let laptop_modulus = laptop.get_public_key().get_public_key_modulus()?;
+        let phone_modulus = phone.get_public_key().get_public_key_modulus()?;
+
+        let mut root_tree = RootTree::empty_with(MemoryBlockStore::default(), rng, time(0));
+        root_tree
+            .add_exchange_key_with("laptop", laptop_modulus.clone(), time(1))
+            .await?;
+        root_tree
+            .add_exchange_key_with("phone", phone_modulus.clone(), time(2))
+            .await?;
+        let error = root_tree
+            .add_exchange_key_with("phone", laptop_modulus.clone(), time(3))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(FsError::DirectoryAlreadyExists)
+        ));
+        assert!(root_tree.add_exchange_key("../x", vec![]).await.is_err());
+
+        assert_eq!(
+            root_tree.exchange_keys().await?,
+            [
+                DeviceExchangeKey {
+                    device: "laptop".into(),
+                    modulus: laptop_modulus,
+                    created: Some(time(1)),
+                },
+                DeviceExchangeKey {
+                    device: "phone".into(),
+                    modulus: phone_modulus.clone(),
+                    created: Some(time(2)),
+                },
+            ]
+        );
+
+        root_tree.remove_exchange_key("laptop").await?;
+        assert!(root_tree.remove_exchange_key("laptop").await.is_err());
+        let exchange_root = PublicLink::with_rc_dir(Arc::clone(&root_tree.exchange_root));
+        let moduli = sharer::fetch_exchange_keys(exchange_root.clone(), &root_tree.store)
+            .await
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(moduli, std::slice::from_ref(&phone_modulus));
+
+        // Others can share with the remaining device
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let store = &root_tree.store;
+        let dir =
+            PrivateDirectory::new_and_store(&forest.empty_name(), time(4), forest, store, rng)
+                .await?;
+        let access_key = dir.as_node().store(forest, store, rng).await?;
+        sharer::share::<RsaPublicKey>(
+            &access_key,
+            0,
+            "did:key:sharer",
+            exchange_root,
+            forest,
+            store,
+        )
+        .await?;
+        let label = sharer::create_share_name(0, "did:key:sharer", &phone_modulus, forest);
+        let node = recipient::receive_share(&label, &phone, forest, store).await?;
+        assert_eq!(node.as_dir()?, dir);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_shared_paths_can_be_accepted_and_mounted() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let store = &MemoryBlockStore::default();
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let phone = RsaPrivateKey::new()?;
+        let phone_modulus = phone.get_public_key().get_public_key_modulus()?;
+
+        let mut sharer = RootTree::empty_with(store, rng, time);
+        sharer
+            .create_private_root_with(&path(&["private", "home"]), time, rng)
+            .await?;
+        sharer
+            .write_with(
+                &path(&["private", "home", "docs", "notes.md"]),
+                b"notes".to_vec(),
+                time,
+                rng,
+            )
+            .await?;
+
+        let setup = sharer.forest.get_accumulator_setup().clone();
+        let mut recipient =
+            RootTree::new(HamtForest::new_rc(setup), store, time, BTreeMap::new()).await;
+        recipient
+            .add_exchange_key("phone", phone_modulus.clone())
+            .await?;
+        let exchange_root = PublicLink::with_rc_dir(Arc::clone(&recipient.exchange_root));
+
+        // Share counters are picked automatically
+        let docs = path(&["private", "home", "docs"]);
+        for expected in 0..2 {
+            let count = sharer
+                .share_path_with::<RsaPublicKey>(
+                    &docs,
+                    exchange_root.clone(),
+                    ShareMode::Temporal,
+                    "did:key:sharer",
+                    rng,
+                )
+                .await?;
+            assert_eq!(count, expected);
+        }
+        let error = sharer
+            .share_path::<RsaPublicKey>(
+                &path(&["public"]),
+                exchange_root.clone(),
+                ShareMode::Temporal,
+                "did:key:sharer",
+            )
+            .await;
+        assert!(error.is_err());
+
+        let shared = path(&["private", "shared"]);
+        let count = recipient
+            .accept_share(
+                &shared,
+                "did:key:sharer",
+                &sharer.forest,
+                &phone,
+                &phone_modulus,
+            )
+            .await?;
+        assert_eq!(count, 1);
+        assert_eq!(
+            recipient
+                .read(&path(&["private", "shared", "notes.md"]))
+                .await?,
+            b"notes"
+        );
+        let error = recipient
+            .accept_share(
+                &shared,
+                "did:key:sharer",
+                &sharer.forest,
+                &phone,
+                &phone_modulus,
             )
             .await
-            .unwrap();
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(FsError::DirectoryAlreadyExists)
+        ));
+
+        // Snapshot shares are mounted read-only
+        let error = sharer
+            .share_path_with::<RsaPublicKey>(
+                &docs,
+                exchange_root.clone(),
+                ShareMode::Snapshot,
+                "did:key:sharer",
+                rng,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(FsError::NotAFile)));
+        let count = sharer
+            .share_path_with::<RsaPublicKey>(
+                &path(&["private", "home", "docs", "notes.md"]),
+                exchange_root,
+                ShareMode::Snapshot,
+                "did:key:sharer",
+                rng,
+            )
+            .await?;
+        assert_eq!(count, 2);
+        sharer
+            .write_with(
+                &path(&["private", "home", "docs", "notes.md"]),
+                b"edited".to_vec(),
+                time,
+                rng,
+            )
+            .await?;
 
-        let content = root_tree
-            .read(&["exchange".into(), "test".into(), "file".into()])
+        let snapshot = path(&["private", "snapshot.md"]);
+        let count = recipient
+            .accept_share(
+                &snapshot,
+                "did:key:sharer",
+                &sharer.forest,
+                &phone,
+                &phone_modulus,
+            )
+            .await?;
+        assert_eq!(count, 2);
+        assert_eq!(recipient.read(&snapshot).await?, b"notes");
+        for error in [
+            recipient
+                .write_with(&snapshot, b"edited".to_vec(), time, rng)
+                .await
+                .unwrap_err(),
+            recipient
+                .set_metadata_with(&snapshot, "color", Ipld::String("blue".into()), time, rng)
+                .await
+                .unwrap_err(),
+        ] {
+            assert!(matches!(
+                error.downcast_ref(),
+                Some(FsError::ReadOnlySnapshot)
+            ));
+        }
+        recipient
+            .cp_with(&snapshot, &path(&["public", "notes.md"]), time, rng)
             .await
-            .unwrap();
+            .unwrap_err();
+        recipient
+            .cp_with(
+                &snapshot,
+                &path(&["private", "shared", "copy.md"]),
+                time,
+                rng,
+            )
+            .await?;
+        assert_eq!(
+            recipient
+                .read(&path(&["private", "shared", "copy.md"]))
+                .await?,
+            b"notes"
+        );
+
+        // Snapshot mounts are kept in the mount table
+        let mount_key = RootTree::<MemoryBlockStore>::derive_mount_key(b"recipient secret");
+        recipient.mount_key = Some(mount_key.clone());
+        let cid = recipient.store_with(time, rng).await?;
+        let loaded = RootTree::load_with_keys(&cid, store, &mount_key).await?;
+        assert_eq!(
+            loaded.snapshot_files.keys().collect::<Vec<_>>(),
+            [&snapshot]
+        );
+        assert_eq!(loaded.read(&snapshot).await?, b"notes");
 
-        assert_eq!(content, b"hello world".to_vec());
+        Ok(())
+    }
 
-        // Private root
+    #[async_std::test]
+    async fn test_revoked_recipients_cant_read_future_revisions() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let store = &MemoryBlockStore::default();
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let docs = path(&["private", "home", "docs"]);
+        let notes = path(&["private", "home", "docs", "notes.md"]);
+        let shared_notes = path(&["private", "shared", "notes.md"]);
+
+        let mut sharer = RootTree::empty_with(store, rng, time);
+        sharer
+            .create_private_root_with(&path(&["private", "home"]), time, rng)
+            .await?;
+        sharer.write_with(&notes, b"v1".to_vec(), time, rng).await?;
+
+        let setup = sharer.forest.get_accumulator_setup().clone();
+        let mut recipients = Vec::new();
+        for _ in 0..2 {
+            let key = RsaPrivateKey::new()?;
+            let modulus = key.get_public_key().get_public_key_modulus()?;
+            let mut tree = RootTree::new(
+                HamtForest::new_rc(setup.clone()),
+                store,
+                time,
+                BTreeMap::new(),
+            )
+            .await;
+            tree.add_exchange_key("laptop", modulus.clone()).await?;
+            let exchange_root = PublicLink::with_rc_dir(Arc::clone(&tree.exchange_root));
+            sharer
+                .share_path_with::<RsaPublicKey>(
+                    &docs,
+                    exchange_root.clone(),
+                    ShareMode::Temporal,
+                    "did:key:sharer",
+                    rng,
+                )
+                .await?;
+            recipients.push((tree, key, modulus, exchange_root));
+        }
+        let [
+            (mut alice, alice_key, alice_modulus, alice_exchange),
+            mut bob,
+        ] = <[_; 2]>::try_from(recipients).unwrap();
+        let (bob_tree, bob_key, bob_modulus, bob_exchange) = &mut bob;
+        bob_tree
+            .accept_share(
+                &path(&["private", "shared"]),
+                "did:key:sharer",
+                &sharer.forest,
+                bob_key,
+                bob_modulus,
+            )
+            .await?;
 
-        root_tree
-            .write(
-                &["private".into(), "test".into(), "file".into()],
-                b"hello world".to_vec(),
+        let photos = path(&["private", "home", "photos.md"]);
+        sharer
+            .write_with(&photos, b"photos".to_vec(), time, rng)
+            .await?;
+        sharer
+            .share_path_with::<RsaPublicKey>(
+                &photos,
+                bob_exchange.clone(),
+                ShareMode::Temporal,
+                "did:key:sharer",
+                rng,
+            )
+            .await?;
+
+        let mut changes = sharer.subscribe();
+        let error = sharer
+            .revoke_path_with::<RsaPublicKey>(
+                &path(&["private", "home"]),
+                std::slice::from_ref(bob_exchange),
+                &[],
+                "did:key:sharer",
+                time,
+                rng,
             )
             .await
-            .unwrap();
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(FsError::InvalidPath(InvalidPathReason::PartitionRoot))
+        ));
+
+        sharer
+            .revoke_path_with::<RsaPublicKey>(
+                &docs,
+                std::slice::from_ref(bob_exchange),
+                std::slice::from_ref(&alice_exchange),
+                "did:key:sharer",
+                time,
+                rng,
+            )
+            .await?;
+        let events = std::iter::from_fn(|| changes.try_next().ok().flatten()).collect::<Vec<_>>();
+        assert!(matches!(&events[..], [
+            ChangeEvent::Revoked(revoked),
+        ] if revoked.path == docs));
+
+        // Only the share of the revoked path is removed, later shares can still be found
+        let share_label = |share_count| {
+            sharer::create_share_name(share_count, "did:key:sharer", bob_modulus, &sharer.forest)
+        };
+        assert!(
+            recipient::receive_access_key(&share_label(0), bob_key, &sharer.forest, store)
+                .await
+                .is_err()
+        );
+        assert!(
+            recipient::receive_access_key(&share_label(1), bob_key, &sharer.forest, store)
+                .await
+                .is_ok()
+        );
+        let latest = recipient::find_latest_share_counter(
+            0,
+            10,
+            bob_modulus,
+            "did:key:sharer",
+            &sharer.forest,
+            store,
+        )
+        .await?;
+        assert_eq!(latest, Some(1));
+
+        sharer.write_with(&notes, b"v2".to_vec(), time, rng).await?;
+        sharer.store().await?;
+        assert_eq!(sharer.read(&notes).await?, b"v2");
+
+        // The remaining recipient gets the new keys
+        alice
+            .accept_share(
+                &path(&["private", "shared"]),
+                "did:key:sharer",
+                &sharer.forest,
+                &alice_key,
+                &alice_modulus,
+            )
+            .await?;
+        assert_eq!(alice.read(&shared_notes).await?, b"v2");
 
-        let content = root_tree
-            .read(&["private".into(), "test".into(), "file".into()])
+        // The revoked recipient can't follow the old ratchet
+        bob_tree.forest = Arc::new(bob_tree.forest.merge(&sharer.forest, store).await?);
+        assert_eq!(bob_tree.read(&shared_notes).await?, b"v1");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_shares_are_recorded_in_the_mount_table() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let store = &MemoryBlockStore::default();
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let notes = path(&["private", "home", "notes.md"]);
+
+        let mut sharer = RootTree::empty_with(store, rng, time);
+        sharer.mount_key = Some(RootTree::<MemoryBlockStore>::derive_mount_key(b"secret"));
+        sharer
+            .create_private_root_with(&path(&["private", "home"]), time, rng)
+            .await?;
+        sharer
+            .write_with(&notes, b"notes".to_vec(), time, rng)
+            .await?;
+
+        let mut recipient = RootTree::empty_with(store, rng, time);
+        let modulus = RsaPrivateKey::new()?
+            .get_public_key()
+            .get_public_key_modulus()?;
+        recipient
+            .add_exchange_key("laptop", modulus.clone())
+            .await?;
+        let exchange_root = PublicLink::with_rc_dir(Arc::clone(&recipient.exchange_root));
+        let share_count = sharer
+            .share_path_with::<RsaPublicKey>(
+                &notes,
+                exchange_root,
+                ShareMode::Temporal,
+                "did:key:sharer",
+                rng,
+            )
+            .await?;
+
+        let cid = sharer.store_with(time, rng).await?;
+        let mount_key = sharer.mount_key.clone().unwrap();
+        let loaded = RootTree::load_with_keys(&cid, store, &mount_key).await?;
+        assert_eq!(
+            loaded.shares,
+            BTreeMap::from([(
+                notes,
+                BTreeSet::from([ShareRecord {
+                    sharer_root_did: "did:key:sharer".into(),
+                    recipient_exchange_key: ByteBuf::from(modulus),
+                    share_count,
+                }])
+            )])
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_reconcile_reports_all_partitions() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let home = path(&["private", "home"]);
+
+        let mut ours = RootTree::empty_with(MemoryBlockStore::default(), rng, time);
+        ours.create_private_root_with(&home, time, rng).await?;
+        ours.store_with(time, rng).await?;
+
+        let mut theirs = ours.clone();
+        for (root_tree, name) in [(&mut ours, "ours"), (&mut theirs, "theirs")] {
+            for file in [
+                path(&["public", name]),
+                path(&["public", "conflict"]),
+                path(&["private", "home", name]),
+            ] {
+                root_tree
+                    .write_with(&file, name.as_bytes().to_vec(), time, rng)
+                    .await?;
+            }
+        }
+        theirs
+            .write_with(&path(&["exchange", "key"]), b"key".to_vec(), time, rng)
+            .await?;
+
+        let report = ours.reconcile_with(&theirs, &TieBreakPolicy, rng).await?;
+        assert!(matches!(report.public, Reconciliation::Merged { .. }));
+        assert!(matches!(report.exchange, Reconciliation::FastForward));
+        assert!(matches!(
+            &report.private[&home],
+            Reconciliation::Merged { conflicts } if conflicts.is_empty()
+        ));
+        assert_eq!(report.conflict_paths(), [path(&["public", "conflict"])]);
+
+        assert_eq!(ours.ls(&path(&["public"])).await?.len(), 3);
+        assert_eq!(ours.ls(&home).await?.len(), 2);
+        assert_eq!(ours.read(&path(&["exchange", "key"])).await?, b"key");
+
+        let report = ours.reconcile_with(&theirs, &TieBreakPolicy, rng).await?;
+        assert!(matches!(report.public, Reconciliation::AlreadyAhead));
+        assert!(matches!(
+            report.private[&home],
+            Reconciliation::AlreadyAhead
+        ));
+
+        let report = theirs.reconcile_with(&ours, &TieBreakPolicy, rng).await?;
+        assert!(matches!(report.public, Reconciliation::FastForward));
+        assert!(matches!(report.private[&home], Reconciliation::FastForward));
+        assert!(report.conflict_paths().is_empty());
+        assert_eq!(theirs.ls(&home).await?.len(), 2);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_subscribers_are_notified_of_changes() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let home = path(&["private", "home"]);
+        let private = |segments: &[&str]| ChangedPath {
+            partition: PartitionKind::Private(home.clone()),
+            path: path(segments),
+        };
+        let public = |segments: &[&str]| ChangedPath {
+            partition: PartitionKind::Public,
+            path: path(segments),
+        };
+
+        let mut root_tree = RootTree::empty_with(MemoryBlockStore::default(), rng, time);
+        let mut changes = root_tree.subscribe();
+        let mut pending =
+            move || std::iter::from_fn(|| changes.try_next().ok().flatten()).collect::<Vec<_>>();
+
+        root_tree.create_private_root_with(&home, time, rng).await?;
+        root_tree
+            .write_with(
+                &path(&["private", "home", "a.md"]),
+                b"a".to_vec(),
+                time,
+                rng,
+            )
+            .await?;
+        root_tree
+            .set_metadata_with(&home, "color", Ipld::String("blue".into()), time, rng)
+            .await?;
+        root_tree
+            .basic_mv_with(
+                &path(&["private", "home", "a.md"]),
+                &path(&["public", "a.md"]),
+                time,
+                rng,
+            )
             .await
-            .unwrap();
+            .unwrap_err();
+        root_tree
+            .publish_with(
+                &path(&["private", "home", "a.md"]),
+                &path(&["public", "a.md"]),
+                time,
+                rng,
+            )
+            .await?;
+        root_tree.rm(&path(&["private", "home", "a.md"])).await?;
+
+        let events = pending();
+        assert!(matches!(&events[..], [
+            ChangeEvent::Mkdir(root),
+            ChangeEvent::Write(written),
+            ChangeEvent::MetadataChanged(changed),
+            ChangeEvent::Cp { from, to },
+            ChangeEvent::Rm(removed),
+        ] if *root == private(&["private", "home"])
+            && *written == private(&["private", "home", "a.md"])
+            && *changed == private(&["private", "home"])
+            && *from == private(&["private", "home", "a.md"])
+            && *to == public(&["public", "a.md"])
+            && *removed == private(&["private", "home", "a.md"])));
+
+        // Writes through an open file are announced once it's closed again
+        let mut file = root_tree
+            .open_file_mut_with(&path(&["public", "b.md"]), time, rng)
+            .await?;
+        file.set_content(&b"b"[..], time, rng).await?;
+        assert!(pending().is_empty());
+        drop(file);
+        let events = pending();
+        assert!(matches!(&events[..], [
+            ChangeEvent::Write(written),
+        ] if *written == public(&["public", "b.md"])));
+
+        let mut copy = root_tree.clone();
+        copy.mkdir_with(&path(&["public", "copy"]), time, rng)
+            .await?;
+        assert!(pending().is_empty());
 
-        assert_eq!(content, b"hello world".to_vec());
+        let mut transaction = root_tree.transaction();
+        transaction
+            .mkdir_with(&path(&["public", "staged"]), time, rng)
+            .await?;
+        transaction.rollback();
+        assert!(pending().is_empty());
+
+        let mut transaction = root_tree.transaction();
+        transaction
+            .mkdir_with(&path(&["public", "staged"]), time, rng)
+            .await?;
+        let cid = transaction.commit_with(time, rng).await?;
+        let events = pending();
+        assert!(matches!(&events[..], [
+            ChangeEvent::Mkdir(dir),
+            ChangeEvent::Stored(stored),
+        ] if *dir == public(&["public", "staged"]) && *stored == cid));
+
+        Ok(())
     }
 }
 
@@ -547,7 +4034,7 @@ mod snapshot_tests {
                 .unwrap();
         }
 
-        let root_cid = root_tree.store_with(rng).await.unwrap();
+        let root_cid = root_tree.store_with(time, rng).await.unwrap();
         let forest = &mut Arc::clone(&root_tree.forest);
         let (_, root_dir) = root_tree.lookup_private_root(&["private".into()]).unwrap();
 
@@ -0,0 +1,392 @@
+//! Atomic changes to a whole root tree.
+//!
+//! A [`Transaction`] stages mutations against all partitions of a [`RootTree`] and keeps
+//! every new block in memory. Only committing it stores the result, writing the blocks
+//! that are reachable from the new root in one go and updating the root tree.
+
+use crate::{
+    changes::{ChangeEvent, ChangeNotifier},
+    private::{
+        PrivateDirectory, PrivateFile, SnapshotAccessKey, SnapshotKey, forest::hamt::HamtForest,
+    },
+    public::PublicDirectory,
+    root_history::RootHistory,
+    root_tree::{RootTree, ShareRecord},
+};
+use anyhow::Result;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::channel::mpsc::UnboundedReceiver;
+use rand_chacha::ChaCha12Rng;
+use rand_core::{CryptoRngCore, SeedableRng};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::{Deref, DerefMut},
+    sync::Mutex,
+};
+use wnfs_common::{
+    BlockStore, BlockStoreError, Cid,
+    utils::{Arc, CondSend},
+};
+use wnfs_unixfs_file::{codecs::Codec, parse_links};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A set of changes to a root tree that's applied all at once, or not at all.
+///
+/// A transaction dereferences to a staged copy of the root tree, so all of the root tree's
+/// operations can be used on it. Dropping it or calling [`Self::rollback`] discards all changes.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use wnfs::{common::MemoryBlockStore, root_tree::RootTree};
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let mut root_tree = RootTree::empty(MemoryBlockStore::new());
+///     root_tree.create_private_root(&["private".into()]).await?;
+///
+///     let mut transaction = root_tree.transaction();
+///     for i in 0..10 {
+///         let path = ["private".into(), "drafts".into(), format!("{i}.md")];
+///         transaction.write(&path, b"draft".to_vec()).await?;
+///     }
+///     transaction
+///         .basic_mv(&["private".into(), "drafts".into()], &["private".into(), "posts".into()])
+///         .await?;
+///     let cid = transaction.commit().await?;
+///
+///     assert_eq!(root_tree.ls(&["private".into(), "posts".into()]).await?.len(), 10);
+///     assert_eq!(cid, root_tree.store().await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Transaction<'a, B: BlockStore> {
+    staged: RootTree<StagedBlockStore<&'a B>>,
+    target: CommitTarget<'a>,
+    /// Changes made to the staged root tree, held back until they're committed.
+    events: UnboundedReceiver<ChangeEvent>,
+}
+
+/// The parts of a root tree that a transaction replaces when it's committed.
+struct CommitTarget<'a> {
+    forest: &'a mut Arc<HamtForest>,
+    public_root: &'a mut Arc<PublicDirectory>,
+    exchange_root: &'a mut Arc<PublicDirectory>,
+    private_map: &'a mut BTreeMap<Vec<String>, Arc<PrivateDirectory>>,
+    private_bases: &'a mut BTreeMap<Vec<String>, Arc<PrivateDirectory>>,
+    private_files: &'a mut BTreeMap<Vec<String>, Arc<PrivateFile>>,
+    snapshot_files: &'a mut BTreeMap<Vec<String>, (SnapshotAccessKey, Arc<PrivateFile>)>,
+    mount_key: &'a mut Option<SnapshotKey>,
+    wrapped_mount_keys: &'a mut Vec<Vec<u8>>,
+    mount_table: &'a mut Option<Cid>,
+    shares: &'a mut BTreeMap<Vec<String>, BTreeSet<ShareRecord>>,
+    notifier: &'a ChangeNotifier,
+    history: &'a mut Option<RootHistory>,
+}
+
+/// A block store that keeps new blocks in memory, until the ones that are
+/// still needed are flushed into the underlying block store.
+#[derive(Debug)]
+pub struct StagedBlockStore<B: BlockStore> {
+    inner: B,
+    staged: Mutex<HashMap<Cid, Bytes>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<'a, B: BlockStore> Transaction<'a, B> {
+    /// Starts staging changes to given root tree.
+    pub fn new(root_tree: &'a mut RootTree<B>) -> Self {
+        let RootTree {
+            store,
+            forest,
+            public_root,
+            exchange_root,
+            private_map,
+            private_bases,
+            private_files,
+            snapshot_files,
+            mount_key,
+            wrapped_mount_keys,
+            mount_table,
+            shares,
+            notifier,
+            history,
+        } = root_tree;
+
+        let staged = RootTree {
+            store: StagedBlockStore::new(&*store),
+            forest: Arc::clone(forest),
+            public_root: Arc::clone(public_root),
+            exchange_root: Arc::clone(exchange_root),
+            private_map: private_map.clone(),
+            private_bases: private_bases.clone(),
+            private_files: private_files.clone(),
+            snapshot_files: snapshot_files.clone(),
+            mount_key: mount_key.clone(),
+            wrapped_mount_keys: wrapped_mount_keys.clone(),
+            mount_table: *mount_table,
+            shares: shares.clone(),
+            notifier: ChangeNotifier::new(),
+            history: history.clone(),
+        };
+        let events = staged.subscribe();
+
+        Self {
+            staged,
+            target: CommitTarget {
+                forest,
+                public_root,
+                exchange_root,
+                private_map,
+                private_bases,
+                private_files,
+                snapshot_files,
+                mount_key,
+                wrapped_mount_keys,
+                mount_table,
+                shares,
+                notifier,
+                history,
+            },
+            events,
+        }
+    }
+
+    /// Commits the transaction, recording the current time in the root history and
+    /// using fresh entropy for any keys it needs.
+    ///
+    /// See [`Self::commit_with`] for details.
+    pub async fn commit(self) -> Result<Cid> {
+        self.commit_with(Utc::now(), &mut ChaCha12Rng::from_entropy())
+            .await
+    }
+
+    /// Stores the staged root tree, writes all blocks it needs into the block store
+    /// and applies the changes to the root tree the transaction was started on.
+    ///
+    /// Returns the CID of the new root. If this fails, the root tree is left unchanged.
+    /// Subscribers of the root tree are notified of the staged changes only once they're applied.
+    pub async fn commit_with(
+        mut self,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<Cid> {
+        let cid = self.staged.store_with(time, rng).await?;
+        self.staged.store.flush(&cid).await?;
+
+        let RootTree {
+            store: _,
+            forest,
+            public_root,
+            exchange_root,
+            private_map,
+            private_bases,
+            private_files,
+            snapshot_files,
+            mount_key,
+            wrapped_mount_keys,
+            mount_table,
+            shares,
+            notifier: _,
+            history,
+        } = self.staged;
+
+        *self.target.forest = forest;
+        *self.target.public_root = public_root;
+        *self.target.exchange_root = exchange_root;
+        *self.target.private_map = private_map;
+        *self.target.private_bases = private_bases;
+        *self.target.private_files = private_files;
+        *self.target.snapshot_files = snapshot_files;
+        *self.target.mount_key = mount_key;
+        *self.target.wrapped_mount_keys = wrapped_mount_keys;
+        *self.target.mount_table = mount_table;
+        *self.target.shares = shares;
+        *self.target.history = history;
+
+        while let Ok(Some(event)) = self.events.try_next() {
+            self.target.notifier.notify(event);
+        }
+
+        Ok(cid)
+    }
+
+    /// Discards all staged changes.
+    pub fn rollback(self) {}
+}
+
+impl<'a, B: BlockStore> Deref for Transaction<'a, B> {
+    type Target = RootTree<StagedBlockStore<&'a B>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.staged
+    }
+}
+
+impl<B: BlockStore> DerefMut for Transaction<'_, B> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.staged
+    }
+}
+
+impl<B: BlockStore> StagedBlockStore<B> {
+    /// Creates a block store staging new blocks on top of given block store.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            staged: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Writes all staged blocks that are reachable from given root into the underlying
+    /// block store and discards all others.
+    pub async fn flush(&self, root: &Cid) -> Result<()> {
+        let staged = std::mem::take(&mut *self.staged.lock().unwrap());
+
+        let mut reachable = HashMap::new();
+        let mut frontier = vec![*root];
+        while let Some(cid) = frontier.pop() {
+            // Blocks that aren't staged are already stored, including everything they link to.
+            let Some(bytes) = staged.get(&cid) else {
+                continue;
+            };
+            if reachable.insert(cid, bytes).is_some() {
+                continue;
+            }
+
+            if let Ok(codec) = Codec::try_from(cid.codec()) {
+                frontier.extend(parse_links(codec, bytes)?);
+            }
+        }
+
+        for (cid, bytes) in reachable {
+            self.inner.put_block_keyed(cid, bytes.clone()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<B: BlockStore> BlockStore for StagedBlockStore<B> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        let staged = self.staged.lock().unwrap().get(cid).cloned();
+        match staged {
+            Some(bytes) => Ok(bytes),
+            None => self.inner.get_block(cid).await,
+        }
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes> + CondSend,
+    ) -> Result<(), BlockStoreError> {
+        self.staged.lock().unwrap().insert(cid, bytes.into());
+        Ok(())
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        if self.staged.lock().unwrap().contains_key(cid) {
+            return Ok(true);
+        }
+
+        self.inner.has_block(cid).await
+    }
+
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
+        self.inner.create_cid(bytes, codec)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use testresult::TestResult;
+    use wnfs_common::{CODEC_RAW, MemoryBlockStore};
+
+    #[async_std::test]
+    async fn commit_stores_only_reachable_blocks() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let mut root_tree = RootTree::empty_with(MemoryBlockStore::new(), rng, time);
+        root_tree
+            .create_private_root_with(&["private".into()], time, rng)
+            .await?;
+        root_tree.store_with(time, rng).await?;
+
+        let first = root_tree.store.create_cid(b"first", CODEC_RAW)?;
+        let last = root_tree.store.create_cid(b"final", CODEC_RAW)?;
+        let public_file = ["public".into(), "file".into()];
+        let private_file = ["private".into(), "file".into()];
+        let mut transaction = root_tree.transaction();
+        for content in [b"first", b"final"] {
+            transaction
+                .write_with(&public_file, content.to_vec(), time, rng)
+                .await?;
+            transaction
+                .write_with(&private_file, content.to_vec(), time, rng)
+                .await?;
+        }
+        assert!(transaction.store.has_block(&last).await?);
+        assert!(!transaction.store.inner.has_block(&last).await?);
+        let cid = transaction.commit_with(time, rng).await?;
+
+        assert!(!root_tree.store.has_block(&first).await?);
+        assert!(root_tree.store.has_block(&last).await?);
+        assert_eq!(root_tree.read(&private_file).await?, b"final");
+
+        let loaded = RootTree::load(&cid, root_tree.store.clone()).await?;
+        assert_eq!(loaded.read(&public_file).await?, b"final");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn failed_or_rolled_back_transactions_leave_root_tree_unchanged() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc.timestamp_opt(0, 0).unwrap();
+        let mut root_tree = RootTree::empty_with(MemoryBlockStore::new(), rng, time);
+        root_tree
+            .create_private_root_with(&["private".into()], time, rng)
+            .await?;
+        let cid = root_tree.store_with(time, rng).await?;
+
+        let mut transaction = root_tree.transaction();
+        transaction
+            .write_with(&["public".into(), "file".into()], b"hi".to_vec(), time, rng)
+            .await?;
+        transaction
+            .mkdir_with(&["private".into(), "dir".into()], time, rng)
+            .await?;
+        let result = transaction
+            .basic_mv_with(
+                &["public".into(), "missing".into()],
+                &["public".into(), "x".into()],
+                time,
+                rng,
+            )
+            .await;
+        assert!(result.is_err());
+        transaction.rollback();
+
+        assert!(root_tree.ls(&["public".into()]).await?.is_empty());
+        assert!(root_tree.ls(&["private".into()]).await?.is_empty());
+        assert_eq!(root_tree.store_with(time, rng).await?, cid);
+
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
-use crate::error::FsError;
+use crate::error::{FsError, InvalidPathReason};
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use std::fmt::Debug;
-use wnfs_common::utils::error;
+use wnfs_common::{Metadata, utils::error};
 
 pub struct OnceCellDebug<T>(pub Option<T>);
 
@@ -18,10 +19,19 @@ impl<T: Debug> Debug for OnceCellDebug<T> {
 pub(crate) fn split_last(path_segments: &[String]) -> Result<(&[String], &String)> {
     match path_segments.split_last() {
         Some((last, rest)) => Ok((rest, last)),
-        None => error(FsError::InvalidPath),
+        None => error(FsError::InvalidPath(InvalidPathReason::Empty)),
     }
 }
 
+/// Whether a node with given metadata was last modified at or before `time`.
+///
+/// Metadata stores timestamps with second precision, so `time` is compared at that precision too.
+pub(crate) fn modified_at_or_before(metadata: &Metadata, time: DateTime<Utc>) -> bool {
+    metadata
+        .get_modified()
+        .is_some_and(|modified| modified.timestamp() <= time.timestamp())
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
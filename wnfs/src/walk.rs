@@ -0,0 +1,190 @@
+//! Options and shared machinery for recursively walking file trees.
+
+use anyhow::Result;
+use async_stream::try_stream;
+use std::collections::VecDeque;
+use wnfs_common::{
+    Metadata,
+    utils::{Arc, BoxFuture, BoxStream, CondSend, CondSync, boxed_stream},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default number of children that are fetched concurrently when expanding a directory.
+pub const DEFAULT_WALK_CONCURRENCY: usize = 16;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The order in which a walk visits nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalkOrder {
+    /// Visits a directory's whole subtree before moving on to its next sibling.
+    #[default]
+    DepthFirst,
+    /// Visits all nodes of one depth before moving on to the next depth.
+    BreadthFirst,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+type PruneFn = Arc<dyn Fn(&[String], &Metadata) -> bool + Send + Sync>;
+
+#[cfg(target_arch = "wasm32")]
+type PruneFn = Arc<dyn Fn(&[String], &Metadata) -> bool>;
+
+/// Configures a recursive walk over a file tree.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs::walk::{WalkOptions, WalkOrder};
+///
+/// let options = WalkOptions::new()
+///     .order(WalkOrder::BreadthFirst)
+///     .max_depth(2)
+///     .prune(|path, _| path.last().is_some_and(|name| name.starts_with('.')));
+/// ```
+#[derive(Clone)]
+pub struct WalkOptions {
+    order: WalkOrder,
+    max_depth: Option<usize>,
+    concurrency: usize,
+    prune: Option<PruneFn>,
+}
+
+/// A single node yielded from a walk: its path, the node itself and its metadata.
+pub type WalkEntry<N> = (Vec<String>, N, Metadata);
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl WalkOptions {
+    /// Creates options for an unlimited depth-first walk.
+    pub fn new() -> Self {
+        Self {
+            order: WalkOrder::DepthFirst,
+            max_depth: None,
+            concurrency: DEFAULT_WALK_CONCURRENCY,
+            prune: None,
+        }
+    }
+
+    /// Sets the order in which nodes are visited.
+    pub fn order(mut self, order: WalkOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Limits how deep the walk descends.
+    ///
+    /// The direct children of the directory the walk starts at have depth 1,
+    /// so a maximum depth of 1 behaves like a non-recursive listing.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Sets how many children of a directory are fetched (and decrypted) concurrently.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets a callback that decides whether to skip a node's descendants.
+    ///
+    /// The callback is called with the path and metadata of every node within the depth limit.
+    /// If it returns `true` for a directory, the directory itself is still yielded,
+    /// but none of its descendants are.
+    pub fn prune(
+        mut self,
+        prune: impl Fn(&[String], &Metadata) -> bool + CondSend + CondSync + 'static,
+    ) -> Self {
+        self.prune = Some(Arc::new(prune));
+        self
+    }
+
+    fn should_descend(&self, path: &[String], depth: usize, metadata: &Metadata) -> bool {
+        if self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return false;
+        }
+
+        !self
+            .prune
+            .as_ref()
+            .is_some_and(|prune| prune(path, metadata))
+    }
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for WalkOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WalkOptions")
+            .field("order", &self.order)
+            .field("max_depth", &self.max_depth)
+            .field("concurrency", &self.concurrency)
+            .field("prune", &self.prune.as_ref().map(|_| "<callback>"))
+            .finish()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Walks the tree below `root`, yielding paths prefixed with `prefix`.
+///
+/// `children` is called with a node and the configured concurrency and returns the node's
+/// named children together with their metadata, or nothing for files.
+pub(crate) fn walk_tree<'a, N, F>(
+    prefix: Vec<String>,
+    root: N,
+    options: WalkOptions,
+    children: F,
+) -> BoxStream<'a, Result<WalkEntry<N>>>
+where
+    N: Clone + CondSend + 'a,
+    F: Fn(N, usize) -> BoxFuture<'a, Result<Vec<(String, N, Metadata)>>> + CondSend + 'a,
+{
+    boxed_stream(try_stream! {
+        let mut queue = VecDeque::new();
+        for (name, node, metadata) in children(root, options.concurrency).await? {
+            let mut path = prefix.clone();
+            path.push(name);
+            queue.push_back((path, 1, node, metadata));
+        }
+
+        while let Some((path, depth, node, metadata)) = queue.pop_front() {
+            let descend = options.should_descend(&path, depth, &metadata);
+            yield (path.clone(), node.clone(), metadata);
+
+            if !descend {
+                continue;
+            }
+
+            let entries = children(node, options.concurrency).await?;
+            let entries = entries.into_iter().map(|(name, node, metadata)| {
+                let mut child_path = path.clone();
+                child_path.push(name);
+                (child_path, depth + 1, node, metadata)
+            });
+
+            match options.order {
+                WalkOrder::DepthFirst => {
+                    for entry in entries.rev() {
+                        queue.push_front(entry);
+                    }
+                }
+                WalkOrder::BreadthFirst => queue.extend(entries),
+            }
+        }
+    })
+}